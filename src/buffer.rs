@@ -1,8 +1,18 @@
-use crate::escape_sequence::{Color, ESCAPE_SEQUENCE_STYLE_RESET, ESCAPE_SEQUENCE_STYLE_REVERSE};
-use crate::TAB_STOP;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Error, Write};
+use crate::editorconfig::EditorConfig;
+use crate::escape_sequence::{
+    Color, ESCAPE_SEQUENCE_STYLE_DIM, ESCAPE_SEQUENCE_STYLE_NORMAL_INTENSITY,
+    ESCAPE_SEQUENCE_STYLE_NO_REVERSE, ESCAPE_SEQUENCE_STYLE_RESET, ESCAPE_SEQUENCE_STYLE_REVERSE,
+};
+use crate::gap_buffer::GapBuffer;
+use crate::theme::Theme;
+use crate::{BACKGROUND_HIGHLIGHT_BYTES, BACKGROUND_HIGHLIGHT_LINES, LOAD_CHUNK_LINES, TAB_STOP};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::ops::Range;
 use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 enum HighlightType {
@@ -87,14 +97,156 @@ impl FileType {
         }
     }
 
-    pub fn to_str(&self) -> &'static str {
+    pub fn to_str(self) -> &'static str {
         match self {
             FileType::C => "C",
         }
     }
 }
 
-fn is_separator(c: char) -> bool {
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Unknown,
+    Binary,
+}
+
+impl Encoding {
+    fn decode(bytes: &[u8]) -> (String, Encoding) {
+        if let Some(rest) = bytes.strip_prefix(&[0xff, 0xfe]) {
+            let units = rest
+                .chunks(2)
+                .map(|c| u16::from_le_bytes([c[0], *c.get(1).unwrap_or(&0)]));
+            return (Self::decode_utf16(units), Encoding::Utf16Le);
+        }
+        if let Some(rest) = bytes.strip_prefix(&[0xfe, 0xff]) {
+            let units = rest
+                .chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]));
+            return (Self::decode_utf16(units), Encoding::Utf16Be);
+        }
+
+        match std::str::from_utf8(bytes) {
+            Ok(s) => (s.to_string(), Encoding::Utf8),
+            Err(_) => (
+                String::from_utf8_lossy(bytes).into_owned(),
+                Encoding::Unknown,
+            ),
+        }
+    }
+
+    fn is_utf16(bytes: &[u8]) -> bool {
+        bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff])
+    }
+
+    fn decode_utf16<I: Iterator<Item = u16>>(units: I) -> String {
+        char::decode_utf16(units)
+            .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Unknown => "unknown",
+            Encoding::Binary => "binary",
+        }
+    }
+}
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+
+/// The line ending a buffer is saved with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn parse(name: &str) -> Option<LineEnding> {
+        match name.to_ascii_lowercase().as_str() {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        }
+    }
+}
+
+/// What a level of indentation is made of.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Indent {
+    /// The text inserted for one level of indentation.
+    pub fn unit(self) -> String {
+        match self {
+            Indent::Tabs => "\t".to_string(),
+            Indent::Spaces(n) => " ".repeat(n),
+        }
+    }
+
+    /// A short description for the status bar, such as `tabs:8` or `spaces:4`.
+    pub fn to_status(self) -> String {
+        match self {
+            Indent::Tabs => format!("tabs:{}", TAB_STOP),
+            Indent::Spaces(n) => format!("spaces:{}", n),
+        }
+    }
+}
+
+/// Guesses whether `bytes` come from a binary file: they contain a NUL, or more than a tenth
+/// of them are control characters other than common whitespace.
+pub fn is_probably_binary(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return true;
+    }
+    let control = bytes
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    control * 10 > bytes.len()
+}
+
+/// Formats up to 16 `bytes` starting at `offset` as `offset: hex bytes | ascii`, padding
+/// short rows so that the ascii column lines up.
+pub fn hex_row(offset: usize, bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{:08x}: {:<47} | {}", offset, hex.join(" "), ascii)
+}
+
+pub fn is_separator(c: char) -> bool {
     c == ' '
         || c == '\t'
         || c == '\r'
@@ -114,15 +266,147 @@ fn is_separator(c: char) -> bool {
         || c == '>'
         || c == '['
         || c == ']'
+        || c == ';'
+        || c == '~'
+}
+
+/// How `EditorBuffer::get_render` styles a line.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions<'a> {
+    pub theme: &'a Theme,
+    /// Draws tabs and trailing spaces as dim glyphs.
+    pub show_whitespace: bool,
+    /// Render columns `begin..end` drawn as selected.
+    pub selection: Option<(usize, usize)>,
+    /// 1-based render columns whose background is colored, padding short lines out so that
+    /// they show.
+    pub rulers: &'a [usize],
+    /// Render columns drawn as a reversed cell, e.g. for extra cursors.
+    pub markers: &'a [usize],
+}
+
+impl<'a> RenderOptions<'a> {
+    /// Plain rendering in `theme`, with every other option off.
+    pub fn new(theme: &'a Theme) -> RenderOptions<'a> {
+        RenderOptions {
+            theme,
+            show_whitespace: false,
+            selection: None,
+            rulers: &[],
+            markers: &[],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SearchOptions {
+    pub ignore_case: bool,
+    pub whole_word: bool,
+}
+
+impl SearchOptions {
+    /// Returns the byte offsets in `line` where `query` matches under these options. Matches
+    /// don't overlap: each one starts after the last ends.
+    pub fn match_positions(&self, line: &str, query: &str) -> Vec<usize> {
+        let mut end = 0;
+        self.overlapping_match_positions(line, query)
+            .into_iter()
+            .filter(|&j| {
+                let keep = end <= j;
+                if keep {
+                    end = j + query.len();
+                }
+                keep
+            })
+            .collect()
+    }
+
+    /// Like [`SearchOptions::match_positions`], but also returns matches that overlap an
+    /// earlier one, e.g. both `aa` in `aaa`.
+    pub fn overlapping_match_positions(&self, line: &str, query: &str) -> Vec<usize> {
+        let (line, query) = if self.ignore_case {
+            (line.to_ascii_lowercase(), query.to_ascii_lowercase())
+        } else {
+            (line.to_string(), query.to_string())
+        };
+        let is_boundary = |i: usize| match (line[..i].chars().last(), line[i..].chars().next()) {
+            (Some(before), Some(after)) => is_separator(before) || is_separator(after),
+            _ => true,
+        };
+
+        line.char_indices()
+            .map(|(j, _)| j)
+            .filter(|&j| line[j..].starts_with(&query))
+            .filter(|&j| !self.whole_word || (is_boundary(j) && is_boundary(j + query.len())))
+            .collect()
+    }
+
+    /// Describes the enabled options for the search prompt, e.g. `" (ignore case)"`.
+    pub fn label(&self) -> String {
+        let mut enabled = Vec::new();
+        if self.ignore_case {
+            enabled.push("ignore case");
+        }
+        if self.whole_word {
+            enabled.push("whole word");
+        }
+        if enabled.is_empty() {
+            "".to_string()
+        } else {
+            format!(" ({})", enabled.join(", "))
+        }
+    }
+}
+
+/// Byte index of the char at `index` in `s`, or `s.len()` past the end. `ascii` promises
+/// that `s` is ASCII, which makes it a plain clamp instead of a scan.
+fn char_byte_index(s: &str, index: usize, ascii: bool) -> usize {
+    if ascii {
+        index.min(s.len())
+    } else {
+        s.char_indices().nth(index).map_or(s.len(), |(i, _)| i)
+    }
+}
+
+/// Terminal columns taken by `c`: two in the wide and fullwidth East Asian blocks and the
+/// common emoji blocks, one everywhere else.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// The number of spaces a tab at terminal column `column` expands to.
+fn tab_spaces(column: usize) -> usize {
+    TAB_STOP - column % TAB_STOP
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 struct EditorLine {
     raw: String,
     render: String,
+    /// Render column at which each char of `raw` starts, followed by the render width.
+    rx_offsets: Vec<usize>,
     highlight: Vec<Highlight>,
     file_type: Option<FileType>,
     open_comment: bool,
+    /// Whether `raw` is known to be ASCII, so byte, char and render indices coincide
+    /// before any tab. It may stay false after the last non-ASCII char is removed.
+    ascii: bool,
 }
 
 impl EditorLine {
@@ -130,54 +414,130 @@ impl EditorLine {
         let mut el = EditorLine {
             raw: line,
             render: String::new(),
+            rx_offsets: Vec::new(),
             highlight: Vec::new(),
             file_type,
             open_comment: false,
+            ascii: false,
         };
 
-        el.render = el.convert_render(&el.raw);
+        el.update_render();
         el.clear_highlight(false);
         el
     }
 
     fn remove_char(&mut self, index: usize) {
         self.raw.remove(index);
-        self.render = self.convert_render(&self.raw);
+        self.update_render_from(index);
     }
 
     fn insert_char(&mut self, index: usize, c: char) {
         self.raw.insert(index, c);
-        self.render = self.convert_render(&self.raw);
+        self.ascii &= c.is_ascii();
+        self.update_render_from(index);
     }
 
     fn insert_str(&mut self, index: usize, str: &str) {
         self.raw.insert_str(index, str);
+        self.ascii &= str.is_ascii();
+        self.update_render_from(index);
+    }
+
+    /// Re-renders `raw` from byte `index` on, keeping the render of the unchanged part
+    /// before it. A tab's width depends on its column, so everything after is redone.
+    fn update_render_from(&mut self, index: usize) {
+        let cx = if self.ascii {
+            index
+        } else {
+            self.raw[..index].chars().count()
+        };
+        let mut rx = match self.rx_offsets.get(cx) {
+            Some(&rx) => rx,
+            None => return self.update_render(),
+        };
+        self.rx_offsets.truncate(cx);
+        let end = char_byte_index(&self.render, rx, self.ascii);
+        self.render.truncate(end);
+        let mut column = if self.ascii {
+            rx
+        } else {
+            self.render.chars().map(char_width).sum()
+        };
+
+        for c in self.raw[index..].chars() {
+            self.rx_offsets.push(rx);
+            if c == '\t' {
+                let spaces = tab_spaces(column);
+                self.render.push_str(&" ".repeat(spaces));
+                rx += spaces;
+                column += spaces;
+            } else {
+                self.render.push(c);
+                rx += 1;
+                column += char_width(c);
+            }
+        }
+        self.rx_offsets.push(rx);
+    }
+
+    fn update_render(&mut self) {
+        self.ascii = self.raw.is_ascii();
         self.render = self.convert_render(&self.raw);
+        self.rx_offsets.clear();
+        let mut rx = 0;
+        let mut column = 0;
+        for c in self.raw.chars() {
+            self.rx_offsets.push(rx);
+            if c == '\t' {
+                let spaces = tab_spaces(column);
+                rx += spaces;
+                column += spaces;
+            } else {
+                rx += 1;
+                column += char_width(c);
+            }
+        }
+        self.rx_offsets.push(rx);
     }
 
+    /// Expands the tabs in `line` to the next tab stop, counting wide chars as the two
+    /// columns they take on the terminal.
     fn convert_render(&self, line: &str) -> String {
         let mut render = String::new();
-        let mut i = 0;
+        let mut column = 0;
         for c in line.chars() {
             match c {
                 '\t' => {
-                    render.push(' ');
-                    i += 1;
-                    while i % TAB_STOP != 0 {
-                        render.push(' ');
-                        i += 1;
-                    }
+                    let spaces = tab_spaces(column);
+                    render.push_str(&" ".repeat(spaces));
+                    column += spaces;
                 }
                 c => {
                     render.push(c);
+                    column += char_width(c);
                 }
             }
-            i += 1;
         }
 
         render
     }
 
+    fn whitespace_glyphs(&self) -> Vec<Option<char>> {
+        let trailing_start = self.raw.trim_end_matches(' ').len();
+        let mut glyphs = Vec::new();
+        for ((j, c), rx) in self.raw.char_indices().zip(&self.rx_offsets[1..]) {
+            match c {
+                '\t' => {
+                    glyphs.push(Some('›'));
+                    glyphs.resize(*rx, None);
+                }
+                ' ' if j >= trailing_start => glyphs.push(Some('·')),
+                _ => glyphs.push(None),
+            }
+        }
+        glyphs
+    }
+
     pub fn clear_highlight(&mut self, open_comment: bool) -> bool {
         if self.render.len() != self.highlight.len() {
             self.highlight.resize(self.render.len(), Highlight::Normal);
@@ -189,6 +549,8 @@ impl EditorLine {
         let mut in_string = false;
         let mut in_comment = open_comment;
         let mut quote = '\0';
+        let mut number_start = 0;
+        let mut in_hex = false;
         let mut i = 0;
 
         let keyword_func = |render: &String,
@@ -201,24 +563,12 @@ impl EditorLine {
             for keyword in keywords {
                 let s: String = render.chars().skip(*i).take(keyword.len()).collect();
                 if keyword == s {
-                    if *i + keyword.len() == render.len() {
-                        for j in *i..*i + keyword.len() {
-                            highlight[j] = keyword_highlight;
-                        }
-                        *i += keyword.len();
+                    let end = *i + keyword.len();
+                    if render.chars().nth(end).is_none_or(is_separator) {
+                        highlight[*i..end].fill(keyword_highlight);
+                        *i = end;
                         *prev_highlight = keyword_highlight;
                         return true;
-                    } else if *i + keyword.len() + 1 < render.len() {
-                        if let Some(end) = render.chars().nth(*i + keyword.len() + 1) {
-                            if is_separator(end) {
-                                for j in *i..*i + keyword.len() {
-                                    highlight[j] = keyword_highlight;
-                                }
-                                *i += keyword.len();
-                                *prev_highlight = keyword_highlight;
-                                return true;
-                            }
-                        }
                     }
                 }
             }
@@ -230,47 +580,67 @@ impl EditorLine {
                 self.highlight[i] = Highlight::Normal;
                 if let Some(file_type) = self.file_type {
                     if file_type.is_highlight(HighlightType::Number) {
-                        if c.is_ascii_digit()
-                            && (prev_separator || prev_highlight == Highlight::Number)
-                        {
-                            self.highlight[i] = Highlight::Number;
-                            prev_separator = false;
-                        } else if c == '.' && prev_highlight == Highlight::Number {
+                        let in_number = prev_highlight == Highlight::Number;
+                        let is_number = if c.is_ascii_digit() {
+                            prev_separator || in_number
+                        } else if !in_number {
+                            false
+                        } else if in_hex {
+                            c.is_ascii_hexdigit() || matches!(c, 'u' | 'U' | 'l' | 'L')
+                        } else {
+                            match c {
+                                '.' | 'f' | 'F' | 'u' | 'U' | 'l' | 'L' => true,
+                                'x' | 'X' => prev_char == '0' && number_start + 1 == i,
+                                'e' | 'E' => prev_char.is_ascii_digit() || prev_char == '.',
+                                '+' | '-' => prev_char == 'e' || prev_char == 'E',
+                                _ => false,
+                            }
+                        };
+                        if is_number {
+                            if !in_number {
+                                number_start = i;
+                                in_hex = false;
+                            }
+                            if c == 'x' || c == 'X' {
+                                in_hex = true;
+                            }
                             self.highlight[i] = Highlight::Number;
                             prev_separator = false;
                         }
                     }
                     if file_type.is_highlight(HighlightType::String) {
                         if in_string {
+                            if c == '\\' && i + 1 < self.render.len() {
+                                self.highlight[i..i + 2].fill(Highlight::Escape);
+                                prev_separator = true;
+                                i += 2;
+                                continue 'char_loop;
+                            }
                             self.highlight[i] = Highlight::String;
                             if c == quote && prev_char != '\\' {
                                 in_string = false;
                             }
                             prev_separator = true;
-                        } else {
-                            if c == '\'' || c == '"' {
-                                in_string = true;
-                                quote = c;
-                                self.highlight[i] = Highlight::String;
-                            }
+                        } else if c == '\'' || c == '"' {
+                            in_string = true;
+                            quote = c;
+                            self.highlight[i] = Highlight::String;
                         }
                     }
-                    if file_type.is_highlight(HighlightType::Comment) {
-                        if !in_string && !in_comment {
-                            if let Some(comment_start) = file_type.singleline_comment_start() {
-                                let s: String = self
-                                    .render
-                                    .chars()
-                                    .skip(i)
-                                    .take(comment_start.len())
-                                    .collect();
-                                if comment_start == s {
-                                    for j in i..self.render.len() {
-                                        self.highlight[j] = Highlight::Comment;
-                                    }
-                                    self.open_comment = false;
-                                    return false;
+                    if file_type.is_highlight(HighlightType::Comment) && !in_string && !in_comment {
+                        if let Some(comment_start) = file_type.singleline_comment_start() {
+                            let s: String = self
+                                .render
+                                .chars()
+                                .skip(i)
+                                .take(comment_start.len())
+                                .collect();
+                            if comment_start == s {
+                                for j in i..self.render.len() {
+                                    self.highlight[j] = Highlight::Comment;
                                 }
+                                self.open_comment = false;
+                                return false;
                             }
                         }
                     }
@@ -315,34 +685,34 @@ impl EditorLine {
                         }
                     }
 
-                    if file_type.is_highlight(HighlightType::Keyword1) {
-                        if prev_separator && !in_comment {
-                            if keyword_func(
-                                &self.render,
-                                &mut self.highlight,
-                                file_type.keyword1(),
-                                &mut i,
-                                &mut prev_highlight,
-                                Highlight::Keyword1,
-                            ) {
-                                continue 'char_loop;
-                            }
-                        }
+                    if file_type.is_highlight(HighlightType::Keyword1)
+                        && prev_separator
+                        && !in_comment
+                        && keyword_func(
+                            &self.render,
+                            &mut self.highlight,
+                            file_type.keyword1(),
+                            &mut i,
+                            &mut prev_highlight,
+                            Highlight::Keyword1,
+                        )
+                    {
+                        continue 'char_loop;
                     }
 
-                    if file_type.is_highlight(HighlightType::Keyword2) {
-                        if prev_separator && !in_comment {
-                            if keyword_func(
-                                &self.render,
-                                &mut self.highlight,
-                                file_type.keyword2(),
-                                &mut i,
-                                &mut prev_highlight,
-                                Highlight::Keyword2,
-                            ) {
-                                continue 'char_loop;
-                            }
-                        }
+                    if file_type.is_highlight(HighlightType::Keyword2)
+                        && prev_separator
+                        && !in_comment
+                        && keyword_func(
+                            &self.render,
+                            &mut self.highlight,
+                            file_type.keyword2(),
+                            &mut i,
+                            &mut prev_highlight,
+                            Highlight::Keyword2,
+                        )
+                    {
+                        continue 'char_loop;
                     }
                 }
                 prev_separator = is_separator(c);
@@ -363,48 +733,126 @@ impl EditorLine {
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum Highlight {
     Normal,
     Number,
     Match,
     String,
+    Escape,
     Comment,
     MultilineComment,
     Keyword1,
     Keyword2,
+    Selection,
+    Ruler,
+    /// The marker on rows past the end of the buffer.
+    EndOfBuffer,
+    /// The cursor line's number in the gutter, left plain when `Color::Default`.
+    CurrentLineNumber,
 }
 
-impl Highlight {
-    fn color(&self) -> Color {
-        match self {
-            Highlight::Normal => Color::White,
-            Highlight::Number => Color::Red,
-            Highlight::Match => Color::Blue,
-            Highlight::String => Color::Magenta,
-            Highlight::Comment => Color::Cyan,
-            Highlight::MultilineComment => Color::Cyan,
-            Highlight::Keyword1 => Color::Yellow,
-            Highlight::Keyword2 => Color::Green,
+/// A run of consecutive lines to re-highlight, starting at `begin` with `open_comment`
+/// carried over from the line before.
+#[derive(Debug)]
+struct HighlightJob {
+    /// The buffer's `generation` when the lines were copied. Results from an older one
+    /// may belong to other lines and are dropped.
+    generation: u64,
+    begin: usize,
+    open_comment: bool,
+    lines: Vec<EditorLine>,
+}
+
+/// Re-highlights copies of lines on a background thread and hands them back in order.
+#[derive(Debug)]
+struct HighlightWorker {
+    jobs: Sender<HighlightJob>,
+    results: Receiver<HighlightJob>,
+    /// The first line still waiting to be re-highlighted.
+    pending: Option<usize>,
+    /// Whether a job is out. Only one is at a time, and it copies at most
+    /// `BACKGROUND_HIGHLIGHT_LINES` lines, so edits don't each copy the rest of the file.
+    in_flight: bool,
+}
+
+impl HighlightWorker {
+    fn new() -> HighlightWorker {
+        let (jobs, job_receiver) = channel::<HighlightJob>();
+        let (result_sender, results) = channel();
+        thread::spawn(move || {
+            for mut job in job_receiver {
+                let mut open_comment = job.open_comment;
+                for line in &mut job.lines {
+                    open_comment = line.clear_highlight(open_comment);
+                }
+                if result_sender.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+        HighlightWorker {
+            jobs,
+            results,
+            pending: None,
+            in_flight: false,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct EditorBuffer {
-    lines: Vec<EditorLine>,
+    lines: GapBuffer<EditorLine>,
     filepath: Option<String>,
     dirty: bool,
     file_type: Option<FileType>,
+    trailing_newline: bool,
+    line_ending: LineEnding,
+    mixed_line_endings: bool,
+    indent: Indent,
+    /// The `.editorconfig` settings for the file, applied on save.
+    editorconfig: EditorConfig,
+    encoding: Encoding,
+    /// Whether the file started with a UTF-8 byte order mark, which is written back on save.
+    bom: bool,
+    read_only: bool,
+    pending: Option<BufReader<File>>,
+    backup_on_save: bool,
+    backed_up_path: Option<String>,
+    refuse_binary: bool,
+    /// Lines edited since the last `take_dirty_lines`.
+    dirty_lines: Option<Range<usize>>,
+    highlighter: Option<HighlightWorker>,
+    /// Set inside `with_batched_highlight`: the first line whose highlight is stale and the
+    /// number of lines at the end that haven't been touched, once an edit has happened.
+    highlight_batch: Option<Option<(usize, usize)>>,
+    /// Counts edits, so that background highlights computed before one can be told apart.
+    generation: u64,
 }
 
 impl EditorBuffer {
     pub fn new() -> EditorBuffer {
         EditorBuffer {
-            lines: Vec::new(),
+            lines: GapBuffer::new(),
             filepath: None,
             dirty: false,
             file_type: None,
+            trailing_newline: true,
+            line_ending: LineEnding::Lf,
+            mixed_line_endings: false,
+            indent: Indent::Tabs,
+            editorconfig: EditorConfig::default(),
+            encoding: Encoding::Utf8,
+            bom: false,
+            read_only: false,
+            pending: None,
+            backup_on_save: false,
+            backed_up_path: None,
+            refuse_binary: false,
+            dirty_lines: None,
+            highlighter: None,
+            highlight_batch: None,
+            generation: 0,
         }
     }
 
@@ -416,6 +864,11 @@ impl EditorBuffer {
         self.lines.len()
     }
 
+    /// The number of digits in the line count, at least one for an empty buffer.
+    pub fn line_count_digits(&self) -> usize {
+        self.len().max(1).ilog10() as usize + 1
+    }
+
     pub fn is_empty(&self) -> bool {
         self.lines.is_empty()
     }
@@ -424,14 +877,213 @@ impl EditorBuffer {
         self.lines.get(num).map(|el| el.raw.clone())
     }
 
-    pub fn clear_highlight(&mut self, cy: usize) {
-        let mut open_comment = if cy == 0 {
-            false
+    /// The leading spaces and tabs of line `cy`, empty past the end of the buffer.
+    pub fn leading_whitespace(&self, cy: usize) -> &str {
+        self.lines.get(cy).map_or("", |el| {
+            let rest = el.raw.trim_start_matches([' ', '\t']);
+            &el.raw[..el.raw.len() - rest.len()]
+        })
+    }
+
+    /// The loaded lines whose leading whitespace has both tabs and spaces.
+    pub fn mixed_indent_lines(&self) -> Vec<usize> {
+        (0..self.lines.len())
+            .filter(|&cy| {
+                let indent = self.leading_whitespace(cy);
+                indent.contains('\t') && indent.contains(' ')
+            })
+            .collect()
+    }
+
+    /// Whether line `cy` has nothing but spaces and tabs. Lines past the end count as empty.
+    pub fn is_line_empty(&self, cy: usize) -> bool {
+        self.leading_whitespace(cy).len() == self.lines.get(cy).map_or(0, |el| el.raw.len())
+    }
+
+    /// The render width of line `cy`'s leading whitespace, with tabs reaching their stop.
+    pub fn indent_of(&self, cy: usize) -> usize {
+        self.cx_to_rx(self.leading_whitespace(cy).len(), cy)
+    }
+
+    /// Returns every match of `query` in the loaded lines as `(cx, cy)`, top to bottom and
+    /// left to right. Matches on a line don't overlap: each one starts after the last ends.
+    pub fn find_all(&self, query: &str, options: SearchOptions) -> Vec<(usize, usize)> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(cy, el)| {
+                options
+                    .match_positions(&el.raw, query)
+                    .into_iter()
+                    .map(move |cx| (cx, cy))
+            })
+            .collect()
+    }
+
+    /// Re-highlights from line `cy` until a line's `open_comment` state stops changing,
+    /// and returns the end (exclusive) of the re-highlighted range.
+    pub fn clear_highlight(&mut self, cy: usize) -> usize {
+        if let Some(batch) = &mut self.highlight_batch {
+            let untouched = self.lines.len().saturating_sub(cy + 1);
+            *batch = Some(match *batch {
+                Some((begin, tail)) => (begin.min(cy), tail.min(untouched)),
+                None => (cy, untouched),
+            });
+            return cy + 1;
+        }
+        self.rehighlight(cy, cy + 1)
+    }
+
+    /// Re-highlights lines `begin..end`, then carries on until a line's `open_comment`
+    /// comes out unchanged. Returns the end of the lines that were re-highlighted.
+    fn rehighlight(&mut self, begin: usize, end: usize) -> usize {
+        let mut open_comment = match begin.checked_sub(1).and_then(|i| self.lines.get(i)) {
+            Some(prev) => prev.open_comment,
+            None => false,
+        };
+
+        if self.highlighter.is_some() {
+            let end = end.min(self.lines.len());
+            let mut changed = false;
+            for i in begin..end {
+                let prev_open_comment = self.lines[i].open_comment;
+                open_comment = self.lines[i].clear_highlight(open_comment);
+                changed = open_comment != prev_open_comment;
+            }
+            if changed {
+                self.queue_highlight(end);
+            }
+            return end;
+        }
+
+        for i in begin..self.lines.len() {
+            let prev_open_comment = self.lines[i].open_comment;
+            open_comment = self.lines[i].clear_highlight(open_comment);
+            if i >= end && open_comment == prev_open_comment {
+                return i + 1;
+            }
+        }
+        self.lines.len()
+    }
+
+    /// Runs `f` with re-highlighting suspended, then re-highlights everything it touched in
+    /// a single pass. Multi-line edits use this so that each line doesn't rescan the tail.
+    pub fn with_batched_highlight<R>(&mut self, f: impl FnOnce(&mut EditorBuffer) -> R) -> R {
+        if self.highlight_batch.is_some() {
+            return f(self);
+        }
+        self.highlight_batch = Some(None);
+        let result = f(self);
+        if let Some(Some((begin, tail))) = self.highlight_batch.take() {
+            let end = self.lines.len().saturating_sub(tail);
+            self.rehighlight(begin, end.max(begin + 1));
+        }
+        result
+    }
+
+    /// Moves re-highlighting of the lines after an edit to a background thread, so that
+    /// opening a comment in a huge file doesn't stall. Until the results are applied by
+    /// `apply_background_highlights`, those lines keep their old colors.
+    pub fn set_background_highlight(&mut self, enabled: bool) {
+        self.highlighter = if enabled {
+            Some(HighlightWorker::new())
         } else {
-            self.lines[cy - 1].open_comment
+            None
+        };
+    }
+
+    /// Marks the lines from `begin` on for re-highlighting in the background.
+    fn queue_highlight(&mut self, begin: usize) {
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.pending = Some(highlighter.pending.map_or(begin, |p| p.min(begin)));
+        }
+        self.send_highlight_job();
+    }
+
+    /// Sends the next run of pending lines to the worker, unless a job is already out.
+    fn send_highlight_job(&mut self) {
+        let len = self.lines.len();
+        let begin = match &mut self.highlighter {
+            Some(highlighter) if !highlighter.in_flight => match highlighter.pending {
+                Some(begin) if begin < len => begin,
+                _ => {
+                    highlighter.pending = None;
+                    return;
+                }
+            },
+            _ => return,
+        };
+        let open_comment = match begin.checked_sub(1).and_then(|i| self.lines.get(i)) {
+            Some(prev) => prev.open_comment,
+            None => false,
+        };
+        let end = (begin + BACKGROUND_HIGHLIGHT_LINES).min(len);
+        let job = HighlightJob {
+            generation: self.generation,
+            begin,
+            open_comment,
+            lines: (begin..end).map(|i| self.lines[i].clone()).collect(),
+        };
+        if let Some(highlighter) = &mut self.highlighter {
+            if highlighter.jobs.send(job).is_ok() {
+                highlighter.in_flight = true;
+                return;
+            }
+        }
+        self.highlighter = None;
+        self.highlight_range(begin, len);
+    }
+
+    /// Takes the highlights finished by the background thread, and returns whether any
+    /// line changed. A job that raced with an edit is dropped and its lines sent again;
+    /// one that changed its last line's `open_comment` is followed by the next run.
+    pub fn apply_background_highlights(&mut self) -> bool {
+        let jobs: Vec<HighlightJob> = match &self.highlighter {
+            Some(highlighter) => highlighter.results.try_iter().collect(),
+            None => return false,
         };
 
-        for i in cy..self.lines.len() {
+        let mut applied = false;
+        for job in jobs {
+            let stale = job.generation != self.generation;
+            let end = job.begin + job.lines.len();
+            let mut carried = false;
+            if !stale {
+                for (i, line) in (job.begin..).zip(job.lines) {
+                    if let Some(current) = self.lines.get_mut(i) {
+                        carried = current.open_comment != line.open_comment;
+                        if carried || current.highlight != line.highlight {
+                            current.highlight = line.highlight;
+                            current.open_comment = line.open_comment;
+                            applied = true;
+                        }
+                    }
+                }
+            }
+            if let Some(highlighter) = &mut self.highlighter {
+                highlighter.in_flight = false;
+                if !stale {
+                    highlighter.pending = carried.then_some(end);
+                }
+            }
+        }
+        self.send_highlight_job();
+        applied
+    }
+
+    fn highlight_all(&mut self) {
+        self.highlight_range(0, self.lines.len());
+    }
+
+    fn highlight_range(&mut self, begin: usize, end: usize) {
+        let mut open_comment = match begin.checked_sub(1).and_then(|i| self.lines.get(i)) {
+            Some(prev) => prev.open_comment,
+            None => false,
+        };
+        for i in begin..end {
             open_comment = self.lines[i].clear_highlight(open_comment);
         }
     }
@@ -442,17 +1094,50 @@ impl EditorBuffer {
         self.lines[cy].highlight(begin, end, highlight);
     }
 
-    pub fn get_render(&self, num: usize, offset: usize, width: usize) -> Option<String> {
+    /// Renders columns `offset..offset + width` of line `num`, styled as `options` asks.
+    pub fn get_render(
+        &self,
+        num: usize,
+        offset: usize,
+        width: usize,
+        options: &RenderOptions,
+    ) -> Option<String> {
+        let RenderOptions {
+            theme,
+            show_whitespace,
+            selection,
+            rulers,
+            markers,
+        } = *options;
         self.lines.get(num).map(|el| {
+            let foreground = |color: Color| {
+                if theme.color_enabled() {
+                    color.foreground_escape_sequence()
+                } else {
+                    String::new()
+                }
+            };
+            let rulers = if theme.color_enabled() { rulers } else { &[] };
+            let ruler_on = theme.color(Highlight::Ruler).background_escape_sequence();
+            let ruler_off = Color::Default.background_escape_sequence();
+            let is_ruler = |i: usize| rulers.contains(&(i + 1));
             let mut output = String::new();
             let mut current_color = Highlight::Normal;
-
-            el.render
+            let glyphs = if show_whitespace {
+                el.whitespace_glyphs()
+            } else {
+                Vec::new()
+            };
+
+            let start = char_byte_index(&el.render, offset, el.ascii);
+            el.render[start..]
                 .chars()
-                .enumerate()
-                .skip(offset)
+                .zip(offset..)
                 .take(width)
-                .for_each(|(i, c)| {
+                .for_each(|(c, i)| {
+                    if is_ruler(i) {
+                        output.push_str(&ruler_on);
+                    }
                     if c.is_ascii_control() {
                         output.push_str(ESCAPE_SEQUENCE_STYLE_REVERSE);
                         match c {
@@ -461,27 +1146,88 @@ impl EditorBuffer {
                             _ => output.push('?'),
                         }
                         output.push_str(ESCAPE_SEQUENCE_STYLE_RESET);
-                        output.push_str(current_color.color().foreground_escape_sequence());
+                        if current_color == Highlight::Selection {
+                            output.push_str(ESCAPE_SEQUENCE_STYLE_REVERSE);
+                        }
+                        output.push_str(&foreground(theme.color(current_color)));
                     } else {
-                        match el.highlight[i] {
-                            Highlight::Normal => {
-                                if current_color != Highlight::Normal {
-                                    output.push_str(Color::Default.foreground_escape_sequence());
-                                    current_color = Highlight::Normal;
-                                }
-                                output.push(c);
-                            }
-                            hi => {
-                                if current_color != hi {
-                                    output.push_str(hi.color().foreground_escape_sequence());
-                                    current_color = hi;
-                                }
-                                output.push(c);
+                        let hi = match selection {
+                            Some((begin, end)) if begin <= i && i < end => Highlight::Selection,
+                            _ => el.highlight[i],
+                        };
+                        if current_color != hi {
+                            if hi == Highlight::Selection {
+                                output.push_str(ESCAPE_SEQUENCE_STYLE_REVERSE);
+                            } else if current_color == Highlight::Selection {
+                                output.push_str(ESCAPE_SEQUENCE_STYLE_NO_REVERSE);
                             }
+                            output.push_str(&foreground(theme.color(hi)));
+                            current_color = hi;
+                        }
+                        // A marker flips reverse video for its cell, so it shows in a
+                        // selection too, and leaves the colors around it alone.
+                        let (mark_on, mark_off) = if hi == Highlight::Selection {
+                            (
+                                ESCAPE_SEQUENCE_STYLE_NO_REVERSE,
+                                ESCAPE_SEQUENCE_STYLE_REVERSE,
+                            )
+                        } else {
+                            (
+                                ESCAPE_SEQUENCE_STYLE_REVERSE,
+                                ESCAPE_SEQUENCE_STYLE_NO_REVERSE,
+                            )
+                        };
+                        let marked = markers.contains(&i);
+                        if marked {
+                            output.push_str(mark_on);
+                        }
+                        if let Some(Some(glyph)) = glyphs.get(i) {
+                            output.push_str(ESCAPE_SEQUENCE_STYLE_DIM);
+                            output.push(*glyph);
+                            output.push_str(ESCAPE_SEQUENCE_STYLE_NORMAL_INTENSITY);
+                        } else {
+                            output.push(c);
+                        }
+                        if marked {
+                            output.push_str(mark_off);
                         }
                     }
+                    if is_ruler(i) {
+                        output.push_str(&ruler_off);
+                    }
                 });
-            output.push_str(Color::Default.foreground_escape_sequence());
+            if current_color == Highlight::Selection {
+                output.push_str(ESCAPE_SEQUENCE_STYLE_NO_REVERSE);
+            }
+            output.push_str(&foreground(Color::Default));
+
+            let mut column = el.render.chars().count().max(offset);
+            let mut padded: Vec<usize> = rulers
+                .iter()
+                .filter_map(|ruler| ruler.checked_sub(1))
+                .chain(markers.iter().copied())
+                .filter(|&i| column <= i && i < offset + width)
+                .collect();
+            padded.sort_unstable();
+            padded.dedup();
+            for i in padded {
+                output.push_str(&" ".repeat(i - column));
+                let marked = markers.contains(&i);
+                if is_ruler(i) {
+                    output.push_str(&ruler_on);
+                }
+                if marked {
+                    output.push_str(ESCAPE_SEQUENCE_STYLE_REVERSE);
+                }
+                output.push(' ');
+                if marked {
+                    output.push_str(ESCAPE_SEQUENCE_STYLE_NO_REVERSE);
+                }
+                if is_ruler(i) {
+                    output.push_str(&ruler_off);
+                }
+                column = i + 1;
+            }
             output
         })
     }
@@ -494,45 +1240,287 @@ impl EditorBuffer {
         self.dirty
     }
 
-    pub fn load_file(&mut self, path: String) -> Result<(), Error> {
-        let mut lines: Vec<EditorLine> = Vec::new();
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
 
-        let file = File::open(&path)?;
-        let file_reader = BufReader::new(file);
-        self.file_type = FileType::select_file_type(&path);
-        for ret in file_reader.lines() {
-            let el = EditorLine::new(ret?, self.file_type);
-            lines.push(el);
-        }
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
 
-        self.lines = lines;
-        self.filepath = Some(path.clone());
-        self.dirty = false;
-        self.clear_highlight(0);
+    pub fn backup_on_save(&self) -> bool {
+        self.backup_on_save
+    }
 
-        Ok(())
+    pub fn set_backup_on_save(&mut self, backup_on_save: bool) {
+        self.backup_on_save = backup_on_save;
     }
 
-    pub fn save_file(&mut self, path: String) -> Result<u64, Error> {
-        let mut file = File::create(&path)?;
-        file.write_all(
-            self.lines
-                .iter()
-                .map(|el| el.raw.clone())
-                .collect::<Vec<String>>()
-                .join("\n")
-                .as_bytes(),
-        )?;
-        file.flush()?;
-        self.filepath = Some(path.clone());
-        self.file_type = FileType::select_file_type(&path);
-        for line in &mut self.lines {
-            line.file_type = self.file_type;
+    pub fn refuse_binary(&self) -> bool {
+        self.refuse_binary
+    }
+
+    /// Makes `load_file` fail on files that look binary instead of opening them read-only.
+    pub fn set_refuse_binary(&mut self, refuse_binary: bool) {
+        self.refuse_binary = refuse_binary;
+    }
+
+    /// Replaces the contents with the file at `path`, reading the rest lazily after the first
+    /// chunk. The buffer is left unchanged if opening or reading the first chunk fails.
+    pub fn load_file(&mut self, path: String) -> Result<(), Error> {
+        let editorconfig = EditorConfig::load_for(Path::new(&path));
+        let mut loaded = EditorBuffer {
+            file_type: FileType::select_file_type(&path),
+            backup_on_save: self.backup_on_save,
+            backed_up_path: self.backed_up_path.clone(),
+            refuse_binary: self.refuse_binary,
+            indent: editorconfig.indent().unwrap_or(Indent::Tabs),
+            editorconfig,
+            ..EditorBuffer::new()
+        };
+
+        let file = File::open(&path)?;
+        if file.metadata()?.len() > BACKGROUND_HIGHLIGHT_BYTES {
+            loaded.set_background_highlight(true);
+        }
+        let mut reader = BufReader::new(file);
+        if Encoding::is_utf16(reader.fill_buf()?) {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let (content, encoding) = Encoding::decode(&bytes);
+            let lines: Vec<EditorLine> = content
+                .lines()
+                .map(|line| EditorLine::new(line.to_string(), loaded.file_type))
+                .collect();
+            loaded.lines = GapBuffer::from(lines);
+            loaded.trailing_newline = content.ends_with('\n');
+            let crlf = |line: &str| line.ends_with("\r\n");
+            let mut terminated = content
+                .split_inclusive('\n')
+                .filter(|line| line.ends_with('\n'));
+            if let Some(first) = terminated.next().map(crlf) {
+                if first {
+                    loaded.line_ending = LineEnding::Crlf;
+                }
+                loaded.mixed_line_endings = terminated.any(|line| crlf(line) != first);
+            }
+            loaded.encoding = encoding;
+        } else {
+            if reader.fill_buf()?.starts_with(UTF8_BOM) {
+                reader.consume(UTF8_BOM.len());
+                loaded.bom = true;
+            }
+            if is_probably_binary(reader.fill_buf()?) {
+                if loaded.refuse_binary {
+                    return Err(Error::new(ErrorKind::InvalidData, "binary file"));
+                }
+                loaded.encoding = Encoding::Binary;
+            }
+            loaded.trailing_newline = false;
+            loaded.pending = Some(reader);
+        }
+
+        loaded.read_only = loaded.encoding != Encoding::Utf8;
+        loaded.filepath = Some(path);
+        loaded.highlight_all();
+        loaded.load_more(LOAD_CHUNK_LINES)?;
+
+        *self = loaded;
+        Ok(())
+    }
+
+    pub fn is_fully_loaded(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Reads up to `max_lines` more lines of a file that is still being loaded,
+    /// and returns the number of lines read.
+    pub fn load_more(&mut self, max_lines: usize) -> Result<usize, Error> {
+        let reader = match self.pending.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(0),
+        };
+
+        let begin = self.lines.len();
+        let mut bytes = Vec::new();
+        for _ in 0..max_lines {
+            bytes.clear();
+            if reader.read_until(b'\n', &mut bytes)? == 0 {
+                self.pending = None;
+                break;
+            }
+            self.trailing_newline = bytes.ends_with(b"\n");
+            if self.trailing_newline {
+                bytes.pop();
+                let crlf = bytes.ends_with(b"\r");
+                if crlf {
+                    bytes.pop();
+                }
+                if self.lines.is_empty() {
+                    if crlf {
+                        self.line_ending = LineEnding::Crlf;
+                    }
+                } else if crlf != (self.line_ending == LineEnding::Crlf) {
+                    self.mixed_line_endings = true;
+                }
+            }
+            let line = match String::from_utf8(bytes.clone()) {
+                Ok(line) => line,
+                Err(_) => {
+                    if self.encoding == Encoding::Utf8 {
+                        self.encoding = Encoding::Unknown;
+                    }
+                    self.read_only = true;
+                    String::from_utf8_lossy(&bytes).into_owned()
+                }
+            };
+            self.lines
+                .insert(self.lines.len(), EditorLine::new(line, self.file_type));
+        }
+        self.highlight_range(begin, self.lines.len());
+
+        Ok(self.lines.len() - begin)
+    }
+
+    pub fn ensure_loaded(&mut self, lines: usize) -> Result<(), Error> {
+        while self.lines.len() < lines && !self.is_fully_loaded() {
+            self.load_more(lines - self.lines.len())?;
+        }
+        Ok(())
+    }
+
+    pub fn load_all(&mut self) -> Result<(), Error> {
+        while !self.is_fully_loaded() {
+            self.load_more(LOAD_CHUNK_LINES)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_file(&mut self, path: String) -> Result<u64, Error> {
+        self.load_all()?;
+        if self.read_only {
+            return Err(Error::other(format!(
+                "{} buffer is read-only",
+                self.encoding.to_str()
+            )));
+        }
+        if self.backup_on_save
+            && self.backed_up_path.as_ref() != Some(&path)
+            && Path::new(&path).is_file()
+        {
+            fs::copy(&path, format!("{}~", path))?;
+            self.backed_up_path = Some(path.clone());
+        }
+        self.apply_editorconfig();
+        let size = write_atomically(&path, &self.file_bytes())?;
+        let _ = fs::remove_file(swap_path(&path));
+        self.filepath = Some(path.clone());
+        self.file_type = FileType::select_file_type(&path);
+        for line in &mut self.lines {
+            line.file_type = self.file_type;
         }
         self.dirty = false;
-        self.clear_highlight(0);
+        self.highlight_all();
 
-        Ok(file.metadata()?.size())
+        Ok(size)
+    }
+
+    /// Applies the `.editorconfig` properties that only matter for the saved file: trailing
+    /// whitespace, the final newline and the line ending.
+    fn apply_editorconfig(&mut self) {
+        if self.editorconfig.trim_trailing_whitespace == Some(true) {
+            for line in &mut self.lines {
+                let trimmed = line.raw.trim_end_matches([' ', '\t']).len();
+                if trimmed < line.raw.len() {
+                    line.raw.truncate(trimmed);
+                    line.update_render();
+                }
+            }
+        }
+        if let Some(insert_final_newline) = self.editorconfig.insert_final_newline {
+            self.trailing_newline = insert_final_newline;
+        }
+        if let Some(end_of_line) = self.editorconfig.end_of_line {
+            self.line_ending = end_of_line;
+            self.mixed_line_endings = false;
+        }
+    }
+
+    pub fn get_text(&self) -> String {
+        let mut text = self
+            .lines
+            .iter()
+            .map(|el| el.raw.clone())
+            .collect::<Vec<String>>()
+            .join(self.line_ending.as_str());
+        if self.trailing_newline && !self.lines.is_empty() {
+            text.push_str(self.line_ending.as_str());
+        }
+        text
+    }
+
+    /// The bytes to save: the text, after the byte order mark if the file had one.
+    fn file_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if self.bom {
+            bytes.extend_from_slice(UTF8_BOM);
+        }
+        bytes.extend_from_slice(self.get_text().as_bytes());
+        bytes
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    pub fn set_bom(&mut self, bom: bool) {
+        if self.bom != bom {
+            self.bom = bom;
+            self.dirty = true;
+        }
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Whether the loaded file had lines not ending in `line_ending`.
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    /// The indentation inserted by `Screen::indent`, from `.editorconfig` or tabs.
+    pub fn indent(&self) -> Indent {
+        self.indent
+    }
+
+    pub fn set_indent(&mut self, indent: Indent) {
+        self.indent = indent;
+    }
+
+    /// Sets the line ending used from the next save on, detected from the first line on load.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending || self.mixed_line_endings {
+            self.line_ending = line_ending;
+            self.mixed_line_endings = false;
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the contents to `path` without changing the buffer's file path or dirty flag.
+    pub fn save_copy(&mut self, path: &str) -> Result<u64, Error> {
+        self.load_all()?;
+        write_atomically(path, &self.file_bytes())
+    }
+
+    /// Appends the contents to `path`, creating it if needed. Unlike `save_file`, the buffer
+    /// keeps its file path and dirty flag.
+    pub fn append_to_file(&mut self, path: &str) -> Result<u64, Error> {
+        self.load_all()?;
+        let text = self.get_text();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(text.as_bytes())?;
+        Ok(text.len() as u64)
     }
 
     pub fn overwrite_file(&mut self) -> Result<u64, Error> {
@@ -543,6 +1531,42 @@ impl EditorBuffer {
         }
     }
 
+    pub fn write_swap(&mut self, path: &Path) -> Result<(), Error> {
+        self.load_all()?;
+        fs::write(path, self.get_text())
+    }
+
+    /// Replaces the contents with the swap file at `path`, keeping the file path and type.
+    pub fn recover_swap(&mut self, path: &Path) -> Result<(), Error> {
+        let text = fs::read_to_string(path)?;
+        let lines: Vec<EditorLine> = text
+            .lines()
+            .map(|line| EditorLine::new(line.to_string(), self.file_type))
+            .collect();
+
+        self.lines = GapBuffer::from(lines);
+        self.generation += 1;
+        self.pending = None;
+        self.trailing_newline = text.ends_with('\n');
+        self.dirty = true;
+        self.highlight_all();
+
+        Ok(())
+    }
+
+    /// Replaces the contents with a read-only hex dump of `bytes`, 16 bytes per line. Edits to
+    /// the dump are never written back as bytes.
+    pub fn load_hex_dump(&mut self, bytes: &[u8]) {
+        let text: String = bytes
+            .chunks(16)
+            .enumerate()
+            .map(|(i, chunk)| hex_row(i * 16, chunk) + "\n")
+            .collect();
+        self.load_string(text);
+        self.encoding = Encoding::Binary;
+        self.read_only = true;
+    }
+
     pub fn load_string(&mut self, text: String) {
         let mut lines: Vec<EditorLine> = Vec::new();
 
@@ -550,26 +1574,68 @@ impl EditorBuffer {
             lines.push(EditorLine::new(line.to_string(), None));
         }
 
-        self.lines = lines;
+        self.lines = GapBuffer::from(lines);
+        self.generation += 1;
+        self.pending = None;
+        self.trailing_newline = text.ends_with('\n');
+        self.line_ending = LineEnding::Lf;
+        self.mixed_line_endings = false;
+        self.encoding = Encoding::Utf8;
+        self.read_only = false;
         self.filepath = None;
         self.file_type = None;
         for line in &mut self.lines {
             line.file_type = self.file_type;
         }
         self.dirty = false;
-        self.clear_highlight(0);
+        self.dirty_lines = None;
+        self.highlight_all();
+    }
+
+    /// Replaces the whole text in one go, re-highlighting it once and marking the buffer
+    /// dirty. Unlike `load_string`, the file path, file type and line ending are kept.
+    pub fn set_text(&mut self, text: &str) {
+        let lines: Vec<EditorLine> = text
+            .lines()
+            .map(|line| EditorLine::new(line.to_string(), self.file_type))
+            .collect();
+        self.lines = GapBuffer::from(lines);
+        self.generation += 1;
+        self.pending = None;
+        self.trailing_newline = text.ends_with('\n');
+        self.mixed_line_endings = false;
+        self.dirty = true;
+        self.dirty_lines = Some(0..self.lines.len());
+        self.highlight_all();
+    }
+
+    /// Returns the range of lines edited since the previous call, coalescing all the edits
+    /// in between, and starts tracking afresh. Lines that moved because lines were inserted
+    /// or deleted before them are not included.
+    pub fn take_dirty_lines(&mut self) -> Range<usize> {
+        self.dirty_lines.take().unwrap_or(0..0)
+    }
+
+    fn mark_dirty_line(&mut self, cy: usize) {
+        self.dirty = true;
+        self.generation += 1;
+        self.dirty_lines = Some(match self.dirty_lines.take() {
+            Some(range) => range.start.min(cy)..range.end.max(cy + 1),
+            None => cy..cy + 1,
+        });
     }
 
     pub fn insert_line(&mut self, cy: usize, line: String) {
         self.lines
             .insert(cy, EditorLine::new(line.to_string(), self.file_type));
-        self.dirty = true;
+        self.mark_dirty_line(cy);
+        self.clear_highlight(cy);
     }
 
     pub fn insert_char(&mut self, cx: usize, cy: usize, c: char) {
         if let Some(el) = self.lines.get_mut(cy) {
             el.insert_char(cx, c);
-            self.dirty = true;
+            self.mark_dirty_line(cy);
             self.clear_highlight(cy);
         }
     }
@@ -578,7 +1644,7 @@ impl EditorBuffer {
         if let Some(el) = self.lines.get_mut(cy) {
             if cx < el.raw.len() {
                 el.remove_char(cx);
-                self.dirty = true;
+                self.mark_dirty_line(cy);
                 self.clear_highlight(cy);
             }
         }
@@ -586,33 +1652,123 @@ impl EditorBuffer {
 
     pub fn delete_line(&mut self, cy: usize) {
         self.lines.remove(cy);
-        self.dirty = true;
+        self.mark_dirty_line(cy);
+        if cy < self.lines.len() {
+            self.clear_highlight(cy);
+        }
     }
 
     pub fn replace_line(&mut self, cy: usize, new_line: String) {
         self.lines[cy] = EditorLine::new(new_line, self.file_type);
+        self.mark_dirty_line(cy);
+        self.clear_highlight(cy);
     }
 
     pub fn append_string(&mut self, cx: usize, cy: usize, message: String) {
         if let Some(el) = self.lines.get_mut(cy) {
             el.insert_str(cx, &message);
-            self.dirty = true;
+            self.mark_dirty_line(cy);
             self.clear_highlight(cy);
         }
     }
 
+    /// Maps byte index `cx` in line `cy` to its render column. An index inside a multibyte
+    /// char maps to that char, and one past the end to the end of the line.
     pub fn cx_to_rx(&self, cx: usize, cy: usize) -> usize {
-        let mut rx = 0;
-        if let Some(line) = self.get_line(cy) {
-            for c in line.chars().take(cx) {
-                if c == '\t' {
-                    rx += (TAB_STOP - 1) - (rx % TAB_STOP);
-                }
-                rx += 1;
-            }
+        self.lines.get(cy).map_or(0, |el| {
+            let cx = cx.min(el.raw.len());
+            let index = if el.ascii {
+                cx
+            } else {
+                el.raw[..el.raw.floor_char_boundary(cx)].chars().count()
+            };
+            let offsets = &el.rx_offsets;
+            offsets[index.min(offsets.len() - 1)]
+        })
+    }
+
+    /// The render width of the widest loaded line.
+    pub fn max_render_width(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|el| el.rx_offsets.last().copied().unwrap_or(0))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Maps render column `rx` back to a char column, snapping a column inside a tab to the
+    /// tab itself and clamping to the end of the line.
+    pub fn rx_to_cx(&self, rx: usize, cy: usize) -> usize {
+        self.lines.get(cy).map_or(0, |el| {
+            let offsets = &el.rx_offsets;
+            (offsets.partition_point(|&start| start <= rx) - 1).min(offsets.len() - 1)
+        })
+    }
+
+    /// Like `rx_to_cx`, but as a byte index into the line, for slicing and editing it.
+    pub fn rx_to_byte(&self, rx: usize, cy: usize) -> usize {
+        self.lines.get(cy).map_or(0, |el| {
+            char_byte_index(&el.raw, self.rx_to_cx(rx, cy), el.ascii)
+        })
+    }
+}
+
+/// Returns the sibling temporary file used while saving `path`, e.g. `dir/.file.c.kilo.tmp`.
+fn temp_save_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let file_name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    path.with_file_name(format!(".{}.kilo.tmp", file_name))
+}
+
+/// Returns the swap file used for auto-saving `path`, e.g. `dir/.file.c.swp`.
+pub fn swap_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let file_name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    path.with_file_name(format!(".{}.swp", file_name))
+}
+
+/// Returns the swap file of `path` if it exists and is newer than `path` itself.
+pub fn find_swap(path: &str) -> Option<PathBuf> {
+    let swap = swap_path(path);
+    let swap_modified = fs::metadata(&swap).and_then(|m| m.modified()).ok()?;
+    match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) if modified >= swap_modified => None,
+        _ => Some(swap),
+    }
+}
+
+/// Writes `data` to a temporary file next to `path` and renames it over `path`,
+/// so that the original file is left intact if anything fails.
+fn write_atomically(path: &str, data: &[u8]) -> Result<u64, Error> {
+    if let Some(dir) = Path::new(path).parent() {
+        if !dir.as_os_str().is_empty() && !dir.is_dir() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("directory does not exist: {}", dir.display()),
+            ));
         }
-        rx
     }
+    let temp_path = temp_save_path(path);
+    let write_temp = || -> Result<u64, Error> {
+        let mut file = File::create(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(data)?;
+        file.flush()?;
+        file.sync_all()?;
+        Ok(file.metadata()?.size())
+    };
+
+    let ret = write_temp().and_then(|size| fs::rename(&temp_path, path).map(|_| size));
+    if ret.is_err() && temp_path.is_file() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    ret
 }
 
 impl Default for EditorBuffer {
@@ -623,7 +1779,23 @@ impl Default for EditorBuffer {
 
 #[cfg(test)]
 mod tests {
-    use super::{EditorBuffer, EditorLine};
+    use super::{
+        find_swap, hex_row, is_probably_binary, swap_path, temp_save_path, EditorBuffer,
+        EditorLine, Encoding, FileType, Highlight, Indent, LineEnding, RenderOptions,
+        SearchOptions,
+    };
+    use crate::escape_sequence::Color;
+    use crate::theme::Theme;
+    use crate::{LOAD_CHUNK_LINES, TAB_STOP};
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(format!("kilo-rs-{}-{}", std::process::id(), name));
+        path.to_string_lossy().to_string()
+    }
 
     #[test]
     fn test_convert_render() {
@@ -640,6 +1812,79 @@ mod tests {
         assert_eq!("123456  ", el.convert_render("123456\t"));
         assert_eq!("1234567 ", el.convert_render("1234567\t"));
         assert_eq!("12345678        ", el.convert_render("12345678\t"));
+        assert_eq!("        1       ", el.convert_render("\t1\t"));
+    }
+
+    #[test]
+    fn test_convert_render_wide_chars() {
+        let el = EditorLine::new("".to_string(), None);
+        assert_eq!("漢      x", el.convert_render("漢\tx"));
+        assert_eq!("a漢字   x", el.convert_render("a漢字\tx"));
+        assert_eq!("é       x", el.convert_render("é\tx"));
+        assert_eq!("漢字漢字        x", el.convert_render("漢字漢字\tx"));
+
+        let mut el = EditorLine::new("漢\tx".to_string(), None);
+        assert_eq!(vec![0, 1, 7, 8], el.rx_offsets);
+        assert_eq!(Some('›'), el.whitespace_glyphs()[1]);
+        assert_eq!(8, el.whitespace_glyphs().len());
+
+        el.insert_char(0, '字');
+        assert_eq!("字漢    x", el.render);
+        assert_eq!(
+            EditorLine::new(el.raw.clone(), None).rx_offsets,
+            el.rx_offsets
+        );
+        let end = el.raw.len() - 1;
+        el.insert_char(end, '\t');
+        assert_eq!(EditorLine::new(el.raw.clone(), None).render, el.render);
+    }
+
+    #[test]
+    fn test_incremental_render() {
+        let mut el = EditorLine::new("ab\tc".to_string(), None);
+        let edits: [&dyn Fn(&mut EditorLine); 6] = [
+            &|el| el.insert_char(0, '\t'),
+            &|el| el.insert_char(3, 'x'),
+            &|el| el.insert_str(5, "\t12\t"),
+            &|el| el.remove_char(0),
+            &|el| el.insert_char(el.raw.len(), '\t'),
+            &|el| el.remove_char(2),
+        ];
+        for (i, edit) in edits.iter().enumerate() {
+            edit(&mut el);
+            let full = EditorLine::new(el.raw.clone(), None);
+            assert_eq!(full.render, el.render, "edit {} {:?}", i, el.raw);
+            assert_eq!(full.rx_offsets, el.rx_offsets, "edit {} {:?}", i, el.raw);
+        }
+    }
+
+    #[test]
+    fn test_ascii_fast_path() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("ab\tc\nxyz\n".to_string());
+        assert!(buffer.lines[0].ascii);
+
+        buffer.insert_char(1, 0, 'x');
+        assert_eq!("axb     c", buffer.lines[0].render);
+        assert_eq!(8, buffer.cx_to_rx(4, 0));
+        let theme = Theme::default();
+        let render = buffer.get_render(0, 1, 4, &RenderOptions::new(&theme));
+        assert_eq!(Some("xb  \x1b[39m".to_string()), render);
+
+        buffer.insert_char(1, 0, 'é');
+        assert!(!buffer.lines[0].ascii);
+        assert!(buffer.lines[1].ascii);
+        assert_eq!("aéxb    c", buffer.lines[0].render);
+        assert_eq!(4, buffer.cx_to_rx(5, 0));
+        assert_eq!(8, buffer.cx_to_rx(6, 0));
+        assert_eq!(3, buffer.rx_to_cx(3, 0));
+        let render = buffer.get_render(0, 1, 4, &RenderOptions::new(&theme));
+        assert_eq!(Some("éxb \x1b[39m".to_string()), render);
+
+        buffer.insert_char(3, 0, 'y');
+        let full = EditorLine::new(buffer.lines[0].raw.clone(), None);
+        assert_eq!(full.render, buffer.lines[0].render);
+        assert_eq!(full.rx_offsets, buffer.lines[0].rx_offsets);
     }
 
     #[test]
@@ -649,5 +1894,1014 @@ mod tests {
 
         let rx = buffer.cx_to_rx(4, 0);
         assert_eq!(8, rx);
+
+        buffer.load_string("\t1\t".to_string());
+        assert_eq!(buffer.lines[0].render.len(), buffer.cx_to_rx(3, 0));
+
+        // `cx` is a byte index, so it counts a multibyte char once per byte.
+        buffer.load_string("éab\n日\tx".to_string());
+        assert_eq!(0, buffer.cx_to_rx(0, 0));
+        assert_eq!(0, buffer.cx_to_rx(1, 0));
+        assert_eq!(1, buffer.cx_to_rx(2, 0));
+        assert_eq!(2, buffer.cx_to_rx(3, 0));
+        assert_eq!(3, buffer.cx_to_rx(usize::MAX, 0));
+        assert_eq!(1, buffer.cx_to_rx(3, 1));
+        assert_eq!(7, buffer.cx_to_rx(4, 1));
+    }
+
+    #[test]
+    fn test_take_dirty_lines() {
+        let mut buffer = EditorBuffer::new();
+        assert_eq!(0..0, buffer.take_dirty_lines());
+
+        buffer.load_string("0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n".to_string());
+        assert_eq!(0..0, buffer.take_dirty_lines());
+        buffer.insert_char(0, 5, 'x');
+        buffer.replace_line(3, "three".to_string());
+        buffer.delete_char(0, 7);
+        buffer.insert_char(1, 5, 'y');
+        assert_eq!(3..8, buffer.take_dirty_lines());
+        assert_eq!(0..0, buffer.take_dirty_lines());
+    }
+
+    #[test]
+    fn test_cached_rx_matches_naive() {
+        let naive_cx_to_rx = |line: &str, cx: usize| {
+            let mut rx = 0;
+            for c in line.chars().take(cx) {
+                if c == '\t' {
+                    rx += (TAB_STOP - 1) - (rx % TAB_STOP);
+                }
+                rx += 1;
+            }
+            rx
+        };
+
+        let lines = [
+            "",
+            "\t",
+            "\t\t\t",
+            "a\tbc\t\td",
+            "1234567\t12345678\t\tx",
+            "\t \t  \t   \t",
+        ];
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string(lines.join("\n"));
+        for (cy, line) in lines.iter().enumerate() {
+            let len = line.chars().count();
+            for cx in 0..=len + 2 {
+                let rx = naive_cx_to_rx(line, cx);
+                assert_eq!(rx, buffer.cx_to_rx(cx, cy), "{:?} cx={}", line, cx);
+                if cx < len {
+                    assert_eq!(cx, buffer.rx_to_cx(rx, cy), "{:?} rx={}", line, rx);
+                }
+            }
+        }
+
+        buffer.insert_char(1, 3, '\t');
+        assert_eq!(naive_cx_to_rx("a\t\tbc\t\td", 4), buffer.cx_to_rx(4, 3));
+    }
+
+    #[test]
+    fn test_rx_to_cx() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("a\tbc\t\td".to_string());
+
+        for cx in 0..=7 {
+            assert_eq!(cx, buffer.rx_to_cx(buffer.cx_to_rx(cx, 0), 0), "cx={}", cx);
+        }
+
+        assert_eq!(1, buffer.rx_to_cx(1, 0));
+        assert_eq!(1, buffer.rx_to_cx(5, 0));
+        assert_eq!(1, buffer.rx_to_cx(7, 0));
+        assert_eq!(2, buffer.rx_to_cx(8, 0));
+        assert_eq!(5, buffer.rx_to_cx(20, 0));
+        assert_eq!(7, buffer.rx_to_cx(100, 0));
+        assert_eq!(0, buffer.rx_to_cx(3, 1));
+
+        buffer.load_string("é\tb".to_string());
+        assert_eq!(1, buffer.rx_to_cx(1, 0));
+        assert_eq!(2, buffer.rx_to_byte(1, 0));
+        assert_eq!(3, buffer.rx_to_byte(8, 0));
+        assert_eq!(4, buffer.rx_to_byte(100, 0));
+    }
+
+    #[test]
+    fn test_set_text() {
+        let path = temp_path("set_text.c");
+        fs::write(&path, "x\n").unwrap();
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+
+        let text = buffer.get_text().replace('x', "/* a\nb */ int y;");
+        buffer.set_text(&text);
+        assert_eq!(Some(path.clone()), buffer.get_filepath());
+        assert_eq!(Some(FileType::C), buffer.get_file_type());
+        assert!(buffer.is_dirty());
+        assert_eq!(text, buffer.get_text());
+        assert_eq!(Highlight::MultilineComment, buffer.lines[1].highlight[0]);
+        assert_eq!(Highlight::Keyword2, buffer.lines[1].highlight[6]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_line_count_digits() {
+        for (lines, digits) in [(0, 1), (9, 1), (10, 2), (100, 3), (1000, 4)] {
+            let mut buffer = EditorBuffer::new();
+            buffer.load_string("a\n".repeat(lines));
+            assert_eq!(lines, buffer.len());
+            assert_eq!(digits, buffer.line_count_digits(), "lines={}", lines);
+        }
+    }
+
+    #[test]
+    fn test_line_whitespace() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("\n  \t \n  foo\n\t bar \nbaz\n".to_string());
+
+        assert!(buffer.is_line_empty(0));
+        assert_eq!("", buffer.leading_whitespace(0));
+        assert_eq!(0, buffer.indent_of(0));
+
+        assert!(buffer.is_line_empty(1));
+        assert_eq!("  \t ", buffer.leading_whitespace(1));
+        assert_eq!(9, buffer.indent_of(1));
+
+        assert!(!buffer.is_line_empty(2));
+        assert_eq!("  ", buffer.leading_whitespace(2));
+        assert_eq!(2, buffer.indent_of(2));
+
+        assert!(!buffer.is_line_empty(3));
+        assert_eq!("\t ", buffer.leading_whitespace(3));
+        assert_eq!(9, buffer.indent_of(3));
+
+        assert!(!buffer.is_line_empty(4));
+        assert_eq!(0, buffer.indent_of(4));
+
+        assert!(buffer.is_line_empty(5));
+        assert_eq!("", buffer.leading_whitespace(5));
+    }
+
+    #[test]
+    fn test_mixed_indent_lines() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("a\n\tb\n    c\n\t  d\n  \te\n\t\tf g\n \t\n".to_string());
+        assert_eq!(vec![3, 4, 6], buffer.mixed_indent_lines());
+
+        buffer.load_string("\ta\n\t\tb\n".to_string());
+        assert!(buffer.mixed_indent_lines().is_empty());
+    }
+
+    #[test]
+    fn test_find_all() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("foo bar foo\nbar\nFoo foofoo\naaaa\n".to_string());
+        let options = SearchOptions::default();
+        assert_eq!(
+            vec![(0, 0), (8, 0), (4, 2), (7, 2)],
+            buffer.find_all("foo", options)
+        );
+        assert_eq!(vec![(0, 3), (2, 3)], buffer.find_all("aa", options));
+        assert_eq!(
+            vec![0, 1, 2],
+            options.overlapping_match_positions("aaaa", "aa")
+        );
+        assert_eq!(Vec::<(usize, usize)>::new(), buffer.find_all("", options));
+
+        let options = SearchOptions {
+            ignore_case: true,
+            whole_word: true,
+        };
+        assert_eq!(
+            vec![(0, 0), (8, 0), (0, 2)],
+            buffer.find_all("foo", options)
+        );
+    }
+
+    #[test]
+    fn test_save_file_trailing_newline() {
+        for (name, content) in [
+            ("with_newline.txt", "abc\ndef\n"),
+            ("without_newline.txt", "abc\ndef"),
+        ] {
+            let src = temp_path(name);
+            let dst = temp_path(&format!("saved_{}", name));
+            fs::write(&src, content).unwrap();
+
+            let mut buffer = EditorBuffer::new();
+            buffer.load_file(src.clone()).unwrap();
+            let size = buffer.save_file(dst.clone()).unwrap();
+
+            assert_eq!(content.len() as u64, size, "{}", name);
+            assert_eq!(content, fs::read_to_string(&dst).unwrap(), "{}", name);
+
+            fs::remove_file(&src).unwrap();
+            fs::remove_file(&dst).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_set_line_ending() {
+        let path = temp_path("line_ending.txt");
+        fs::write(&path, "abc\ndef\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        assert_eq!(LineEnding::Lf, buffer.line_ending());
+        buffer.set_line_ending(LineEnding::Crlf);
+        assert!(buffer.is_dirty());
+        buffer.overwrite_file().unwrap();
+        assert_eq!("abc\r\ndef\r\n", fs::read_to_string(&path).unwrap());
+
+        buffer.load_file(path.clone()).unwrap();
+        assert_eq!(LineEnding::Crlf, buffer.line_ending());
+        assert_eq!(Some("abc".to_string()), buffer.get_line(0));
+        buffer.set_line_ending(LineEnding::Lf);
+        buffer.overwrite_file().unwrap();
+        assert_eq!("abc\ndef\n", fs::read_to_string(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mixed_line_endings() {
+        for (name, content, line_ending, mixed) in [
+            ("lf.txt", "a\nb\nc", LineEnding::Lf, false),
+            ("crlf.txt", "a\r\nb\r\n", LineEnding::Crlf, false),
+            ("mixed.txt", "a\r\nb\nc\r\n", LineEnding::Crlf, true),
+        ] {
+            let path = temp_path(name);
+            fs::write(&path, content).unwrap();
+
+            let mut buffer = EditorBuffer::new();
+            buffer.load_file(path.clone()).unwrap();
+            assert_eq!(line_ending, buffer.line_ending(), "{}", name);
+            assert_eq!(mixed, buffer.has_mixed_line_endings(), "{}", name);
+
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_save_file_new_buffer_trailing_newline() {
+        let path = temp_path("new_buffer.txt");
+
+        let mut buffer = EditorBuffer::new();
+        buffer.insert_line(0, "abc".to_string());
+        buffer.save_file(path.clone()).unwrap();
+
+        assert_eq!("abc\n", fs::read_to_string(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_file_missing_directory() {
+        let dir = temp_path("nonexistent_dir");
+        let path = format!("{}/file.txt", dir);
+
+        let mut buffer = EditorBuffer::new();
+        buffer.insert_line(0, "abc".to_string());
+        let err = buffer.save_file(path).unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert_eq!(
+            format!("directory does not exist: {}", dir),
+            err.to_string()
+        );
+        assert_eq!(None, buffer.get_filepath());
+    }
+
+    #[test]
+    fn test_save_copy() {
+        let src = temp_path("copy_src.txt");
+        let dst = temp_path("copy_dst.txt");
+        fs::write(&src, "abc\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(src.clone()).unwrap();
+        buffer.insert_line(1, "def".to_string());
+        buffer.save_copy(&dst).unwrap();
+
+        assert_eq!("abc\ndef\n", fs::read_to_string(&dst).unwrap());
+        assert_eq!("abc\n", fs::read_to_string(&src).unwrap());
+        assert_eq!(Some(src.clone()), buffer.get_filepath());
+        assert!(buffer.is_dirty());
+
+        fs::remove_file(&src).unwrap();
+        fs::remove_file(&dst).unwrap();
+    }
+
+    #[test]
+    fn test_append_to_file() {
+        let path = temp_path("append.log");
+        fs::write(&path, "first\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.insert_line(0, "second".to_string());
+        let size = buffer.append_to_file(&path).unwrap();
+
+        assert_eq!(7, size);
+        assert_eq!("first\nsecond\n", fs::read_to_string(&path).unwrap());
+        assert_eq!(None, buffer.get_filepath());
+        assert!(buffer.is_dirty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_file_failure_keeps_buffer() {
+        let path = temp_path("keep.c");
+        let dir = temp_path("unreadable.txt");
+        fs::write(&path, "int x;\n").unwrap();
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        buffer.insert_line(1, "int y;".to_string());
+        assert!(buffer.load_file(dir.clone()).is_err());
+
+        assert_eq!(Some(path.clone()), buffer.get_filepath());
+        assert_eq!(Some(FileType::C), buffer.get_file_type());
+        assert_eq!(Some("int y;".to_string()), buffer.get_line(1));
+        assert!(buffer.is_dirty());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_probably_binary() {
+        assert!(!is_probably_binary(b""));
+        assert!(!is_probably_binary(
+            b"fn main() {\r\n\tprintln!(\"\x1b[1m\");\n}\n"
+        ));
+        assert!(!is_probably_binary("caf\u{e9}\n".as_bytes()));
+        assert!(is_probably_binary(b"ELF\x02\x01\x00\x00"));
+        assert!(is_probably_binary(b"\x01\x02\x03abcdef"));
+    }
+
+    #[test]
+    fn test_hex_row() {
+        assert_eq!(
+            "00000000: 48 65 6c 6c 6f 00 0a 7f 20 21 7e ff 01 02 03 04 | Hello... !~.....",
+            hex_row(0, b"Hello\x00\n\x7f !~\xff\x01\x02\x03\x04")
+        );
+        assert_eq!(
+            "00000010: 61 62                                           | ab",
+            hex_row(16, b"ab")
+        );
+    }
+
+    #[test]
+    fn test_load_hex_dump() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_hex_dump(&[b'x'; 20]);
+
+        assert_eq!(2, buffer.len());
+        assert_eq!(Some(hex_row(16, b"xxxx")), buffer.get_line(1));
+        assert!(buffer.is_read_only());
+        assert_eq!(None, buffer.get_filepath());
+    }
+
+    #[test]
+    fn test_load_file_binary() {
+        let path = temp_path("binary.bin");
+        fs::write(&path, b"\x7fELF\x00\x01\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        assert_eq!(Encoding::Binary, buffer.encoding());
+        assert!(buffer.is_read_only());
+
+        let mut buffer = EditorBuffer::new();
+        buffer.set_refuse_binary(true);
+        let err = buffer.load_file(path.clone()).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert_eq!(None, buffer.get_filepath());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_file_invalid_utf8() {
+        let path = temp_path("latin1.txt");
+        fs::write(&path, b"caf\xe9\nabc\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        assert!(buffer.load_file(path.clone()).is_ok());
+        assert_eq!(Some("caf\u{fffd}".to_string()), buffer.get_line(0));
+        assert_eq!(Some("abc".to_string()), buffer.get_line(1));
+        assert_eq!(Encoding::Unknown, buffer.encoding());
+        assert!(buffer.is_read_only());
+        assert!(buffer.overwrite_file().is_err());
+        assert_eq!(b"caf\xe9\nabc\n".to_vec(), fs::read(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_editorconfig_applied_on_save() {
+        let dir = PathBuf::from(temp_path("editorconfig"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(".editorconfig"),
+            "root = true\n[*.c]\nindent_style = space\nindent_size = 2\n\
+             trim_trailing_whitespace = true\ninsert_final_newline = true\nend_of_line = crlf\n",
+        )
+        .unwrap();
+        let path = dir.join("main.c").to_string_lossy().into_owned();
+        fs::write(&path, "int x; \t\nint y;").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        assert_eq!(Indent::Spaces(2), buffer.indent());
+        assert_eq!(LineEnding::Lf, buffer.line_ending());
+        buffer.overwrite_file().unwrap();
+        assert_eq!("int x;\r\nint y;\r\n", fs::read_to_string(&path).unwrap());
+        assert_eq!(Some("int x;".to_string()), buffer.get_line(0));
+
+        fs::write(dir.join("notes.txt"), "a \n").unwrap();
+        buffer
+            .load_file(dir.join("notes.txt").to_string_lossy().into_owned())
+            .unwrap();
+        assert_eq!(Indent::Tabs, buffer.indent());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_file_utf16() {
+        let path = temp_path("utf16.txt");
+        fs::write(&path, b"\xff\xfea\x00b\x00\n\x00").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        assert_eq!(Some("ab".to_string()), buffer.get_line(0));
+        assert_eq!(Encoding::Utf16Le, buffer.encoding());
+        assert!(buffer.is_read_only());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_file_bom() {
+        let path = temp_path("bom.txt");
+        fs::write(&path, b"\xef\xbb\xbfab\ncd\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        assert_eq!(Some("ab".to_string()), buffer.get_line(0));
+        assert!(buffer.has_bom());
+        assert!(!buffer.is_read_only());
+
+        buffer.insert_char(2, 1, 'e');
+        buffer.save_file(path.clone()).unwrap();
+        assert_eq!(b"\xef\xbb\xbfab\ncde\n".to_vec(), fs::read(&path).unwrap());
+
+        buffer.set_bom(false);
+        assert!(buffer.is_dirty());
+        buffer.save_file(path.clone()).unwrap();
+        assert_eq!(b"ab\ncde\n".to_vec(), fs::read(&path).unwrap());
+
+        fs::write(&path, b"ab\n").unwrap();
+        buffer.load_file(path.clone()).unwrap();
+        assert!(!buffer.has_bom());
+        buffer.save_file(path.clone()).unwrap();
+        assert_eq!(b"ab\n".to_vec(), fs::read(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_batched_highlight() {
+        let path = temp_path("batched.c");
+        fs::write(&path, "1;\n".repeat(10)).unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        buffer.highlight(0, 3, 1, Highlight::Match);
+        let end = buffer.with_batched_highlight(|buffer| {
+            for (i, line) in ["/*", "a", "b", "*/"].iter().enumerate() {
+                buffer.insert_line(2 + i, line.to_string());
+            }
+            assert_eq!(Highlight::Normal, buffer.lines[3].highlight[0]);
+            buffer.len()
+        });
+        assert_eq!(14, end);
+        for i in 2..6 {
+            assert_eq!(Highlight::MultilineComment, buffer.lines[i].highlight[0]);
+        }
+        assert_eq!(Highlight::Number, buffer.lines[6].highlight[0]);
+        // The single pass stopped at the first untouched line that came out unchanged.
+        assert_eq!(Highlight::Match, buffer.lines[7].highlight[0]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_clear_highlight_stops_at_stable_line() {
+        let path = temp_path("rehighlight.c");
+        fs::write(&path, "1 + 2;\n".repeat(5000)).unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        buffer.load_all().unwrap();
+        assert_eq!(7, buffer.clear_highlight(5));
+
+        buffer.highlight(0, 6, 3, Highlight::Match);
+        buffer.highlight(0, 7, 3, Highlight::Match);
+        buffer.insert_char(0, 5, 'x');
+        assert_eq!(Highlight::Number, buffer.lines[6].highlight[0]);
+        assert_eq!(Highlight::Match, buffer.lines[7].highlight[0]);
+
+        buffer.append_string(0, 5, "/*".to_string());
+        assert_eq!(Highlight::MultilineComment, buffer.lines[7].highlight[0]);
+        assert!(buffer.lines[4999].open_comment);
+
+        buffer.append_string(0, 10, "*/".to_string());
+        assert_eq!(Highlight::Number, buffer.lines[11].highlight[0]);
+        assert!(!buffer.lines[4999].open_comment);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_background_highlight() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("int a;\nint b;\nint c;\n".to_string());
+        buffer.file_type = Some(FileType::C);
+        for line in &mut buffer.lines {
+            line.file_type = buffer.file_type;
+        }
+        buffer.highlight_all();
+        buffer.set_background_highlight(true);
+
+        buffer.replace_line(0, "/* int a;".to_string());
+        assert_eq!(Highlight::MultilineComment, buffer.lines[0].highlight[3]);
+        buffer.replace_line(1, "int x;".to_string());
+
+        let started = std::time::Instant::now();
+        while !buffer.lines[2].open_comment {
+            assert!(started.elapsed().as_secs() < 5, "highlights never arrived");
+            buffer.apply_background_highlights();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        for line in buffer.lines.iter() {
+            assert!(line
+                .highlight
+                .iter()
+                .all(|&hl| hl == Highlight::MultilineComment));
+        }
+    }
+
+    #[test]
+    fn test_background_highlight_after_shift() {
+        let text = format!("int a;\n{}int b;\n", "}\n".repeat(2500));
+        let mut buffer = EditorBuffer::new();
+        buffer.file_type = Some(FileType::C);
+        buffer.load_string(text);
+        buffer.set_background_highlight(true);
+
+        buffer.replace_line(0, "/* int a;".to_string());
+        buffer.insert_line(0, "int z;".to_string());
+        buffer.delete_line(5);
+
+        let started = std::time::Instant::now();
+        loop {
+            buffer.apply_background_highlights();
+            let highlighter = buffer.highlighter.as_ref().unwrap();
+            if !highlighter.in_flight && highlighter.pending.is_none() {
+                break;
+            }
+            assert!(started.elapsed().as_secs() < 5, "highlights never arrived");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let mut expected = EditorBuffer::new();
+        expected.file_type = Some(FileType::C);
+        expected.load_string(buffer.get_text());
+        assert_eq!(expected.len(), buffer.len());
+        for (i, (line, expected)) in buffer.lines.iter().zip(expected.lines.iter()).enumerate() {
+            assert_eq!(expected.highlight, line.highlight, "line {}", i);
+            assert_eq!(expected.open_comment, line.open_comment, "line {}", i);
+        }
+    }
+
+    fn assert_highlight(line: &str, begin: usize, end: usize, highlight: Highlight) {
+        let el = EditorLine::new(line.to_string(), Some(FileType::C));
+        for (i, hl) in el.highlight.iter().enumerate() {
+            let expected = if (begin..end).contains(&i) {
+                highlight
+            } else {
+                Highlight::Normal
+            };
+            assert_eq!(expected, *hl, "line:{} i:{}", line, i);
+        }
+    }
+
+    #[test]
+    fn test_highlight_keyword() {
+        assert_highlight("int x;", 0, 3, Highlight::Keyword2);
+        assert_highlight("int;", 0, 3, Highlight::Keyword2);
+        assert_highlight("(int)", 1, 4, Highlight::Keyword2);
+        assert_highlight("int", 0, 3, Highlight::Keyword2);
+        assert_highlight("integer", 0, 0, Highlight::Keyword2);
+    }
+
+    #[test]
+    fn test_highlight_number() {
+        assert_highlight("x = 123;", 4, 7, Highlight::Number);
+        assert_highlight("x = 3.14f;", 4, 9, Highlight::Number);
+        assert_highlight("x = 0xFF;", 4, 8, Highlight::Number);
+        assert_highlight("x = 0x1fUL;", 4, 10, Highlight::Number);
+        assert_highlight("x = 1.5e-3;", 4, 10, Highlight::Number);
+        assert_highlight("x = 1E10;", 4, 8, Highlight::Number);
+        assert_highlight("x = 100UL;", 4, 9, Highlight::Number);
+        assert_highlight("a1 = 1x;", 5, 6, Highlight::Number);
+    }
+
+    #[test]
+    fn test_highlight_escape() {
+        let el = EditorLine::new("x = \"a\\nb\\\"\";".to_string(), Some(FileType::C));
+        let expected = [
+            vec![Highlight::Normal; 4],
+            vec![Highlight::String; 2],
+            vec![Highlight::Escape; 2],
+            vec![Highlight::String; 1],
+            vec![Highlight::Escape; 2],
+            vec![Highlight::String; 1],
+            vec![Highlight::Normal; 1],
+        ]
+        .concat();
+        assert_eq!(expected, el.highlight);
+    }
+
+    #[test]
+    fn test_get_render_theme() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("if (x) return 1;".to_string());
+        buffer.file_type = Some(FileType::C);
+        for line in &mut buffer.lines {
+            line.file_type = buffer.file_type;
+        }
+        buffer.highlight_all();
+
+        let mut theme = Theme::default();
+        theme.set_color(Highlight::Keyword1, Color::Rgb(255, 136, 0));
+        theme.set_color(Highlight::Number, Color::Ansi256(208));
+
+        let render = buffer
+            .get_render(0, 0, 80, &RenderOptions::new(&theme))
+            .unwrap();
+        assert_eq!(
+            "\x1b[38;2;255;136;0mif\x1b[39m (x) \x1b[38;2;255;136;0mreturn\x1b[39m \x1b[38;5;208m1\x1b[39m;\x1b[39m",
+            render
+        );
+    }
+
+    #[test]
+    fn test_get_render_color_disabled() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("if (x) return 1; /* \x01 */".to_string());
+        buffer.file_type = Some(FileType::C);
+        for line in &mut buffer.lines {
+            line.file_type = buffer.file_type;
+        }
+        buffer.highlight_all();
+
+        let mut theme = Theme::default();
+        theme.set_color_enabled(false);
+        let render = buffer
+            .get_render(
+                0,
+                0,
+                80,
+                &RenderOptions {
+                    rulers: &[40],
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!("if (x) return 1; /* \x1b[7mA\x1b[m */", render);
+
+        let render = buffer
+            .get_render(0, 0, 16, &RenderOptions::new(&theme))
+            .unwrap();
+        assert!(!render.contains("\x1b["), "{:?}", render);
+    }
+
+    #[test]
+    fn test_get_render_selection() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("abcdef".to_string());
+        let theme = Theme::default();
+
+        let render = buffer
+            .get_render(
+                0,
+                0,
+                80,
+                &RenderOptions {
+                    selection: Some((2, 5)),
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!("ab\x1b[7m\x1b[39mcde\x1b[27m\x1b[39mf\x1b[39m", render);
+    }
+
+    #[test]
+    fn test_get_render_markers() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("ab 123".to_string());
+        buffer.highlight(3, 0, 3, Highlight::Match);
+        let theme = Theme::default();
+
+        let render = buffer
+            .get_render(
+                0,
+                0,
+                80,
+                &RenderOptions {
+                    markers: &[4],
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!("ab \x1b[34m1\x1b[7m2\x1b[27m3\x1b[39m", render);
+
+        let render = buffer
+            .get_render(
+                0,
+                0,
+                80,
+                &RenderOptions {
+                    selection: Some((0, 2)),
+                    markers: &[1, 8],
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            "\x1b[7m\x1b[39ma\x1b[27mb\x1b[7m\x1b[27m\x1b[39m \x1b[34m123\x1b[39m  \x1b[7m \x1b[27m",
+            render
+        );
+    }
+
+    #[test]
+    fn test_get_render_rulers() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("abcdef\r\nab\r\n".to_string());
+        let mut theme = Theme::default();
+        theme.set_color(Highlight::Ruler, Color::Ansi256(236));
+
+        let render = buffer
+            .get_render(
+                0,
+                0,
+                80,
+                &RenderOptions {
+                    rulers: &[4],
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!("abc\x1b[48;5;236md\x1b[49mef\x1b[39m", render);
+
+        let render = buffer
+            .get_render(
+                1,
+                0,
+                80,
+                &RenderOptions {
+                    rulers: &[6, 4],
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            "ab\x1b[39m \x1b[48;5;236m \x1b[49m \x1b[48;5;236m \x1b[49m",
+            render
+        );
+
+        let render = buffer
+            .get_render(
+                1,
+                0,
+                3,
+                &RenderOptions {
+                    rulers: &[4],
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!("ab\x1b[39m", render);
+    }
+
+    #[test]
+    fn test_get_render_show_whitespace() {
+        let mut buffer = EditorBuffer::new();
+        buffer.load_string("\ta b  ".to_string());
+        let theme = Theme::default();
+
+        let render = buffer
+            .get_render(0, 0, 80, &RenderOptions::new(&theme))
+            .unwrap();
+        assert_eq!("        a b  \x1b[39m", render);
+        assert!(!render.contains('›'));
+        assert!(!render.contains('·'));
+
+        let render = buffer
+            .get_render(
+                0,
+                0,
+                80,
+                &RenderOptions {
+                    show_whitespace: true,
+                    ..RenderOptions::new(&theme)
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            "\x1b[2m›\x1b[22m       a b\x1b[2m·\x1b[22m\x1b[2m·\x1b[22m\x1b[39m",
+            render
+        );
+        assert_eq!(
+            buffer.lines[0].whitespace_glyphs().len(),
+            buffer.cx_to_rx(6, 0)
+        );
+    }
+
+    #[test]
+    fn test_load_file_streamed() {
+        let path = temp_path("large.txt");
+        let total = LOAD_CHUNK_LINES * 5 + 10;
+        let content: String = (0..total).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, content).unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        assert!(!buffer.is_fully_loaded());
+        assert_eq!(LOAD_CHUNK_LINES, buffer.len());
+        assert_eq!(Some("line 0".to_string()), buffer.get_line(0));
+
+        buffer.ensure_loaded(LOAD_CHUNK_LINES + 20).unwrap();
+        assert!(!buffer.is_fully_loaded());
+        assert_eq!(LOAD_CHUNK_LINES + 20, buffer.len());
+        assert_eq!(
+            Some(format!("line {}", LOAD_CHUNK_LINES + 19)),
+            buffer.get_line(LOAD_CHUNK_LINES + 19)
+        );
+
+        buffer.load_all().unwrap();
+        assert!(buffer.is_fully_loaded());
+        assert_eq!(total, buffer.len());
+        assert_eq!(
+            Some(format!("line {}", total - 1)),
+            buffer.get_line(total - 1)
+        );
+        assert!(buffer.trailing_newline);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_temp_save_path() {
+        assert_eq!(
+            "dir/.file.c.kilo.tmp",
+            temp_save_path("dir/file.c").to_string_lossy()
+        );
+        assert_eq!(".file.kilo.tmp", temp_save_path("file").to_string_lossy());
+    }
+
+    #[test]
+    fn test_save_file_preserves_original_on_failure() {
+        let path = temp_path("atomic.txt");
+        fs::write(&path, "original\n").unwrap();
+        let temp = temp_save_path(&path);
+        fs::create_dir(&temp).unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        buffer.insert_char(0, 0, 'x');
+        assert!(buffer.save_file(path.clone()).is_err());
+        assert_eq!("original\n", fs::read_to_string(&path).unwrap());
+        assert!(buffer.is_dirty());
+
+        fs::remove_dir(&temp).unwrap();
+        assert_eq!(10, buffer.save_file(path.clone()).unwrap());
+        assert_eq!("xoriginal\n", fs::read_to_string(&path).unwrap());
+        assert!(!temp.exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_file_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("permissions.sh");
+        fs::write(&path, "echo\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o754)).unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        buffer.save_file(path.clone()).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o754, mode & 0o777);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_file_backup() {
+        let path = temp_path("backup.txt");
+        let backup = format!("{}~", path);
+        fs::write(&path, "original\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.set_backup_on_save(true);
+        buffer.load_file(path.clone()).unwrap();
+        buffer.insert_char(0, 0, 'x');
+        buffer.save_file(path.clone()).unwrap();
+
+        assert_eq!("original\n", fs::read_to_string(&backup).unwrap());
+        assert_eq!("xoriginal\n", fs::read_to_string(&path).unwrap());
+
+        buffer.insert_char(0, 0, 'y');
+        buffer.overwrite_file().unwrap();
+
+        assert_eq!("original\n", fs::read_to_string(&backup).unwrap());
+        assert_eq!("yxoriginal\n", fs::read_to_string(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn test_save_file_without_backup() {
+        let path = temp_path("no_backup.txt");
+        fs::write(&path, "original\n").unwrap();
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        buffer.save_file(path.clone()).unwrap();
+
+        assert!(!std::path::Path::new(&format!("{}~", path)).exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_swap() {
+        let path = temp_path("swap.txt");
+        fs::write(&path, "abc\n").unwrap();
+        let swap = swap_path(&path);
+
+        let mut buffer = EditorBuffer::new();
+        buffer.load_file(path.clone()).unwrap();
+        buffer.insert_line(1, "def".to_string());
+        buffer.write_swap(&swap).unwrap();
+        assert_eq!("abc\ndef\n", fs::read_to_string(&swap).unwrap());
+        assert_eq!("abc\n", fs::read_to_string(&path).unwrap());
+
+        let mut recovered = EditorBuffer::new();
+        recovered.load_file(path.clone()).unwrap();
+        recovered.recover_swap(&swap).unwrap();
+        assert_eq!(Some("def".to_string()), recovered.get_line(1));
+        assert_eq!(Some(path.clone()), recovered.get_filepath());
+        assert!(recovered.is_dirty());
+
+        recovered.save_file(path.clone()).unwrap();
+        assert!(!swap.exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_find_swap() {
+        use std::time::{Duration, SystemTime};
+
+        let path = temp_path("find_swap.txt");
+        let swap = swap_path(&path);
+        assert_eq!(None, find_swap(&path));
+
+        fs::write(&swap, "abc\n").unwrap();
+        assert_eq!(Some(swap.clone()), find_swap(&path));
+
+        fs::write(&path, "abc\n").unwrap();
+        let now = SystemTime::now();
+        let file = fs::File::options().write(true).open(&swap).unwrap();
+        file.set_modified(now - Duration::from_secs(60)).unwrap();
+        assert_eq!(None, find_swap(&path));
+
+        file.set_modified(now + Duration::from_secs(60)).unwrap();
+        assert_eq!(Some(swap.clone()), find_swap(&path));
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&swap).unwrap();
     }
 }
@@ -0,0 +1,228 @@
+use crate::screen::LineNumbers;
+use std::io::Error;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides whether to use color. `Always` and `Never` win over the environment, while
+    /// `Auto` needs a terminal that isn't `TERM=dumb` and no non-empty `NO_COLOR`.
+    pub fn enabled(self, no_color: bool, is_terminal: bool, dumb_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && !no_color && !dumb_terminal,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct Args {
+    pub filepath: Option<String>,
+    /// 1-based line and column to open `filepath` at.
+    pub position: Option<(usize, usize)>,
+    pub backup: bool,
+    /// 1-based columns to draw rulers at.
+    pub rulers: Vec<usize>,
+    pub color: ColorChoice,
+    pub line_numbers: LineNumbers,
+    /// Render columns to cut long lines off at.
+    pub max_line_length: Option<usize>,
+    pub smooth_scroll: bool,
+    /// Refuse to open files that look binary instead of showing them read-only.
+    pub refuse_binary: bool,
+}
+
+impl Args {
+    /// Parses the command line, where `args[0]` is the program name.
+    pub fn parse(args: &[String]) -> Result<Args, Error> {
+        let mut parsed = Args::default();
+        for arg in args.iter().skip(1) {
+            match arg.as_str() {
+                "--backup" => parsed.backup = true,
+                "--smooth-scroll" => parsed.smooth_scroll = true,
+                "--refuse-binary" => parsed.refuse_binary = true,
+                "--color=auto" => parsed.color = ColorChoice::Auto,
+                "--color=always" => parsed.color = ColorChoice::Always,
+                "--color=never" => parsed.color = ColorChoice::Never,
+                "--line-numbers" => parsed.line_numbers = LineNumbers::Absolute,
+                "--relative-number" => parsed.line_numbers = LineNumbers::Relative,
+                flag if flag.starts_with("--ruler=") => {
+                    let column = flag["--ruler=".len()..]
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&column| column > 0)
+                        .ok_or_else(|| Error::other(format!("invalid ruler: {}", flag)))?;
+                    parsed.rulers.push(column);
+                }
+                flag if flag.starts_with("--max-line-length=") => {
+                    let columns = flag["--max-line-length=".len()..]
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&columns| columns > 0)
+                        .ok_or_else(|| Error::other(format!("invalid line length: {}", flag)))?;
+                    parsed.max_line_length = Some(columns);
+                }
+                flag if flag.starts_with("--") => {
+                    return Err(Error::other(format!("unknown option: {}", flag)));
+                }
+                path => {
+                    if parsed.filepath.is_some() {
+                        return Err(Error::other(format!("unexpected argument: {}", path)));
+                    }
+                    let (path, position) = split_position(path);
+                    parsed.filepath = Some(path.to_string());
+                    parsed.position = position;
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Splits a `grep -n` style location such as `foo.c:128:` or `foo.c:128:5` into the path
+/// and its 1-based line and column. Only trailing all-digit components are split off, so
+/// the path keeps any other `:`, and a bare `foo.c` has no position.
+fn split_position(arg: &str) -> (&str, Option<(usize, usize)>) {
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
+    let number = |s: &str| -> Option<usize> {
+        if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse().ok()
+        } else {
+            None
+        }
+    };
+
+    if let Some((rest, col)) = trimmed.rsplit_once(':') {
+        if let (Some((path, line)), Some(col)) = (rest.rsplit_once(':'), number(col)) {
+            if let (Some(line), false) = (number(line), path.is_empty()) {
+                return (path, Some((line, col)));
+            }
+        }
+        if let (Some(line), false) = (number(col), rest.is_empty()) {
+            return (rest, Some((line, 1)));
+        }
+    }
+    (arg, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_position, Args, ColorChoice};
+    use crate::screen::LineNumbers;
+
+    fn parse(args: &[&str]) -> Result<Args, std::io::Error> {
+        let args: Vec<String> = std::iter::once("kilo-rs")
+            .chain(args.iter().copied())
+            .map(|s| s.to_string())
+            .collect();
+        Args::parse(&args)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Args::default(), parse(&[]).unwrap());
+        assert_eq!(
+            Args {
+                filepath: Some("foo.c".to_string()),
+                position: None,
+                backup: true,
+                rulers: Vec::new(),
+                color: ColorChoice::Auto,
+                line_numbers: LineNumbers::Off,
+                max_line_length: None,
+                smooth_scroll: false,
+                refuse_binary: false,
+            },
+            parse(&["--backup", "foo.c"]).unwrap()
+        );
+        assert_eq!(
+            vec![80, 100],
+            parse(&["--ruler=80", "--ruler=100"]).unwrap().rulers
+        );
+        assert!(parse(&["--ruler=0"]).is_err());
+        assert!(parse(&["--ruler=x"]).is_err());
+        assert_eq!(
+            Some(200),
+            parse(&["--max-line-length=200"]).unwrap().max_line_length
+        );
+        assert!(parse(&["--max-line-length=0"]).is_err());
+        assert!(parse(&["--smooth-scroll"]).unwrap().smooth_scroll);
+        assert!(parse(&["--refuse-binary"]).unwrap().refuse_binary);
+        assert!(parse(&["--unknown"]).is_err());
+        assert!(parse(&["foo.c", "bar.c"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_position() {
+        assert_eq!(
+            Args {
+                filepath: Some("foo.c".to_string()),
+                position: Some((128, 5)),
+                backup: false,
+                rulers: Vec::new(),
+                color: ColorChoice::Auto,
+                line_numbers: LineNumbers::Off,
+                max_line_length: None,
+                smooth_scroll: false,
+                refuse_binary: false,
+            },
+            parse(&["foo.c:128:5"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(ColorChoice::Auto, parse(&[]).unwrap().color);
+        assert_eq!(
+            ColorChoice::Always,
+            parse(&["--color=always"]).unwrap().color
+        );
+        assert_eq!(
+            ColorChoice::Never,
+            parse(&["--color=always", "--color=never"]).unwrap().color
+        );
+        assert!(parse(&["--color=sometimes"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_line_numbers() {
+        assert_eq!(
+            LineNumbers::Absolute,
+            parse(&["--line-numbers"]).unwrap().line_numbers
+        );
+        assert_eq!(
+            LineNumbers::Relative,
+            parse(&["--line-numbers", "--relative-number"])
+                .unwrap()
+                .line_numbers
+        );
+    }
+
+    #[test]
+    fn test_color_choice_enabled() {
+        assert!(ColorChoice::Always.enabled(true, false, true));
+        assert!(!ColorChoice::Never.enabled(false, true, false));
+        assert!(ColorChoice::Auto.enabled(false, true, false));
+        assert!(!ColorChoice::Auto.enabled(true, true, false));
+        assert!(!ColorChoice::Auto.enabled(false, false, false));
+        assert!(!ColorChoice::Auto.enabled(false, true, true));
+    }
+
+    #[test]
+    fn test_split_position() {
+        assert_eq!(("foo.c", Some((128, 1))), split_position("foo.c:128"));
+        assert_eq!(("foo.c", Some((128, 1))), split_position("foo.c:128:"));
+        assert_eq!(("foo.c", Some((128, 5))), split_position("foo.c:128:5"));
+        assert_eq!(("foo.c", Some((128, 5))), split_position("foo.c:128:5:"));
+        assert_eq!(("a:b.c", Some((3, 1))), split_position("a:b.c:3"));
+        assert_eq!(("foo.c", None), split_position("foo.c"));
+        assert_eq!(("foo.c:x", None), split_position("foo.c:x"));
+        assert_eq!((":12", None), split_position(":12"));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::escape_sequence::{
+    ESCAPE_SEQUENCE_CLEAR_SCREEN, ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION,
+};
+use crate::key::{InputSource, Key};
+use crate::pane::Pane;
+use crate::screen::{refresh_screen, render};
+use crate::ui::Terminal;
+use std::io::Error;
+use std::time::{Duration, SystemTime};
+
+const AUTO_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// The editor as a whole: a pane drawn to a terminal and the input it reads keys from.
+pub struct Editor {
+    pane: Pane,
+    input: Box<dyn InputSource>,
+    last_auto_save: SystemTime,
+}
+
+impl Editor {
+    pub fn new(input: Box<dyn InputSource>) -> Editor {
+        Editor {
+            pane: Pane::new("HELP: Ctrl+Q = quit".to_string(), SystemTime::now()),
+            input,
+            last_auto_save: SystemTime::now(),
+        }
+    }
+
+    pub fn pane(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    pub fn set_terminal(&mut self, terminal: Terminal) {
+        self.pane.set_terminal(terminal);
+        self.fit_terminal();
+    }
+
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        self.pane.set_size(0, 0, width, height);
+    }
+
+    fn fit_terminal(&mut self) {
+        let (width, height) = (
+            self.pane.terminal().get_width(),
+            self.pane.terminal().get_height(),
+        );
+        self.set_size(width, height);
+    }
+
+    pub fn open(&mut self, path: String) -> Result<(), Error> {
+        self.pane.open_buffer(path)
+    }
+
+    /// Moves the cursor to the 1-based `line` and `col`, clamped to the buffer.
+    pub fn goto(&mut self, line: usize, col: usize) -> Result<(), Error> {
+        let screen = self.pane.screen();
+        screen.buffer().ensure_loaded(line)?;
+        screen.ensure_visible(col.saturating_sub(1), line.saturating_sub(1));
+        Ok(())
+    }
+
+    /// Runs the command bound to `key`. Returns an error when the editor should exit.
+    pub fn handle_key(&mut self, key: Key) -> Result<(), Error> {
+        self.pane.screen().finish_scroll();
+        let position = self.pane.buffer_position();
+        let offset_y = self.pane.screen().offset().1;
+
+        let command = self.pane.resolve_command(key);
+        self.pane.process_command(&mut *self.input, command)?;
+
+        if self.pane.buffer_position() == position {
+            self.pane.screen().animate_from(offset_y);
+        }
+        Ok(())
+    }
+
+    /// Appends the escape sequences for the next frame to `buf`. Rows unchanged since
+    /// the previous frame are skipped.
+    pub fn render_to(&mut self, buf: &mut String) -> Result<(), Error> {
+        render(&self.pane, buf)
+    }
+
+    /// Runs time-based work: expiring the message bar and auto-saving.
+    pub fn tick(&mut self, now: SystemTime) {
+        self.pane.tick(now);
+
+        if now
+            .duration_since(self.last_auto_save)
+            .is_ok_and(|elapsed| elapsed >= AUTO_SAVE_INTERVAL)
+        {
+            self.pane.write_swap();
+            self.last_auto_save = now;
+        }
+    }
+
+    /// Clears the whole terminal once and draws the first frame over it, so that nothing
+    /// left on the terminal shows through. Later frames only clear the rows they redraw.
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.pane.terminal().write(&format!(
+            "{}{}",
+            ESCAPE_SEQUENCE_CLEAR_SCREEN, ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION
+        ))?;
+        refresh_screen(&mut self.pane)
+    }
+
+    pub fn run(&mut self) -> Result<(), Error> {
+        self.start()?;
+        self.pane.recover_swap(&mut *self.input);
+
+        loop {
+            if self.pane.terminal().update()? {
+                self.fit_terminal();
+            }
+
+            refresh_screen(&mut self.pane)?;
+            let scrolling = self.pane.screen().is_scrolling();
+            let timeout = if scrolling {
+                FRAME_INTERVAL
+            } else {
+                TICK_INTERVAL
+            };
+            if self.input.poll(timeout)? {
+                let key = self.input.next_key()?;
+                if self.handle_key(key).is_err() {
+                    break;
+                }
+            } else if scrolling {
+                self.pane.screen().step_scroll();
+            }
+            self.tick(SystemTime::now());
+        }
+
+        self.pane.remove_swap();
+        self.pane.terminal().write(&format!(
+            "{}{}",
+            ESCAPE_SEQUENCE_CLEAR_SCREEN, ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Editor;
+    use crate::escape_sequence::move_terminal_cursor;
+    use crate::key::Key;
+    use crate::ui::{SharedWriter, Terminal};
+    use std::io;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_start_clears_once() {
+        let writer = SharedWriter::default();
+        let mut editor = Editor::new(Box::new(io::empty()));
+        editor
+            .pane()
+            .set_terminal(Terminal::with_writer(Box::new(writer.clone())));
+        editor.set_size(40, 10);
+
+        editor.start().unwrap();
+        let output = String::from_utf8(writer.0.borrow().clone()).unwrap();
+        assert_eq!(1, output.matches("\x1b[2J").count(), "{:?}", output);
+        assert!(output.starts_with("\x1b[2J\x1b[H"), "{:?}", output);
+        assert!(output.contains("HELP: Ctrl+Q = quit"), "{:?}", output);
+    }
+
+    #[test]
+    fn test_handle_key_and_render() {
+        let mut editor = Editor::new(Box::new(io::empty()));
+        editor.set_size(80, 10);
+
+        for c in "hi".chars() {
+            editor.handle_key(Key::NormalKey(c)).unwrap();
+        }
+        editor.handle_key(Key::Enter).unwrap();
+        editor.handle_key(Key::NormalKey('x')).unwrap();
+
+        let mut buf = String::new();
+        editor.render_to(&mut buf).unwrap();
+        assert!(buf.contains(&format!("{}hi", move_terminal_cursor(0, 0))));
+        assert!(buf.contains(&format!("{}x", move_terminal_cursor(0, 1))));
+        assert!(buf.contains("[No Name]"));
+        assert!(buf.ends_with(&format!("{}\x1b[?25h", move_terminal_cursor(1, 1))));
+
+        assert!(editor.handle_key(Key::ControlSequence('q')).is_ok());
+        assert!(editor.handle_key(Key::ControlSequence('q')).is_ok());
+        assert!(editor.handle_key(Key::ControlSequence('q')).is_ok());
+        assert!(editor.handle_key(Key::ControlSequence('q')).is_err());
+    }
+
+    #[test]
+    fn test_tick_expires_message() {
+        let mut editor = Editor::new(Box::new(io::empty()));
+        editor.set_size(40, 10);
+
+        let mut buf = String::new();
+        editor.render_to(&mut buf).unwrap();
+        assert!(buf.contains("HELP: Ctrl+Q = quit"));
+
+        editor.tick(SystemTime::now() + Duration::from_secs(6));
+        let mut buf = String::new();
+        editor.render_to(&mut buf).unwrap();
+        assert!(!buf.contains("HELP: Ctrl+Q = quit"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::buffer::{Indent, LineEnding};
+use crate::TAB_STOP;
+use std::fs;
+use std::path::Path;
+
+/// The `.editorconfig` properties kilo-rs understands, for one file. `None` leaves the
+/// editor's own behavior alone.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct EditorConfig {
+    /// `Some(true)` for `indent_style = space`, `Some(false)` for `tab`.
+    pub indent_with_spaces: Option<bool>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+    pub end_of_line: Option<LineEnding>,
+}
+
+impl EditorConfig {
+    /// Applies the sections of `text` whose glob matches `file_name` on top of `self`, in
+    /// order, and returns whether the file declares `root = true`. Unknown properties and
+    /// malformed lines are ignored, as the format asks.
+    pub fn apply(&mut self, text: &str, file_name: &str) -> bool {
+        let mut root = false;
+        let mut matched = false;
+        let mut in_preamble = true;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                in_preamble = false;
+                matched = glob_matches(glob, file_name);
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+            let value = value.to_ascii_lowercase();
+            if in_preamble {
+                root |= key == "root" && value == "true";
+            } else if matched {
+                self.set(&key, &value);
+            }
+        }
+        root
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        let flag = match value {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+        match key {
+            "indent_style" => {
+                self.indent_with_spaces = match value {
+                    "space" => Some(true),
+                    "tab" => Some(false),
+                    _ => self.indent_with_spaces,
+                }
+            }
+            "indent_size" => self.indent_size = value.parse().ok().or(self.indent_size),
+            "tab_width" => self.tab_width = value.parse().ok().or(self.tab_width),
+            "trim_trailing_whitespace" => {
+                self.trim_trailing_whitespace = flag.or(self.trim_trailing_whitespace)
+            }
+            "insert_final_newline" => {
+                self.insert_final_newline = flag.or(self.insert_final_newline)
+            }
+            "end_of_line" => self.end_of_line = LineEnding::parse(value).or(self.end_of_line),
+            _ => {}
+        }
+    }
+
+    /// Collects the settings for `path` from the `.editorconfig` files in its directory and
+    /// each parent up to the first with `root = true`, nearer files winning. Files that
+    /// can't be read are skipped, since they shouldn't keep a file from opening.
+    pub fn load_for(path: &Path) -> EditorConfig {
+        let file_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return EditorConfig::default(),
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => Path::new(".").to_path_buf(),
+        };
+        let dir = dir.canonicalize().unwrap_or(dir);
+
+        let mut texts = Vec::new();
+        for ancestor in dir.ancestors() {
+            if let Ok(text) = fs::read_to_string(ancestor.join(".editorconfig")) {
+                let root = EditorConfig::default().apply(&text, &file_name);
+                texts.push(text);
+                if root {
+                    break;
+                }
+            }
+        }
+
+        let mut config = EditorConfig::default();
+        for text in texts.iter().rev() {
+            config.apply(text, &file_name);
+        }
+        config
+    }
+
+    /// The indentation to use, with `indent_size` falling back to `tab_width` for spaces.
+    pub fn indent(&self) -> Option<Indent> {
+        match self.indent_with_spaces? {
+            true => Some(Indent::Spaces(
+                self.indent_size.or(self.tab_width).unwrap_or(TAB_STOP),
+            )),
+            false => Some(Indent::Tabs),
+        }
+    }
+}
+
+/// Matches a section glob against a file name. Supports `*`, `?` and `{a,b}`; since only the
+/// file name is compared, a leading `**/` is dropped and other globs with a `/` never match.
+fn glob_matches(glob: &str, file_name: &str) -> bool {
+    let glob = glob.strip_prefix("**/").unwrap_or(glob);
+    if glob.contains('/') {
+        return false;
+    }
+    expand_braces(glob)
+        .iter()
+        .any(|pattern| wildcard_matches(pattern.as_bytes(), file_name.as_bytes()))
+}
+
+fn expand_braces(glob: &str) -> Vec<String> {
+    let (open, close) = match (glob.find('{'), glob.find('}')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return vec![glob.to_string()],
+    };
+    glob[open + 1..close]
+        .split(',')
+        .flat_map(|choice| {
+            expand_braces(&format!(
+                "{}{}{}",
+                &glob[..open],
+                choice,
+                &glob[close + 1..]
+            ))
+        })
+        .collect()
+}
+
+fn wildcard_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| wildcard_matches(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && wildcard_matches(rest, &name[1..]),
+        Some((&c, rest)) => name.first() == Some(&c) && wildcard_matches(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_matches, EditorConfig};
+    use crate::buffer::{Indent, LineEnding};
+    use std::fs;
+
+    const SAMPLE: &str = "\
+# top-most EditorConfig file
+root = true
+
+[*]
+end_of_line = lf
+insert_final_newline = true
+
+[*.{c,h}]
+indent_style = space
+indent_size = 4
+trim_trailing_whitespace = true
+
+[Makefile]
+indent_style = tab
+end_of_line = CRLF
+";
+
+    #[test]
+    fn test_apply() {
+        let mut config = EditorConfig::default();
+        assert!(config.apply(SAMPLE, "main.c"));
+        assert_eq!(
+            EditorConfig {
+                indent_with_spaces: Some(true),
+                indent_size: Some(4),
+                tab_width: None,
+                trim_trailing_whitespace: Some(true),
+                insert_final_newline: Some(true),
+                end_of_line: Some(LineEnding::Lf),
+            },
+            config
+        );
+        assert_eq!(Some(Indent::Spaces(4)), config.indent());
+
+        let mut config = EditorConfig::default();
+        config.apply(SAMPLE, "Makefile");
+        assert_eq!(Some(Indent::Tabs), config.indent());
+        assert_eq!(Some(LineEnding::Crlf), config.end_of_line);
+        assert_eq!(None, config.trim_trailing_whitespace);
+
+        let mut config = EditorConfig::default();
+        assert!(!config.apply("[*]\nindent_style = space\ntab_width = 2\n", "a.rs"));
+        assert_eq!(Some(Indent::Spaces(2)), config.indent());
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*", "main.c"));
+        assert!(glob_matches("*.c", "main.c"));
+        assert!(!glob_matches("*.c", "main.cc"));
+        assert!(glob_matches("*.{c,h}", "main.h"));
+        assert!(glob_matches("m?in.c", "main.c"));
+        assert!(glob_matches("**/*.c", "main.c"));
+        assert!(!glob_matches("src/*.c", "main.c"));
+        assert!(glob_matches("Makefile", "Makefile"));
+    }
+
+    #[test]
+    fn test_load_for() {
+        let dir = std::env::temp_dir().join(format!("kilo-rs-editorconfig-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join(".editorconfig"), SAMPLE).unwrap();
+        fs::write(dir.join("src/.editorconfig"), "[*.c]\nindent_size = 2\n").unwrap();
+
+        let config = EditorConfig::load_for(&dir.join("src/main.c"));
+        assert_eq!(Some(Indent::Spaces(2)), config.indent());
+        assert_eq!(Some(true), config.insert_final_newline);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,13 +3,19 @@ pub const ESCAPE_SEQUENCE_CLEAR_LINE: &str = "\x1b[K";
 pub const ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION: &str = "\x1b[H";
 pub const ESCAPE_SEQUENCE_STYLE_RESET: &str = "\x1b[m";
 pub const ESCAPE_SEQUENCE_STYLE_REVERSE: &str = "\x1b[7m";
+pub const ESCAPE_SEQUENCE_STYLE_NO_REVERSE: &str = "\x1b[27m";
+pub const ESCAPE_SEQUENCE_STYLE_DIM: &str = "\x1b[2m";
+pub const ESCAPE_SEQUENCE_STYLE_NORMAL_INTENSITY: &str = "\x1b[22m";
 pub const ESCAPE_SEQUENCE_HIDE_CURSOR: &str = "\x1b[?25l";
 pub const ESCAPE_SEQUENCE_SHOW_CURSOR: &str = "\x1b[?25h";
 
+/// Moves the cursor to the 0-based `(x, y)`; the terminal itself counts from 1.
 pub fn move_terminal_cursor(x: usize, y: usize) -> String {
     format!("\x1b[{};{}H", y + 1, x + 1)
 }
 
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Color {
     Black,
     Red,
@@ -20,11 +26,13 @@ pub enum Color {
     Cyan,
     White,
     Default,
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
 }
 
 impl Color {
-    pub fn foreground_escape_sequence(&self) -> &'static str {
-        match self {
+    pub fn foreground_escape_sequence(&self) -> String {
+        let seq = match self {
             Color::Black => "\x1b[30m",
             Color::Red => "\x1b[31m",
             Color::Green => "\x1b[32m",
@@ -34,11 +42,14 @@ impl Color {
             Color::Cyan => "\x1b[36m",
             Color::White => "\x1b[37m",
             Color::Default => "\x1b[39m",
-        }
+            Color::Ansi256(n) => return format!("\x1b[38;5;{}m", n),
+            Color::Rgb(r, g, b) => return format!("\x1b[38;2;{};{};{}m", r, g, b),
+        };
+        seq.to_string()
     }
 
-    pub fn background_escape_sequence(&self) -> &'static str {
-        match self {
+    pub fn background_escape_sequence(&self) -> String {
+        let seq = match self {
             Color::Black => "\x1b[40m",
             Color::Red => "\x1b[41m",
             Color::Green => "\x1b[42m",
@@ -48,6 +59,42 @@ impl Color {
             Color::Cyan => "\x1b[46m",
             Color::White => "\x1b[47m",
             Color::Default => "\x1b[49m",
-        }
+            Color::Ansi256(n) => return format!("\x1b[48;5;{}m", n),
+            Color::Rgb(r, g, b) => return format!("\x1b[48;2;{};{};{}m", r, g, b),
+        };
+        seq.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{move_terminal_cursor, Color};
+
+    #[test]
+    fn test_move_terminal_cursor() {
+        assert_eq!("\x1b[1;1H", move_terminal_cursor(0, 0));
+        assert_eq!("\x1b[3;8H", move_terminal_cursor(7, 2));
+    }
+
+    #[test]
+    fn test_color_escape_sequence() {
+        assert_eq!("\x1b[31m", Color::Red.foreground_escape_sequence());
+        assert_eq!("\x1b[41m", Color::Red.background_escape_sequence());
+        assert_eq!(
+            "\x1b[38;5;208m",
+            Color::Ansi256(208).foreground_escape_sequence()
+        );
+        assert_eq!(
+            "\x1b[48;5;208m",
+            Color::Ansi256(208).background_escape_sequence()
+        );
+        assert_eq!(
+            "\x1b[38;2;255;136;0m",
+            Color::Rgb(255, 136, 0).foreground_escape_sequence()
+        );
+        assert_eq!(
+            "\x1b[48;2;255;136;0m",
+            Color::Rgb(255, 136, 0).background_escape_sequence()
+        );
     }
 }
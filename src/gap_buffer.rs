@@ -0,0 +1,217 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+const MIN_GAP: usize = 16;
+
+/// A sequence with a movable gap, so that inserting or removing near the previous edit
+/// position only shifts the elements between the old and new positions.
+pub struct GapBuffer<T> {
+    buf: Vec<Option<T>>,
+    gap_start: usize,
+    gap_end: usize,
+}
+
+impl<T> GapBuffer<T> {
+    pub fn new() -> GapBuffer<T> {
+        GapBuffer {
+            buf: Vec::new(),
+            gap_start: 0,
+            gap_end: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len() - (self.gap_end - self.gap_start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn physical_index(&self, index: usize) -> usize {
+        if index < self.gap_start {
+            index
+        } else {
+            index + (self.gap_end - self.gap_start)
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        self.buf[self.physical_index(index)].as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len() {
+            return None;
+        }
+        let i = self.physical_index(index);
+        self.buf[i].as_mut()
+    }
+
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.len(), "insertion index out of bounds");
+        self.move_gap(index);
+        if self.gap_start == self.gap_end {
+            self.grow();
+        }
+        self.buf[self.gap_start] = Some(value);
+        self.gap_start += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "removal index out of bounds");
+        self.move_gap(index);
+        let value = self.buf[self.gap_end].take();
+        self.gap_end += 1;
+        value.expect("element outside the gap")
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.buf[..self.gap_start]
+            .iter()
+            .chain(self.buf[self.gap_end..].iter())
+            .map(|v| v.as_ref().expect("element outside the gap"))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        let (before, rest) = self.buf.split_at_mut(self.gap_start);
+        let after = &mut rest[self.gap_end - self.gap_start..];
+        before
+            .iter_mut()
+            .chain(after.iter_mut())
+            .map(|v| v.as_mut().expect("element outside the gap"))
+    }
+
+    fn move_gap(&mut self, index: usize) {
+        while index < self.gap_start {
+            self.gap_start -= 1;
+            self.gap_end -= 1;
+            self.buf.swap(self.gap_start, self.gap_end);
+        }
+        while index > self.gap_start {
+            self.buf.swap(self.gap_start, self.gap_end);
+            self.gap_start += 1;
+            self.gap_end += 1;
+        }
+    }
+
+    fn grow(&mut self) {
+        let extra = self.buf.len().max(MIN_GAP);
+        let tail = self.buf.split_off(self.gap_end);
+        self.buf.extend((0..extra).map(|_| None));
+        self.gap_end = self.gap_start + extra;
+        self.buf.extend(tail);
+    }
+}
+
+impl<T> Default for GapBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<Vec<T>> for GapBuffer<T> {
+    fn from(values: Vec<T>) -> Self {
+        let len = values.len();
+        GapBuffer {
+            buf: values.into_iter().map(Some).collect(),
+            gap_start: len,
+            gap_end: len,
+        }
+    }
+}
+
+impl<T> Index<usize> for GapBuffer<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for GapBuffer<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut GapBuffer<T> {
+    type Item = &'a mut T;
+    type IntoIter = Box<dyn Iterator<Item = &'a mut T> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter_mut())
+    }
+}
+
+impl<T: PartialEq> PartialEq for GapBuffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for GapBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GapBuffer;
+
+    #[test]
+    fn test_random_edits_match_vec() {
+        let mut gap_buffer = GapBuffer::new();
+        let mut vec = Vec::new();
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |n: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as usize % n
+        };
+
+        for i in 0..5000 {
+            match next(4) {
+                0 | 1 => {
+                    let index = next(vec.len() + 1);
+                    gap_buffer.insert(index, i);
+                    vec.insert(index, i);
+                }
+                2 if !vec.is_empty() => {
+                    let index = next(vec.len());
+                    assert_eq!(vec.remove(index), gap_buffer.remove(index));
+                }
+                _ if !vec.is_empty() => {
+                    let index = next(vec.len());
+                    gap_buffer[index] += 1;
+                    vec[index] += 1;
+                }
+                _ => {}
+            }
+            assert_eq!(vec.len(), gap_buffer.len());
+        }
+
+        assert_eq!(vec, gap_buffer.iter().copied().collect::<Vec<_>>());
+        for (i, v) in vec.iter().enumerate() {
+            assert_eq!(Some(v), gap_buffer.get(i));
+        }
+        assert_eq!(None, gap_buffer.get(vec.len()));
+        assert_eq!(GapBuffer::from(vec), gap_buffer);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut gap_buffer = GapBuffer::from(vec![1, 2, 3]);
+        gap_buffer.insert(1, 10);
+        for v in &mut gap_buffer {
+            *v *= 2;
+        }
+        assert_eq!(
+            vec![2, 20, 4, 6],
+            gap_buffer.iter().copied().collect::<Vec<_>>()
+        );
+    }
+}
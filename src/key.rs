@@ -1,6 +1,11 @@
-use std::io::{Error, Read};
+use std::collections::VecDeque;
+use std::io::{stdin, Error, ErrorKind, Read};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Key {
     ArrowLeft,
     ArrowRight,
@@ -8,24 +13,123 @@ pub enum Key {
     ArrowDown,
     PageUp,
     PageDown,
+    AltArrowUp,
+    AltArrowDown,
+    ShiftArrowLeft,
+    ShiftArrowRight,
+    BackTab,
+    CtrlPageUp,
+    CtrlPageDown,
     Home,
     End,
     Enter,
     Delete,
     Backspace,
     Escape,
+    AltKey(char),
     ControlSequence(char),
     NormalKey(char),
 }
 
+/// A source of key presses, such as the terminal or a scripted sequence in tests.
+pub trait InputSource {
+    fn next_key(&mut self) -> Result<Key, Error>;
+
+    /// Waits up to `timeout` for a key, returning whether `next_key` would not block.
+    fn poll(&mut self, _timeout: Duration) -> Result<bool, Error> {
+        Ok(true)
+    }
+}
+
+impl<R: Read> InputSource for R {
+    fn next_key(&mut self) -> Result<Key, Error> {
+        read_key(self)
+    }
+}
+
+/// Replays a fixed sequence of keys, then reports end of input.
+#[derive(Debug, Default)]
+pub struct ScriptedInput {
+    keys: VecDeque<Key>,
+}
+
+impl From<Vec<Key>> for ScriptedInput {
+    fn from(keys: Vec<Key>) -> Self {
+        ScriptedInput { keys: keys.into() }
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn next_key(&mut self) -> Result<Key, Error> {
+        self.keys
+            .pop_front()
+            .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))
+    }
+}
+
+/// Reads keys from stdin on a background thread so that they can be polled with a timeout.
+/// The thread is started on first use, once the terminal is in raw mode.
+#[derive(Debug, Default)]
+pub struct TerminalInput {
+    keys: Option<Receiver<Result<Key, Error>>>,
+    pending: Option<Result<Key, Error>>,
+}
+
+impl TerminalInput {
+    pub fn new() -> TerminalInput {
+        TerminalInput::default()
+    }
+
+    fn keys(&mut self) -> &Receiver<Result<Key, Error>> {
+        self.keys.get_or_insert_with(|| {
+            let (sender, receiver) = channel();
+            thread::spawn(move || {
+                let mut stdin = stdin();
+                loop {
+                    let key = read_key(&mut stdin);
+                    let failed = key.is_err();
+                    if sender.send(key).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+            receiver
+        })
+    }
+}
+
+impl InputSource for TerminalInput {
+    fn next_key(&mut self) -> Result<Key, Error> {
+        match self.pending.take() {
+            Some(key) => key,
+            None => self
+                .keys()
+                .recv()
+                .map_err(|_| Error::from(ErrorKind::UnexpectedEof))?,
+        }
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<bool, Error> {
+        if self.pending.is_some() {
+            return Ok(true);
+        }
+        match self.keys().recv_timeout(timeout) {
+            Ok(key) => {
+                self.pending = Some(key);
+                Ok(true)
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(false),
+            Err(RecvTimeoutError::Disconnected) => Ok(true),
+        }
+    }
+}
+
 fn read_char(reader: &mut dyn Read) -> Result<char, Error> {
     let mut buf = [0u8; 1];
 
-    loop {
-        match reader.read(&mut buf)? {
-            0 => continue,
-            _ => return Ok(buf[0] as char),
-        }
+    match reader.read(&mut buf)? {
+        0 => Err(Error::from(ErrorKind::UnexpectedEof)),
+        _ => Ok(buf[0] as char),
     }
 }
 
@@ -43,6 +147,13 @@ pub fn read_key(reader: &mut dyn Read) -> Result<Key, Error> {
         ("\x1b[4~", Key::End),
         ("\x1b[5~", Key::PageUp),
         ("\x1b[6~", Key::PageDown),
+        ("\x1b[1;3A", Key::AltArrowUp),
+        ("\x1b[1;3B", Key::AltArrowDown),
+        ("\x1b[1;2D", Key::ShiftArrowLeft),
+        ("\x1b[1;2C", Key::ShiftArrowRight),
+        ("\x1b[Z", Key::BackTab),
+        ("\x1b[5;5~", Key::CtrlPageUp),
+        ("\x1b[6;5~", Key::CtrlPageDown),
         ("\x1b[7~", Key::Home),
         ("\x1b[8~", Key::End),
         ("\x1bOH", Key::Home),
@@ -52,10 +163,14 @@ pub fn read_key(reader: &mut dyn Read) -> Result<Key, Error> {
     match c {
         '\r' => Ok(Key::Enter),
         '\x01'..'\x1b' => Ok(Key::ControlSequence(((c as u8) + b'a' - 1) as char)),
+        '\x00' | '\x1c'..='\x1f' => Ok(Key::ControlSequence(((c as u8) + b'@') as char)),
         '\x1b' => {
             let mut buf = String::from("\x1b");
             loop {
                 let c2 = read_char(reader)?;
+                if buf.len() == 1 && c2 != '[' && c2 != 'O' && !c2.is_ascii_control() {
+                    return Ok(Key::AltKey(c2));
+                }
                 buf.push(c2);
 
                 let matches = escape_sequence_table
@@ -77,9 +192,9 @@ pub fn read_key(reader: &mut dyn Read) -> Result<Key, Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::Key;
+    use super::{InputSource, Key, ScriptedInput};
     use crate::key::read_key;
-    use std::io::BufReader;
+    use std::io::{BufReader, ErrorKind};
 
     fn assert_read_editor_key(input: &str, expected: Key) {
         let data = input.bytes().collect::<Vec<u8>>();
@@ -102,6 +217,13 @@ mod tests {
         assert_read_editor_key("\x1b[4~", Key::End);
         assert_read_editor_key("\x1b[5~", Key::PageUp);
         assert_read_editor_key("\x1b[6~", Key::PageDown);
+        assert_read_editor_key("\x1b[1;3A", Key::AltArrowUp);
+        assert_read_editor_key("\x1b[1;3B", Key::AltArrowDown);
+        assert_read_editor_key("\x1b[1;2D", Key::ShiftArrowLeft);
+        assert_read_editor_key("\x1b[1;2C", Key::ShiftArrowRight);
+        assert_read_editor_key("\x1b[Z", Key::BackTab);
+        assert_read_editor_key("\x1b[5;5~", Key::CtrlPageUp);
+        assert_read_editor_key("\x1b[6;5~", Key::CtrlPageDown);
         assert_read_editor_key("\x1b[7~", Key::Home);
         assert_read_editor_key("\x1b[8~", Key::End);
 
@@ -117,5 +239,29 @@ mod tests {
         assert_read_editor_key("~", Key::NormalKey('~'));
         assert_read_editor_key("\x01", Key::ControlSequence('a'));
         assert_read_editor_key("\x1a", Key::ControlSequence('z'));
+        assert_read_editor_key("\x00", Key::ControlSequence('@'));
+        assert_read_editor_key("\x1c", Key::ControlSequence('\\'));
+        assert_read_editor_key("\x1d", Key::ControlSequence(']'));
+        assert_read_editor_key("\x1by", Key::AltKey('y'));
+    }
+
+    #[test]
+    fn test_read_key_eof() {
+        let mut reader = BufReader::new("".as_bytes());
+        let err = read_key(&mut reader).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+
+        let mut reader = BufReader::new("\x1b[".as_bytes());
+        let err = read_key(&mut reader).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn test_scripted_input() {
+        let mut input = ScriptedInput::from(vec![Key::NormalKey('a'), Key::Enter]);
+        assert_eq!(Key::NormalKey('a'), input.next_key().unwrap());
+        assert_eq!(Key::Enter, input.next_key().unwrap());
+        let err = input.next_key().unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
     }
 }
@@ -0,0 +1,265 @@
+use crate::key::Key;
+use crate::pane::Command;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<Key, Command>,
+}
+
+impl KeyMap {
+    /// Returns the command bound to `key`. Unbound normal keys insert themselves.
+    pub fn resolve(&self, key: Key) -> Command {
+        match self.bindings.get(&key) {
+            Some(command) => *command,
+            None => match key {
+                Key::NormalKey(c) => Command::Input(c),
+                _ => Command::Noop,
+            },
+        }
+    }
+
+    pub fn bind(&mut self, key: Key, command: Command) {
+        self.bindings.insert(key, command);
+    }
+
+    /// Parses `key = command` lines on top of the default bindings, e.g. `ctrl-w = exit`.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> Result<KeyMap, Error> {
+        let mut keymap = KeyMap::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || Error::other(format!("invalid keymap line {}: {}", i + 1, line));
+            let (key, command) = line.split_once('=').ok_or_else(invalid)?;
+            let key = parse_key(key.trim()).ok_or_else(invalid)?;
+            let command = parse_command(command.trim()).ok_or_else(invalid)?;
+            keymap.bind(key, command);
+        }
+        Ok(keymap)
+    }
+
+    pub fn load_file(path: &str) -> Result<KeyMap, Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => KeyMap::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(KeyMap::default()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let bindings = HashMap::from([
+            (Key::ControlSequence('@'), Command::StartSelection),
+            (Key::ControlSequence('a'), Command::Home),
+            (Key::ControlSequence('c'), Command::Copy),
+            (Key::ControlSequence('d'), Command::DuplicateLine),
+            (Key::ControlSequence('e'), Command::End),
+            (Key::ControlSequence('f'), Command::Find),
+            (Key::ControlSequence('h'), Command::Backspace),
+            (Key::ControlSequence('i'), Command::Indent),
+            (Key::ControlSequence('k'), Command::DeleteToEol),
+            (Key::ControlSequence('m'), Command::Enter),
+            (Key::ControlSequence('o'), Command::Open),
+            (Key::ControlSequence('q'), Command::Exit),
+            (Key::ControlSequence('r'), Command::Revert),
+            (Key::ControlSequence('s'), Command::Save),
+            (Key::ControlSequence('t'), Command::ToggleWhitespace),
+            (Key::ControlSequence('v'), Command::Paste),
+            (Key::ControlSequence('w'), Command::DeleteWord),
+            (Key::ControlSequence('x'), Command::Cut),
+            (Key::ControlSequence('y'), Command::Yank),
+            (Key::AltKey('y'), Command::YankPop),
+            (Key::AltKey('q'), Command::ForceQuit),
+            (Key::AltKey('x'), Command::SaveAndQuit),
+            (Key::AltKey('o'), Command::OpenLineBelow),
+            (Key::AltKey('u'), Command::InsertUnicode),
+            (Key::AltKey('n'), Command::AddCursorBelow),
+            (Key::AltKey('p'), Command::AddCursorAbove),
+            (Key::AltKey('b'), Command::StartBlockSelection),
+            (Key::AltKey('h'), Command::ToggleHex),
+            (Key::AltKey('a'), Command::OpenLineAbove),
+            (Key::AltKey('s'), Command::SaveAs),
+            (Key::AltKey('S'), Command::AppendSave),
+            (Key::AltKey('l'), Command::NormalizeLineEndings),
+            (Key::AltKey('B'), Command::StripBom),
+            (Key::AltKey('i'), Command::CheckIndent),
+            (Key::AltKey('c'), Command::ToggleHeaderSource),
+            (Key::AltKey('v'), Command::ToggleScrollbar),
+            (Key::AltKey('m'), Command::ToggleMinimap),
+            (Key::ControlSequence(']'), Command::MatchBracket),
+            (Key::ControlSequence('\\'), Command::SplitVertical),
+            (Key::ControlSequence('_'), Command::FocusNextPane),
+            (Key::BackTab, Command::Dedent),
+            (Key::AltArrowUp, Command::MoveLineUp),
+            (Key::AltArrowDown, Command::MoveLineDown),
+            (Key::ShiftArrowLeft, Command::ScrollLeft),
+            (Key::ShiftArrowRight, Command::ScrollRight),
+            (Key::CtrlPageUp, Command::PrevBuffer),
+            (Key::CtrlPageDown, Command::NextBuffer),
+            (Key::ArrowLeft, Command::ArrowLeft),
+            (Key::ArrowRight, Command::ArrowRight),
+            (Key::ArrowUp, Command::ArrowUp),
+            (Key::ArrowDown, Command::ArrowDown),
+            (Key::PageUp, Command::PageUp),
+            (Key::PageDown, Command::PageDown),
+            (Key::Home, Command::Home),
+            (Key::End, Command::End),
+            (Key::Enter, Command::Enter),
+            (Key::Delete, Command::Delete),
+            (Key::Backspace, Command::Backspace),
+            (Key::Escape, Command::Escape),
+        ]);
+        KeyMap { bindings }
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    if let Some(c) = name.strip_prefix("ctrl-") {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => {
+                Some(Key::ControlSequence(c.to_ascii_lowercase()))
+            }
+            (Some(c @ ('@' | '\\' | ']' | '^' | '_')), None) => Some(Key::ControlSequence(c)),
+            _ if c == "pageup" => Some(Key::CtrlPageUp),
+            _ if c == "pagedown" => Some(Key::CtrlPageDown),
+            _ => None,
+        };
+    }
+    match name {
+        "left" => Some(Key::ArrowLeft),
+        "right" => Some(Key::ArrowRight),
+        "up" => Some(Key::ArrowUp),
+        "down" => Some(Key::ArrowDown),
+        "alt-up" => Some(Key::AltArrowUp),
+        "alt-down" => Some(Key::AltArrowDown),
+        "shift-left" => Some(Key::ShiftArrowLeft),
+        "shift-right" => Some(Key::ShiftArrowRight),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "enter" => Some(Key::Enter),
+        "delete" => Some(Key::Delete),
+        "backspace" => Some(Key::Backspace),
+        "escape" => Some(Key::Escape),
+        "shift-tab" => Some(Key::BackTab),
+        _ => {
+            let mut chars = name.strip_prefix("alt-")?.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii_graphic() => Some(Key::AltKey(c)),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn parse_command(name: &str) -> Option<Command> {
+    match name {
+        "exit" => Some(Command::Exit),
+        "force_quit" => Some(Command::ForceQuit),
+        "save" => Some(Command::Save),
+        "save_and_quit" => Some(Command::SaveAndQuit),
+        "save_as" => Some(Command::SaveAs),
+        "append_save" => Some(Command::AppendSave),
+        "normalize_line_endings" => Some(Command::NormalizeLineEndings),
+        "strip_bom" => Some(Command::StripBom),
+        "check_indent" => Some(Command::CheckIndent),
+        "toggle_header_source" => Some(Command::ToggleHeaderSource),
+        "open" => Some(Command::Open),
+        "insert_unicode" => Some(Command::InsertUnicode),
+        "revert" => Some(Command::Revert),
+        "find" => Some(Command::Find),
+        "match_bracket" => Some(Command::MatchBracket),
+        "toggle_whitespace" => Some(Command::ToggleWhitespace),
+        "toggle_scrollbar" => Some(Command::ToggleScrollbar),
+        "toggle_minimap" => Some(Command::ToggleMinimap),
+        "toggle_hex" => Some(Command::ToggleHex),
+        "next_buffer" => Some(Command::NextBuffer),
+        "prev_buffer" => Some(Command::PrevBuffer),
+        "split_vertical" => Some(Command::SplitVertical),
+        "focus_next_pane" => Some(Command::FocusNextPane),
+        "left" => Some(Command::ArrowLeft),
+        "right" => Some(Command::ArrowRight),
+        "up" => Some(Command::ArrowUp),
+        "down" => Some(Command::ArrowDown),
+        "page_up" => Some(Command::PageUp),
+        "page_down" => Some(Command::PageDown),
+        "scroll_left" => Some(Command::ScrollLeft),
+        "scroll_right" => Some(Command::ScrollRight),
+        "home" => Some(Command::Home),
+        "end" => Some(Command::End),
+        "enter" => Some(Command::Enter),
+        "duplicate_line" => Some(Command::DuplicateLine),
+        "open_line_below" => Some(Command::OpenLineBelow),
+        "open_line_above" => Some(Command::OpenLineAbove),
+        "add_cursor_below" => Some(Command::AddCursorBelow),
+        "add_cursor_above" => Some(Command::AddCursorAbove),
+        "move_line_up" => Some(Command::MoveLineUp),
+        "move_line_down" => Some(Command::MoveLineDown),
+        "delete" => Some(Command::Delete),
+        "backspace" => Some(Command::Backspace),
+        "delete_to_eol" => Some(Command::DeleteToEol),
+        "delete_word" => Some(Command::DeleteWord),
+        "indent" => Some(Command::Indent),
+        "dedent" => Some(Command::Dedent),
+        "start_selection" => Some(Command::StartSelection),
+        "start_block_selection" => Some(Command::StartBlockSelection),
+        "copy" => Some(Command::Copy),
+        "cut" => Some(Command::Cut),
+        "paste" => Some(Command::Paste),
+        "yank" => Some(Command::Yank),
+        "yank_pop" => Some(Command::YankPop),
+        "escape" => Some(Command::Escape),
+        "noop" => Some(Command::Noop),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyMap;
+    use crate::key::Key;
+    use crate::pane::Command;
+
+    #[test]
+    fn test_resolve_default() {
+        let keymap = KeyMap::default();
+        assert_eq!(Command::Exit, keymap.resolve(Key::ControlSequence('q')));
+        assert_eq!(Command::ArrowUp, keymap.resolve(Key::ArrowUp));
+        assert_eq!(Command::Input('a'), keymap.resolve(Key::NormalKey('a')));
+        assert_eq!(Command::Noop, keymap.resolve(Key::ControlSequence('g')));
+        assert_eq!(Command::SaveAs, keymap.resolve(Key::AltKey('s')));
+        assert_eq!(Command::AppendSave, keymap.resolve(Key::AltKey('S')));
+        assert_eq!(Command::ToggleMinimap, keymap.resolve(Key::AltKey('m')));
+    }
+
+    #[test]
+    fn test_parse() {
+        let keymap =
+            KeyMap::parse("# comment\nctrl-w = exit\n\nctrl-q=noop\nshift-tab = indent\n").unwrap();
+        assert_eq!(Command::Exit, keymap.resolve(Key::ControlSequence('w')));
+        assert_eq!(Command::Noop, keymap.resolve(Key::ControlSequence('q')));
+        assert_eq!(Command::Indent, keymap.resolve(Key::BackTab));
+        assert_eq!(Command::Save, keymap.resolve(Key::ControlSequence('s')));
+
+        let keymap = KeyMap::parse("alt-p = yank_pop").unwrap();
+        assert_eq!(Command::YankPop, keymap.resolve(Key::AltKey('p')));
+        assert_eq!(Command::YankPop, keymap.resolve(Key::AltKey('y')));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(KeyMap::parse("ctrl-w exit").is_err());
+        assert!(KeyMap::parse("ctrl-ww = exit").is_err());
+        assert!(KeyMap::parse("hyper-w = exit").is_err());
+        assert!(KeyMap::parse("alt-yy = exit").is_err());
+        assert!(KeyMap::parse("ctrl-w = explode").is_err());
+    }
+}
@@ -0,0 +1,27 @@
+pub mod buffer;
+pub mod cli;
+pub mod editor;
+pub mod editorconfig;
+pub mod escape_sequence;
+pub mod gap_buffer;
+pub mod key;
+pub mod keymap;
+pub mod message_bar;
+pub mod pane;
+pub mod screen;
+pub mod snippet;
+pub mod status_bar;
+pub mod theme;
+pub mod ui;
+
+pub use crate::editor::Editor;
+
+const KILO_VERSION: &str = "0.1.0";
+const TAB_STOP: usize = 8;
+const QUIT_TIMES: usize = 3;
+const LOAD_CHUNK_LINES: usize = 1000;
+const KILL_RING_SIZE: usize = 16;
+const BACKGROUND_HIGHLIGHT_BYTES: u64 = 1 << 20;
+const BACKGROUND_HIGHLIGHT_LINES: usize = 1000;
+const SCROLL_COLUMNS: usize = 8;
+const MINIMAP_COLUMNS: usize = 2;
@@ -1,26 +1,12 @@
-mod buffer;
-mod escape_sequence;
-mod key;
-mod message_bar;
-mod pane;
-mod screen;
-mod status_bar;
-mod ui;
-
-use crate::escape_sequence::{
-    ESCAPE_SEQUENCE_CLEAR_SCREEN, ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION,
-};
-use crate::key::read_key;
-use crate::pane::Pane;
-use crate::screen::refresh_screen;
-use crate::ui::Terminal;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use std::io::{stdin, stdout, Error, Write};
-use std::time::SystemTime;
-
-const KILO_VERSION: &str = "0.1.0";
-const TAB_STOP: usize = 8;
-const QUIT_TIMES: usize = 3;
+use kilo_rs::cli::Args;
+use kilo_rs::key::TerminalInput;
+use kilo_rs::keymap::KeyMap;
+use kilo_rs::snippet::Snippets;
+use kilo_rs::theme::{Background, Theme};
+use kilo_rs::ui::Terminal;
+use kilo_rs::Editor;
+use std::io::{stdout, Error, IsTerminal};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
@@ -31,40 +17,128 @@ fn main() {
 }
 
 fn run(args: Vec<String>) -> Result<(), Error> {
-    let mut stdin = stdin();
-    let mut pane = Pane::new("HELP: Ctrl+Q = quit".to_string(), SystemTime::now());
-    let mut terminal = Terminal::new()?;
+    let args = Args::parse(&args)?;
+    let mut editor = Editor::new(Box::new(TerminalInput::new()));
+    editor.set_terminal(Terminal::new()?);
 
-    pane.set_size(0, 0, terminal.get_width(), terminal.get_height());
+    let background = Background::detect();
+    let mut theme = Theme::for_background(background);
+    // A broken config file shouldn't keep the editor from starting: fall back to the
+    // defaults and say what went wrong in the message bar.
+    let mut config_errors = Vec::new();
+    if let Ok(home) = std::env::var("HOME") {
+        let config = |name: &str| format!("{}/.config/kilo-rs/{}", home, name);
+        match Theme::load_file(&config("theme"), background) {
+            Ok(loaded) => theme = loaded,
+            Err(err) => config_errors.push(format!("theme: {}", err)),
+        }
+        match KeyMap::load_file(&config("keymap")) {
+            Ok(keymap) => editor.pane().set_keymap(keymap),
+            Err(err) => config_errors.push(format!("keymap: {}", err)),
+        }
+        match Snippets::load_file(&config("snippets")) {
+            Ok(snippets) => editor.pane().set_snippets(snippets),
+            Err(err) => config_errors.push(format!("snippets: {}", err)),
+        }
+    }
+    if !config_errors.is_empty() {
+        let message = format!(
+            "Config error, using defaults for {}",
+            config_errors.join("; ")
+        );
+        editor.pane().set_message(message);
+    }
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let dumb_terminal = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+    let color_enabled = args
+        .color
+        .enabled(no_color, stdout().is_terminal(), dumb_terminal);
+    theme.set_color_enabled(color_enabled);
+    editor.pane().screen().set_theme(theme);
 
-    if args.len() > 1 {
-        pane.screen()
-            .buffer()
-            .load_file(args.get(1).unwrap().to_string())?;
+    editor
+        .pane()
+        .screen()
+        .buffer()
+        .set_backup_on_save(args.backup);
+    editor
+        .pane()
+        .screen()
+        .buffer()
+        .set_refuse_binary(args.refuse_binary);
+    editor.pane().screen().set_rulers(args.rulers);
+    editor.pane().screen().set_line_numbers(args.line_numbers);
+    editor
+        .pane()
+        .screen()
+        .set_max_line_length(args.max_line_length);
+    editor.pane().screen().set_smooth_scroll(args.smooth_scroll);
+    if let Some(filepath) = args.filepath {
+        editor.open(filepath)?;
+        if let Some((line, col)) = args.position {
+            editor.goto(line, col)?;
+        }
     }
 
+    // crossterm's raw mode goes through cfmakeraw, which also clears IXON, so Ctrl+S and
+    // Ctrl+Q reach the editor instead of pausing and resuming terminal output.
     enable_raw_mode()?;
+    let ret = editor.run();
+    disable_raw_mode()?;
 
-    loop {
-        if terminal.update()? {
-            pane.set_size(0, 0, terminal.get_width(), terminal.get_height());
-        }
+    ret
+}
 
-        refresh_screen(&mut pane)?;
-        let key = read_key(&mut stdin)?;
-        let command = pane.resolve_command(key);
-        match pane.process_command(&mut stdin, command) {
-            Err(_) => break,
-            Ok(_) => continue,
-        }
+#[cfg(all(test, unix))]
+mod tests {
+    use crossterm::terminal::enable_raw_mode;
+    use std::env;
+    use std::fs::File;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::process::{Command, Stdio};
+    use std::ptr;
+
+    const RAW_MODE_CHILD: &str = "KILO_RS_RAW_MODE_CHILD";
+
+    fn ixon(file: &File) -> bool {
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        assert_eq!(0, unsafe {
+            libc::tcgetattr(file.as_raw_fd(), &mut termios)
+        });
+        termios.c_iflag & libc::IXON != 0
     }
 
-    print!(
-        "{}{}",
-        ESCAPE_SEQUENCE_CLEAR_SCREEN, ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION
-    );
-    stdout().flush()?;
-    disable_raw_mode()?;
+    // crossterm puts stdin in raw mode when it is a terminal, so the test runs itself again
+    // with a pty as stdin, and checks the pty once that child has exited.
+    #[test]
+    fn test_raw_mode_clears_ixon() {
+        if env::var_os(RAW_MODE_CHILD).is_some() {
+            enable_raw_mode().unwrap();
+            return;
+        }
 
-    Ok(())
+        let (mut master, mut slave) = (0, 0);
+        let ret = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(0, ret);
+        let (_master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+        assert!(ixon(&slave));
+
+        let status = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "tests::test_raw_mode_clears_ixon"])
+            .env(RAW_MODE_CHILD, "1")
+            .stdin(slave.try_clone().unwrap())
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(!ixon(&slave));
+    }
 }
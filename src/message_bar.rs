@@ -30,9 +30,20 @@ impl MessageBar {
             .map_or(None, |b| if b { Some(self.message.clone()) } else { None })
     }
 
+    /// Drops the message once it has expired, so that it is gone on the next redraw.
+    pub fn tick(&mut self, now: SystemTime) {
+        if self.get_message(now).is_none() {
+            self.message.clear();
+        }
+    }
+
     pub fn set_size(&mut self, x: usize, y: usize, width: usize, height: usize) {
         self.component.set_size(x, y, width, height);
     }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.component.contains(x, y)
+    }
 }
 
 impl Drawable for MessageBar {
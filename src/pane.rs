@@ -1,95 +1,458 @@
-use crate::buffer::Highlight;
-use crate::key::{read_key, Key};
+use crate::buffer::{find_swap, swap_path, Highlight, LineEnding, SearchOptions};
+use crate::escape_sequence::move_terminal_cursor;
+use crate::key::{InputSource, Key, ScriptedInput};
+use crate::keymap::KeyMap;
 use crate::message_bar::MessageBar;
 use crate::screen::{refresh_screen, Screen};
+use crate::snippet::Snippets;
 use crate::status_bar::StatusBar;
-use crate::ui::{Component, Drawable};
-use crate::QUIT_TIMES;
-use std::io::{Error, Read};
+use crate::ui::{Component, Drawable, Terminal};
+use crate::{KILL_RING_SIZE, LOAD_CHUNK_LINES, QUIT_TIMES};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Error;
+use std::path::Path;
 use std::time::SystemTime;
 
+/// The part of a pane under a terminal cell. `Screen` holds the column of the split, counted
+/// from the left.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Region {
+    Screen(usize),
+    StatusBar,
+    MessageBar,
+}
+
 pub struct Pane {
     component: Component,
-    screen: Screen,
+    screens: Vec<Screen>,
+    /// Index into `screens` of the screen shown in each column, from left to right.
+    panes: Vec<usize>,
+    focus: usize,
+    /// The area and row padding of a column showing the focused screen a second time; the
+    /// screen itself is sized for the focused column.
+    mirror: Option<(Component, bool)>,
+    mirror_rows: RefCell<Vec<String>>,
     status_bar: StatusBar,
     message_bar: MessageBar,
     quit_times: usize,
+    clipboard: String,
+    /// Killed text, most recent first.
+    kill_ring: VecDeque<String>,
+    /// Where the last yank started and which kill ring entry it inserted.
+    yank: Option<((usize, usize), usize)>,
+    last_command: Command,
+    keymap: KeyMap,
+    snippets: Snippets,
+    terminal: Terminal,
 }
 
 impl Pane {
     pub fn new(message: String, system_time: SystemTime) -> Pane {
         Pane {
             component: Component::default(),
-            screen: Screen::new(),
+            screens: vec![Screen::new()],
+            panes: vec![0],
+            focus: 0,
+            mirror: None,
+            mirror_rows: RefCell::new(Vec::new()),
             status_bar: StatusBar::new(),
             message_bar: MessageBar::new(message, system_time),
             quit_times: QUIT_TIMES,
+            clipboard: String::new(),
+            kill_ring: VecDeque::new(),
+            yank: None,
+            last_command: Command::Noop,
+            keymap: KeyMap::default(),
+            snippets: Snippets::default(),
+            terminal: Terminal::default(),
         }
     }
 
     pub fn set_size(&mut self, x: usize, y: usize, width: usize, height: usize) {
         self.component.set_size(x, y, width, height);
-        self.screen.set_size(x, y, width, height - 2);
+        self.layout();
         self.status_bar.set_size(x, y + height - 2, width, 1);
         self.message_bar.set_size(x, y + height - 1, width, 1);
     }
 
     pub fn screen(&mut self) -> &mut Screen {
-        &mut self.screen
+        let active = self.active();
+        &mut self.screens[active]
+    }
+
+    fn active(&self) -> usize {
+        self.panes[self.focus]
+    }
+
+    /// Splits the width of the pane between the columns in `panes`, leaving one column
+    /// between neighbours for the separator.
+    fn areas(&self) -> Vec<Component> {
+        let (x, y) = (self.component.x(), self.component.y());
+        let (width, height) = (self.component.width(), self.component.height());
+        let count = self.panes.len();
+        let pane_width = width.saturating_sub(count - 1) / count;
+
+        (0..count)
+            .map(|i| {
+                let pane_x = x + i * (pane_width + 1);
+                let pane_width = if i == count - 1 {
+                    (x + width).saturating_sub(pane_x)
+                } else {
+                    pane_width
+                };
+                Component::new(pane_x, y, pane_width, height.saturating_sub(2))
+            })
+            .collect()
+    }
+
+    /// Sizes each screen for its column. A screen shown in two columns takes the focused
+    /// one's area, and the other is drawn as a mirror of it.
+    fn layout(&mut self) {
+        let count = self.panes.len();
+        let active = self.active();
+        self.mirror = None;
+        self.mirror_rows.borrow_mut().clear();
+
+        for (i, area) in self.areas().into_iter().enumerate() {
+            let index = self.panes[i];
+            let pad_rows = i != count - 1;
+            if index == active && i != self.focus {
+                self.mirror = Some((area, pad_rows));
+                continue;
+            }
+            let screen = &mut self.screens[index];
+            screen.set_size(area.x(), area.y(), area.width(), area.height());
+            screen.set_pad_rows(pad_rows);
+        }
+    }
+
+    /// Returns the 1-based index of the active buffer and the number of open buffers.
+    pub fn buffer_position(&self) -> (usize, usize) {
+        (self.active() + 1, self.screens.len())
+    }
+
+    pub fn next_buffer(&mut self) {
+        let len = self.screens.len();
+        let active = self.active();
+        if let Some(index) = (1..len)
+            .map(|i| (active + i) % len)
+            .find(|index| !self.panes.contains(index))
+        {
+            self.switch_buffer(index);
+        }
+    }
+
+    pub fn prev_buffer(&mut self) {
+        let len = self.screens.len();
+        let active = self.active();
+        if let Some(index) = (1..len)
+            .map(|i| (active + len - i) % len)
+            .find(|index| !self.panes.contains(index))
+        {
+            self.switch_buffer(index);
+        }
+    }
+
+    /// Shows the hex dump of the active buffer in a read-only buffer, or returns from a hex
+    /// view to the buffer it was made from. Unmodified read-only buffers are dumped from disk
+    /// so that bytes lost in decoding still show. The view is for inspecting bytes only;
+    /// editing them goes through the original buffer.
+    pub fn toggle_hex(&mut self) -> Result<(), Error> {
+        let active = self.active();
+        if let Some(source) = self.screens[active].hex_source() {
+            self.show_buffer(source);
+            return Ok(());
+        }
+
+        let buffer = self.screen().buffer();
+        let bytes = match buffer.get_filepath() {
+            Some(path) if buffer.is_read_only() && !buffer.is_dirty() => fs::read(path)?,
+            _ => {
+                buffer.load_all()?;
+                buffer.get_text().into_bytes()
+            }
+        };
+
+        let index = match (0..self.screens.len())
+            .find(|&index| self.screens[index].hex_source() == Some(active))
+        {
+            Some(index) => index,
+            None => {
+                let mut screen = self.new_screen();
+                screen.set_hex_source(Some(active));
+                self.screens.push(screen);
+                self.screens.len() - 1
+            }
+        };
+        let screen = &mut self.screens[index];
+        screen.buffer().load_hex_dump(&bytes);
+        screen.set_cursor(0, 0);
+        screen.set_offset(0, 0);
+        self.show_buffer(index);
+        Ok(())
+    }
+
+    /// An empty screen with the active one's display settings and save options.
+    fn new_screen(&mut self) -> Screen {
+        let active = self.screen();
+        let mut screen = Screen::new();
+        screen.set_theme(active.theme().clone());
+        screen.set_rulers(active.rulers().to_vec());
+        screen.set_line_numbers(active.line_numbers());
+        screen.set_max_line_length(active.max_line_length());
+        screen.set_smooth_scroll(active.smooth_scroll());
+        let backup_on_save = active.buffer().backup_on_save();
+        let refuse_binary = active.buffer().refuse_binary();
+        screen.buffer().set_backup_on_save(backup_on_save);
+        screen.buffer().set_refuse_binary(refuse_binary);
+        screen
+    }
+
+    /// Focuses the pane showing `index`, or shows it in the focused pane.
+    fn show_buffer(&mut self, index: usize) {
+        match self.panes.iter().position(|&i| i == index) {
+            Some(pane) => {
+                self.focus = pane;
+                self.layout();
+            }
+            None => self.switch_buffer(index),
+        }
+    }
+
+    fn switch_buffer(&mut self, index: usize) {
+        self.panes[self.focus] = index;
+        self.layout();
+    }
+
+    /// Divides the pane into two columns that both show the active buffer, and focuses the
+    /// right one.
+    pub fn split_vertical(&mut self) {
+        if self.panes.len() > 1 {
+            return;
+        }
+
+        self.panes.push(self.active());
+        self.focus = self.panes.len() - 1;
+        self.layout();
+    }
+
+    pub fn focus_next_pane(&mut self) {
+        self.focus = (self.focus + 1) % self.panes.len();
+        self.layout();
+    }
+
+    /// Finds what is drawn at terminal cell `(x, y)`, `None` for a split separator or a cell
+    /// outside the pane.
+    pub fn region_at(&self, x: usize, y: usize) -> Option<Region> {
+        if let Some(i) = self.areas().iter().position(|area| area.contains(x, y)) {
+            return Some(Region::Screen(i));
+        }
+        if self.status_bar.contains(x, y) {
+            return Some(Region::StatusBar);
+        }
+        if self.message_bar.contains(x, y) {
+            return Some(Region::MessageBar);
+        }
+        None
+    }
+
+    /// Handles a click at terminal cell `(x, y)`: a click on a screen focuses it and moves its
+    /// cursor there, and the bars ignore clicks.
+    pub fn click(&mut self, x: usize, y: usize) {
+        if let Some(Region::Screen(i)) = self.region_at(x, y) {
+            if self.focus != i {
+                self.focus = i;
+                self.layout();
+            }
+            self.screen().click(x, y);
+        }
+    }
+
+    /// Opens `path` in a new buffer, reusing the active one if it is an untouched empty buffer.
+    pub fn open_buffer(&mut self, path: String) -> Result<(), Error> {
+        let reuse = {
+            let buffer = self.screen().buffer();
+            buffer.get_filepath().is_none() && !buffer.is_dirty() && buffer.is_empty()
+        };
+        if reuse {
+            self.screen().buffer().load_file(path)?;
+            self.screen().set_cursor(0, 0);
+            self.screen().set_offset(0, 0);
+            return Ok(());
+        }
+
+        let mut screen = self.new_screen();
+        screen.buffer().load_file(path)?;
+
+        self.screens.push(screen);
+        self.switch_buffer(self.screens.len() - 1);
+        Ok(())
+    }
+
+    pub fn terminal(&mut self) -> &mut Terminal {
+        &mut self.terminal
+    }
+
+    pub fn set_terminal(&mut self, terminal: Terminal) {
+        self.terminal = terminal;
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message_bar.set(message, SystemTime::now());
+    }
+
+    pub fn set_keymap(&mut self, keymap: KeyMap) {
+        self.keymap = keymap;
+    }
+
+    pub fn set_snippets(&mut self, snippets: Snippets) {
+        self.snippets = snippets;
     }
 
     pub fn resolve_command(&self, key: Key) -> Command {
-        match key {
-            Key::ControlSequence('f') => Command::Find,
-            Key::ControlSequence('h') => Command::Backspace,
-            Key::ControlSequence('m') => Command::Enter,
-            Key::ControlSequence('q') => Command::Exit,
-            Key::ControlSequence('s') => Command::Save,
-            Key::ArrowLeft => Command::ArrowLeft,
-            Key::ArrowRight => Command::ArrowRight,
-            Key::ArrowUp => Command::ArrowUp,
-            Key::ArrowDown => Command::ArrowDown,
-            Key::PageUp => Command::PageUp,
-            Key::PageDown => Command::PageDown,
-            Key::Home => Command::Home,
-            Key::End => Command::End,
-            Key::Enter => Command::Enter,
-            Key::Delete => Command::Delete,
-            Key::Backspace => Command::Backspace,
-            Key::Escape => Command::Escape,
-            Key::NormalKey(c) => Command::Input(c),
-            _ => Command::Noop,
+        self.keymap.resolve(key)
+    }
+
+    /// Runs `keys` as if typed, prompts included, and returns whether one of them asked to
+    /// exit. Keys left over after an exit are dropped. This drives whole editing sessions
+    /// without a terminal.
+    pub fn feed_keys(&mut self, keys: &[Key]) -> bool {
+        let mut input = ScriptedInput::from(keys.to_vec());
+        while let Ok(key) = input.next_key() {
+            let command = self.resolve_command(key);
+            if self.process_command(&mut input, command).is_err() {
+                return true;
+            }
         }
+        false
     }
 
     pub fn process_command(
         &mut self,
-        reader: &mut dyn Read,
+        reader: &mut dyn InputSource,
         command: Command,
     ) -> Result<(), Error> {
+        if !matches!(
+            command,
+            Command::Input(_)
+                | Command::Backspace
+                | Command::Delete
+                | Command::AddCursorBelow
+                | Command::AddCursorAbove
+        ) {
+            self.screen().collapse_cursors();
+        }
+
         match command {
             Command::Exit => self.process_exit_command()?,
+            Command::ForceQuit => return Err(Error::other("exit")),
             Command::Save => self.process_save_command(reader)?,
+            Command::SaveAndQuit => self.process_save_and_quit_command(reader)?,
+            Command::SaveAs => self.process_save_as_command(reader)?,
+            Command::NormalizeLineEndings => self.process_normalize_line_endings_command(reader)?,
+            Command::StripBom => {
+                let message = if self.screen().buffer().has_bom() {
+                    self.screen().buffer().set_bom(false);
+                    "Byte order mark removed"
+                } else {
+                    "No byte order mark"
+                };
+                self.message_bar.set(message.to_string(), SystemTime::now());
+            }
+            Command::AppendSave => self.process_append_save_command(reader)?,
+            Command::Open => self.process_open_command(reader)?,
+            Command::ToggleHeaderSource => self.toggle_header_source(),
+            Command::CheckIndent => self.check_indent(),
+            Command::InsertUnicode => self.process_insert_unicode_command(reader)?,
+            Command::Revert => self.process_revert_command(reader)?,
             Command::Find => self.process_find_command(reader)?,
-            Command::ArrowDown => self.screen.down(),
-            Command::ArrowUp => self.screen.up(),
-            Command::ArrowLeft => self.screen.left(),
-            Command::ArrowRight => self.screen.right(),
-            Command::PageUp => self.screen.page_up(),
-            Command::PageDown => self.screen.page_down(),
-            Command::Home => self.screen.home(),
-            Command::Enter => self.screen.insert_new_line(),
-            Command::End => self.screen.end(),
-            Command::Delete => {
-                self.screen.right();
-                self.screen.delete_char();
-            }
-            Command::Backspace => self.screen.delete_char(),
-            Command::Input(c) => self.screen.insert_char(c),
-            Command::Escape => {}
+            Command::MatchBracket => {
+                if !self.screen().match_bracket() {
+                    self.message_bar
+                        .set("No matching bracket".to_string(), SystemTime::now());
+                }
+            }
+            Command::SplitVertical => self.split_vertical(),
+            Command::FocusNextPane => self.focus_next_pane(),
+            Command::NextBuffer => self.next_buffer(),
+            Command::PrevBuffer => self.prev_buffer(),
+            Command::ToggleHex => {
+                if let Err(err) = self.toggle_hex() {
+                    let err_message = format!("Can't show hex! I/O error: {}", err);
+                    self.message_bar.set(err_message, SystemTime::now());
+                }
+            }
+            Command::ToggleWhitespace => self.screen().toggle_show_whitespace(),
+            Command::ToggleScrollbar => self.screen().toggle_scrollbar(),
+            Command::ToggleMinimap => self.screen().toggle_minimap(),
+            Command::ArrowDown => self.screen().down(),
+            Command::ArrowUp => self.screen().up(),
+            Command::ArrowLeft => self.screen().left(),
+            Command::ArrowRight => self.screen().right(),
+            Command::PageUp => self.screen().page_up(),
+            Command::PageDown => self.screen().page_down(),
+            Command::ScrollLeft => self.screen().scroll_left(),
+            Command::ScrollRight => self.screen().scroll_right(),
+            Command::Home => self.screen().home(),
+            Command::Enter => self.screen().insert_new_line(),
+            Command::DuplicateLine => self.screen().duplicate_line(),
+            Command::OpenLineBelow => self.screen().open_line_below(),
+            Command::OpenLineAbove => self.screen().open_line_above(),
+            Command::MoveLineUp => self.screen().move_line_up(),
+            Command::MoveLineDown => self.screen().move_line_down(),
+            Command::End => self.screen().end(),
+            Command::Delete if self.screen().block().is_some() => {
+                self.screen().delete_in_block(true)
+            }
+            Command::Delete => self.screen().delete_forward_at_cursors(),
+            Command::Backspace if self.screen().block().is_some() => {
+                self.screen().delete_in_block(false)
+            }
+            Command::Backspace => self.screen().delete_char_at_cursors(),
+            Command::AddCursorBelow => self.screen().add_cursor_below(),
+            Command::AddCursorAbove => self.screen().add_cursor_above(),
+            Command::DeleteToEol => {
+                let text = self.screen().delete_to_eol();
+                self.kill(text, true);
+            }
+            Command::DeleteWord => {
+                let text = self.screen().delete_word();
+                self.kill(text, false);
+            }
+            Command::Indent => {
+                if !self.expand_snippet() {
+                    self.screen().indent();
+                }
+            }
+            Command::Dedent => self.screen().dedent(),
+            Command::Input(c) if self.screen().block().is_some() => {
+                self.screen().insert_in_block(&c.to_string())
+            }
+            Command::Input(c) => self.screen().insert_char_at_cursors(c),
+            Command::StartSelection => self.screen().start_selection(),
+            Command::StartBlockSelection => self.screen().start_block_selection(),
+            Command::Copy => {
+                self.copy_selection();
+                self.screen().clear_selection();
+            }
+            Command::Cut => {
+                self.copy_selection();
+                self.screen().delete_selection();
+            }
+            Command::Paste => {
+                self.screen().delete_selection();
+                let text = self.clipboard.clone();
+                self.screen().insert_text(&text);
+            }
+            Command::Yank => self.yank(),
+            Command::YankPop => self.yank_pop(),
+            Command::Escape => self.screen().clear_selection(),
             Command::Noop => {}
         }
 
+        self.last_command = command;
         self.post_process();
         if command != Command::Exit {
             self.quit_times = QUIT_TIMES;
@@ -98,48 +461,160 @@ impl Pane {
         Ok(())
     }
 
+    /// Pushes killed `text` onto the kill ring. Right after another kill it is added to the
+    /// most recent entry instead, after it when killing `forward` and before it otherwise.
+    fn kill(&mut self, text: String, forward: bool) {
+        if text.is_empty() {
+            return;
+        }
+        let continued = matches!(
+            self.last_command,
+            Command::DeleteToEol | Command::DeleteWord
+        );
+        match self.kill_ring.front_mut() {
+            Some(top) if continued && forward => top.push_str(&text),
+            Some(top) if continued => top.insert_str(0, &text),
+            _ => {
+                self.kill_ring.push_front(text);
+                self.kill_ring.truncate(KILL_RING_SIZE);
+            }
+        }
+    }
+
+    fn yank(&mut self) {
+        let text = match self.kill_ring.front() {
+            Some(text) => text.clone(),
+            None => return,
+        };
+        self.screen().delete_selection();
+        self.yank = Some((self.screen().cursor(), 0));
+        self.screen().insert_text(&text);
+    }
+
+    /// Replaces the text inserted by the preceding yank with the next older kill.
+    fn yank_pop(&mut self) {
+        let ((x, y), index) = match self.yank {
+            Some(yank) if matches!(self.last_command, Command::Yank | Command::YankPop) => yank,
+            _ => return,
+        };
+        let (cx, cy) = self.screen().cursor();
+        self.screen().set_cursor(x, y);
+        self.screen().clear_selection();
+        self.screen().start_selection();
+        self.screen().set_cursor(cx, cy);
+        self.screen().delete_selection();
+
+        let index = (index + 1) % self.kill_ring.len();
+        let text = self.kill_ring[index].clone();
+        self.yank = Some(((x, y), index));
+        self.screen().insert_text(&text);
+    }
+
     fn post_process(&mut self) {
-        self.screen.adjust();
-        self.status_bar.set_left_status(&mut self.screen);
-        self.status_bar.set_right_status(&mut self.screen);
+        let ret = self
+            .screen()
+            .load_visible_lines()
+            .and_then(|_| self.screen().buffer().load_more(LOAD_CHUNK_LINES));
+        if let Err(err) = ret {
+            let err_message = format!("Can't load! I/O error: {}", err);
+            self.message_bar.set(err_message, SystemTime::now());
+        }
+        self.screen().buffer().apply_background_highlights();
+        self.screen().adjust();
+        let position = self.buffer_position();
+        let active = self.active();
+        let screen = &mut self.screens[active];
+        self.status_bar.set_left_status(screen, position);
+        self.status_bar.set_right_status(screen);
     }
 
     pub fn get_terminal_cursor(&self) -> (usize, usize) {
-        self.screen.get_terminal_cursor()
+        self.screens[self.active()].get_terminal_cursor()
     }
 
+    /// Exits at once when every buffer is clean. With unsaved changes it warns instead,
+    /// counting down `QUIT_TIMES` more presses before it gives in.
     pub fn process_exit_command(&mut self) -> Result<(), Error> {
-        let buffer = &mut self.screen.buffer();
-        let message_bar = &mut self.message_bar;
-        if buffer.is_dirty() && self.quit_times > 0 {
-            let warning_message = format!(
-                "WARNING!!! File has unsaved changes. Press Ctrl+Q {} more times to quit.",
-                self.quit_times
-            );
-            message_bar.set(warning_message, SystemTime::now());
-            self.quit_times -= 1;
-            return Ok(());
+        let dirty = self
+            .screens
+            .iter_mut()
+            .any(|screen| screen.buffer().is_dirty());
+        if !dirty || self.quit_times == 0 {
+            return Err(Error::other("exit"));
         }
 
-        Err(Error::other("exit"))
+        let warning_message = format!(
+            "WARNING!!! File has unsaved changes. Press Ctrl+Q {} more times to quit.",
+            self.quit_times
+        );
+        self.message_bar.set(warning_message, SystemTime::now());
+        self.quit_times -= 1;
+        Ok(())
+    }
+
+    pub fn process_save_command(&mut self, reader: &mut dyn InputSource) -> Result<(), Error> {
+        self.save(reader);
+        Ok(())
+    }
+
+    /// Saves the active buffer and exits like `process_exit_command`, so other buffers with
+    /// unsaved changes still get the warning. Stays open if the save is aborted or fails.
+    pub fn process_save_and_quit_command(
+        &mut self,
+        reader: &mut dyn InputSource,
+    ) -> Result<(), Error> {
+        if self.save(reader) {
+            return self.process_exit_command();
+        }
+        Ok(())
     }
 
-    pub fn process_save_command(&mut self, reader: &mut dyn Read) -> Result<(), Error> {
-        let mut callback = |_: &str, _: Key, _: &mut Screen| {};
+    /// Saves the active buffer, prompting for a path if it has none, and reports the
+    /// outcome in the message bar. Returns whether the buffer was written.
+    fn save(&mut self, reader: &mut dyn InputSource) -> bool {
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
 
-        let filepath = self.screen.buffer().get_filepath();
+        let filepath = self.screen().buffer().get_filepath();
         let ret = if filepath.is_none() {
-            match self.prompt(reader, "Save as: ", &mut callback) {
-                Ok(path) => self.screen.buffer().save_file(path),
-                Err(_) => return Ok(()),
+            let suggestion = self.suggest_filepath();
+            match self.prompt(reader, "Save as: ", &suggestion, &mut callback) {
+                Ok(path) => self.screen().buffer().save_file(path),
+                Err(_) => return false,
             }
         } else {
-            self.screen.buffer().overwrite_file()
+            self.screen().buffer().overwrite_file()
         };
 
         match ret {
             Ok(size) => {
-                let success_message = format!("{} bytes written to disk", size);
+                // Trimming trailing whitespace on save may have shortened the cursor's line.
+                let screen = self.screen();
+                let (cx, cy) = screen.cursor();
+                if let Some(line) = screen.buffer().get_line(cy) {
+                    screen.set_cursor(cx.min(line.len()), cy);
+                }
+                let success_message = format!("{} written to disk", humanize_bytes(size));
+                self.message_bar.set(success_message, SystemTime::now());
+                true
+            }
+            Err(err) => {
+                let err_message = format!("Can't save! I/O error: {}", err);
+                self.message_bar.set(err_message, SystemTime::now());
+                false
+            }
+        }
+    }
+
+    pub fn process_save_as_command(&mut self, reader: &mut dyn InputSource) -> Result<(), Error> {
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
+        let path = match self.prompt(reader, "Save copy as: ", "", &mut callback) {
+            Ok(path) => path,
+            Err(_) => return Ok(()),
+        };
+
+        match self.screen().buffer().save_copy(&path) {
+            Ok(size) => {
+                let success_message = format!("{} written to {}", humanize_bytes(size), path);
                 self.message_bar.set(success_message, SystemTime::now());
             }
             Err(err) => {
@@ -151,142 +626,472 @@ impl Pane {
         Ok(())
     }
 
-    pub fn process_find_command(&mut self, reader: &mut dyn Read) -> Result<(), Error> {
-        let mut direction = Direction::Down;
-        let mut last_match = true;
-        let mut callback = |query: &str, key: Key, screen: &mut Screen| match key {
-            Key::ArrowUp | Key::ArrowLeft => {
-                direction = Direction::Up;
-                if !last_match {
-                    let buffer_len = screen.buffer().len();
-                    let buffer_last_line = screen.buffer().get_line(buffer_len - 1);
-                    if let Some(last_line) = buffer_last_line {
-                        screen.set_cursor(last_line.len() - 1, buffer_len)
-                    }
+    pub fn process_append_save_command(
+        &mut self,
+        reader: &mut dyn InputSource,
+    ) -> Result<(), Error> {
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
+        let path = match self.prompt(reader, "Append to: ", "", &mut callback) {
+            Ok(path) => path,
+            Err(_) => return Ok(()),
+        };
+
+        match self.screen().buffer().append_to_file(&path) {
+            Ok(size) => {
+                let success_message = format!("{} appended to {}", humanize_bytes(size), path);
+                self.message_bar.set(success_message, SystemTime::now());
+            }
+            Err(err) => {
+                let err_message = format!("Can't append! I/O error: {}", err);
+                self.message_bar.set(err_message, SystemTime::now());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn process_normalize_line_endings_command(
+        &mut self,
+        reader: &mut dyn InputSource,
+    ) -> Result<(), Error> {
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
+        let answer = match self.prompt(reader, "Line endings (lf/crlf): ", "", &mut callback) {
+            Ok(answer) => answer,
+            Err(_) => return Ok(()),
+        };
+
+        let message = match LineEnding::parse(answer.trim()) {
+            Some(line_ending) => {
+                self.screen().buffer().set_line_ending(line_ending);
+                format!("Line endings set to {}", line_ending.to_str())
+            }
+            None => format!("Unknown line ending: {}", answer),
+        };
+        self.message_bar.set(message, SystemTime::now());
+
+        Ok(())
+    }
+
+    pub fn process_insert_unicode_command(
+        &mut self,
+        reader: &mut dyn InputSource,
+    ) -> Result<(), Error> {
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
+        let answer = match self.prompt(reader, "Code point (hex): ", "", &mut callback) {
+            Ok(answer) => answer,
+            Err(_) => return Ok(()),
+        };
+
+        match parse_code_point(&answer) {
+            Some(c) => self.screen().insert_char(c),
+            None => self
+                .message_bar
+                .set(format!("Invalid code point: {}", answer), SystemTime::now()),
+        }
+
+        Ok(())
+    }
+
+    pub fn process_open_command(&mut self, reader: &mut dyn InputSource) -> Result<(), Error> {
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
+        let path = match self.prompt(reader, "Open: ", "", &mut callback) {
+            Ok(path) => path,
+            Err(_) => return Ok(()),
+        };
+
+        let open_dirty = self.screens.iter_mut().any(|screen| {
+            screen.buffer().is_dirty() && screen.buffer().get_filepath().as_ref() == Some(&path)
+        });
+        if open_dirty
+            && !self.confirm(
+                reader,
+                "WARNING!!! File is open with unsaved changes. Open it again? (y/n) ",
+            )
+        {
+            return Ok(());
+        }
+
+        if let Err(err) = self.open_buffer(path) {
+            let err_message = format!("Can't open! I/O error: {}", err);
+            self.message_bar.set(err_message, SystemTime::now());
+        }
+
+        Ok(())
+    }
+
+    /// Reports how many lines mix tabs and spaces in their indentation, and the first one.
+    fn check_indent(&mut self) {
+        if let Err(err) = self.screen().buffer().load_all() {
+            let err_message = format!("Can't load! I/O error: {}", err);
+            self.message_bar.set(err_message, SystemTime::now());
+            return;
+        }
+        let lines = self.screen().buffer().mixed_indent_lines();
+        let message = match lines.first() {
+            Some(first) => format!(
+                "{} line(s) mix tabs and spaces, first at line {}",
+                lines.len(),
+                first + 1
+            ),
+            None => "No mixed indentation".to_string(),
+        };
+        self.message_bar.set(message, SystemTime::now());
+    }
+
+    /// Switches to the header for a C or C++ source file, or to the source for a header,
+    /// opening the first counterpart that exists unless it is already open.
+    fn toggle_header_source(&mut self) {
+        let path = match self.screen().buffer().get_filepath() {
+            Some(path) => path,
+            None => {
+                self.message_bar
+                    .set("No file to switch from".to_string(), SystemTime::now());
+                return;
+            }
+        };
+        let counterpart = match header_source_candidates(&path)
+            .into_iter()
+            .find(|candidate| Path::new(candidate).is_file())
+        {
+            Some(counterpart) => counterpart,
+            None => {
+                let message = format!("No header or source for {}", path);
+                self.message_bar.set(message, SystemTime::now());
+                return;
+            }
+        };
+
+        let open = (0..self.screens.len()).find(|&index| {
+            self.screens[index].buffer().get_filepath() == Some(counterpart.clone())
+        });
+        match open {
+            Some(index) => self.show_buffer(index),
+            None => {
+                if let Err(err) = self.open_buffer(counterpart) {
+                    let err_message = format!("Can't open! I/O error: {}", err);
+                    self.message_bar.set(err_message, SystemTime::now());
                 }
-                let (cx, cy) = screen.cursor();
-                screen.left();
-                last_match = screen.rfind(query);
-                if last_match {
-                    screen.buffer().clear_highlight(cy);
-                    let cur = screen.cursor();
-                    screen
-                        .buffer()
-                        .highlight(cur.0, cur.1, query.len(), Highlight::Match);
-                } else {
-                    screen.set_cursor(cx, cy);
+            }
+        }
+    }
+
+    pub fn process_revert_command(&mut self, reader: &mut dyn InputSource) -> Result<(), Error> {
+        let path = match self.screen().buffer().get_filepath() {
+            Some(path) => path,
+            None => {
+                self.message_bar
+                    .set("No file to revert".to_string(), SystemTime::now());
+                return Ok(());
+            }
+        };
+
+        if self.screen().buffer().is_dirty()
+            && !self.confirm(
+                reader,
+                "WARNING!!! File has unsaved changes. Revert anyway? (y/n) ",
+            )
+        {
+            return Ok(());
+        }
+
+        match self.screen().buffer().load_file(path) {
+            Ok(_) => {
+                let (cx, cy) = self.screen().cursor();
+                let cy = cy.min(self.screen().buffer().len());
+                let cx = self
+                    .screen()
+                    .buffer()
+                    .get_line(cy)
+                    .map_or(0, |line| cx.min(line.len()));
+                self.screen().set_cursor(cx, cy);
+            }
+            Err(err) => {
+                let err_message = format!("Can't revert! I/O error: {}", err);
+                self.message_bar.set(err_message, SystemTime::now());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Expands the snippet triggered by the word before the cursor, if there is one.
+    fn expand_snippet(&mut self) -> bool {
+        let (start, word) = match self.screen().word_at() {
+            Some(word) => word,
+            None => return false,
+        };
+        let text = match self.snippets.get(&word) {
+            Some(text) => text.to_string(),
+            None => return false,
+        };
+        self.screen().expand_snippet(start, &text);
+        true
+    }
+
+    fn copy_selection(&mut self) {
+        if let Some(text) = self.screen().selected_text() {
+            self.clipboard = text;
+        }
+    }
+
+    pub fn tick(&mut self, now: SystemTime) {
+        self.message_bar.tick(now);
+        for screen in &mut self.screens {
+            screen.buffer().apply_background_highlights();
+        }
+    }
+
+    pub fn write_swap(&mut self) {
+        for screen in &mut self.screens {
+            let buffer = screen.buffer();
+            if !buffer.is_dirty() {
+                continue;
+            }
+
+            if let Some(path) = buffer.get_filepath() {
+                if let Err(err) = buffer.write_swap(&swap_path(&path)) {
+                    let err_message = format!("Can't write swap file! I/O error: {}", err);
+                    self.message_bar.set(err_message, SystemTime::now());
                 }
-                screen.adjust();
             }
-            Key::ArrowDown | Key::ArrowRight => {
-                direction = Direction::Down;
-                if !last_match {
-                    screen.set_cursor(0, 0);
+        }
+    }
+
+    pub fn remove_swap(&mut self) {
+        for screen in &mut self.screens {
+            if let Some(path) = screen.buffer().get_filepath() {
+                let _ = fs::remove_file(swap_path(&path));
+            }
+        }
+    }
+
+    pub fn recover_swap(&mut self, reader: &mut dyn InputSource) {
+        let swap = match self
+            .screen()
+            .buffer()
+            .get_filepath()
+            .and_then(|p| find_swap(&p))
+        {
+            Some(swap) => swap,
+            None => return,
+        };
+
+        if !self.confirm(reader, "Swap file found. Recover unsaved changes? (y/n) ") {
+            return;
+        }
+
+        if let Err(err) = self.screen().buffer().recover_swap(&swap) {
+            let err_message = format!("Can't recover! I/O error: {}", err);
+            self.message_bar.set(err_message, SystemTime::now());
+        }
+    }
+
+    pub fn process_find_command(&mut self, reader: &mut dyn InputSource) -> Result<(), Error> {
+        if let Err(err) = self.screen().buffer().load_all() {
+            let err_message = format!("Can't load! I/O error: {}", err);
+            self.message_bar.set(err_message, SystemTime::now());
+            return Ok(());
+        }
+
+        let mut direction = Direction::Down;
+        let mut options = SearchOptions::default();
+        let mut callback = |query: &str, key: Key, screen: &mut Screen| {
+            let (cx, cy) = screen.cursor();
+            let mut label = None;
+            let found = match key {
+                Key::ArrowUp | Key::ArrowLeft => {
+                    direction = Direction::Up;
+                    screen.left();
+                    screen.rfind_wrapping(query, options)
                 }
-                let (cx, cy) = screen.cursor();
-                screen.right();
-                last_match = screen.find(query);
-                if last_match {
-                    screen.buffer().clear_highlight(cy);
-                    let cur = screen.cursor();
-                    screen
-                        .buffer()
-                        .highlight(cur.0, cur.1, query.len(), Highlight::Match);
-                } else {
-                    screen.set_cursor(cx, cy);
+                Key::ArrowDown | Key::ArrowRight => {
+                    direction = Direction::Down;
+                    screen.right();
+                    screen.find_wrapping(query, options)
                 }
-                screen.adjust();
-            }
-            _ => {
-                if !last_match {
+                _ => {
+                    match key {
+                        Key::ControlSequence('i') => options.ignore_case = !options.ignore_case,
+                        Key::ControlSequence('w') => options.whole_word = !options.whole_word,
+                        _ => {}
+                    }
+                    label = Some(format!("Search{}: ", options.label()));
                     match direction {
-                        Direction::Up => {
-                            let buffer_len = screen.buffer().len();
-                            let buffer_last_line = screen.buffer().get_line(buffer_len - 1);
-                            if let Some(last_line) = buffer_last_line {
-                                screen.set_cursor(last_line.len() - 1, buffer_len)
-                            }
-                        }
-                        Direction::Down => {
-                            screen.set_cursor(0, 0);
-                        }
+                        Direction::Up => screen.rfind_wrapping(query, options),
+                        Direction::Down => screen.find_wrapping(query, options),
                     }
                 }
-                let (_, cy) = screen.cursor();
-                last_match = match direction {
-                    Direction::Up => screen.rfind(query),
-                    Direction::Down => screen.find(query),
-                };
-                screen.buffer().clear_highlight(cy);
-                if last_match {
-                    let cur = screen.cursor();
-                    screen
-                        .buffer()
-                        .highlight(cur.0, cur.1, query.len(), Highlight::Match);
-                }
-                screen.adjust();
+            };
+            screen.buffer().clear_highlight(cy);
+            if found.is_some() {
+                let cur = screen.cursor();
+                screen.ensure_visible(cur.0, cur.1);
+                screen
+                    .buffer()
+                    .highlight(cur.0, cur.1, query.len(), Highlight::Match);
+                screen.set_search_marker(Some(cur));
+            } else {
+                screen.set_cursor(cx, cy);
+                screen.set_search_marker(None);
             }
+            screen.adjust();
+            label
         };
-        let (cx, cy) = self.screen.cursor();
-        let (offset_x, offset_y) = self.screen.offset();
+        let (cx, cy) = self.screen().cursor();
+        let (offset_x, offset_y) = self.screen().offset();
 
-        match self.prompt(reader, "Search: ", &mut callback) {
+        // Searching for the word under the cursor starts after it, so Enter goes to the next
+        // occurrence rather than staying put.
+        let seed = match self.screen().word_under_cursor() {
+            Some((start, word)) => {
+                self.screen().set_cursor(start + word.len(), cy);
+                word
+            }
+            None => String::new(),
+        };
+        let prompted = self.prompt_seeded(reader, "Search: ", &seed, &mut callback);
+        self.screen().set_search_marker(None);
+        match prompted {
             Ok(_) => {}
             Err(_) => {
-                self.screen.set_cursor(cx, cy);
-                self.screen.set_offset(offset_x, offset_y);
-                self.screen.adjust();
+                self.screen().set_cursor(cx, cy);
+                self.screen().set_offset(offset_x, offset_y);
+                self.screen().adjust();
             }
         }
         Ok(())
     }
 
+    /// Prompts for a single key: `y` or `Y` agrees, while any other character, Enter or
+    /// Escape declines.
+    pub fn confirm(&mut self, reader: &mut dyn InputSource, prompt: &str) -> bool {
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
+        match self.read_prompt(reader, prompt, "", PromptMode::Key, &mut callback) {
+            Ok(answer) => answer.eq_ignore_ascii_case("y"),
+            Err(_) => false,
+        }
+    }
+
+    /// Suggests `untitled.txt` next to the file of another open buffer, if there is one.
+    fn suggest_filepath(&mut self) -> String {
+        let dir = self
+            .screens
+            .iter_mut()
+            .filter_map(|screen| screen.buffer().get_filepath())
+            .find_map(|path| {
+                let parent = Path::new(&path).parent()?;
+                (!parent.as_os_str().is_empty()).then(|| parent.to_path_buf())
+            });
+        match dir {
+            Some(dir) => dir.join("untitled.txt").to_string_lossy().to_string(),
+            None => "untitled.txt".to_string(),
+        }
+    }
+
+    /// Reads a line of input after `prompt`, starting out with the editable `initial` text.
     pub fn prompt<T>(
         &mut self,
-        reader: &mut dyn Read,
+        reader: &mut dyn InputSource,
+        prompt: &str,
+        initial: &str,
+        callback: &mut T,
+    ) -> Result<String, Error>
+    where
+        T: FnMut(&str, Key, &mut Screen) -> Option<String>,
+    {
+        self.read_prompt(reader, prompt, initial, PromptMode::Line, callback)
+    }
+
+    /// Like `prompt`, but a char typed before any Backspace replaces `seed` instead of being
+    /// appended, so the suggestion costs nothing to ignore.
+    pub fn prompt_seeded<T>(
+        &mut self,
+        reader: &mut dyn InputSource,
         prompt: &str,
+        seed: &str,
         callback: &mut T,
     ) -> Result<String, Error>
     where
-        T: FnMut(&str, Key, &mut Screen),
+        T: FnMut(&str, Key, &mut Screen) -> Option<String>,
     {
-        let mut input = String::new();
-        let mut buf = String::from(prompt);
+        self.read_prompt(reader, prompt, seed, PromptMode::Seeded, callback)
+    }
 
-        self.message_bar.set(buf.clone(), SystemTime::now());
+    fn read_prompt<T>(
+        &mut self,
+        reader: &mut dyn InputSource,
+        prompt: &str,
+        initial: &str,
+        mut mode: PromptMode,
+        callback: &mut T,
+    ) -> Result<String, Error>
+    where
+        T: FnMut(&str, Key, &mut Screen) -> Option<String>,
+    {
+        let active = self.active();
+        let mut input = initial.to_string();
+        let mut label = prompt.to_string();
+
+        self.message_bar
+            .set(format!("{}{}", label, input), SystemTime::now());
 
         loop {
             refresh_screen(self)?;
-            match read_key(reader)? {
+            let key = reader.next_key()?;
+            match key {
                 Key::Enter => {
                     self.message_bar.set("".to_string(), SystemTime::now());
-                    callback(&input, Key::Enter, &mut self.screen);
+                    callback(&input, Key::Enter, &mut self.screens[active]);
                     return Ok(input);
                 }
                 Key::Escape => {
                     self.message_bar
                         .set("aborted".to_string(), SystemTime::now());
-                    callback(&input, Key::Escape, &mut self.screen);
+                    callback(&input, Key::Escape, &mut self.screens[active]);
                     return Err(Error::other("aborted"));
                 }
                 Key::NormalKey(c) => {
+                    if mode == PromptMode::Seeded {
+                        input.clear();
+                    }
                     input.push(c);
-                    buf.push(c);
-                    self.message_bar.set(buf.clone(), SystemTime::now());
-                    callback(&input, Key::NormalKey(c), &mut self.screen);
+                    if mode == PromptMode::Key {
+                        self.message_bar.set("".to_string(), SystemTime::now());
+                        return Ok(input);
+                    }
+                    mode = PromptMode::Line;
                 }
-                key => {
-                    self.message_bar.set(buf.clone(), SystemTime::now());
-                    callback(&input, key, &mut self.screen);
+                Key::Backspace | Key::ControlSequence('h') if mode != PromptMode::Key => {
+                    input.pop();
+                    mode = PromptMode::Line;
                 }
+                _ => {}
+            }
+            if let Some(new_label) = callback(&input, key, &mut self.screens[active]) {
+                label = new_label;
             }
+            self.message_bar
+                .set(format!("{}{}", label, input), SystemTime::now());
         }
     }
 }
 
 impl Drawable for Pane {
     fn draw(&self, buf: &mut String) -> Result<(), Error> {
-        self.screen.draw(buf)?;
+        for (i, &index) in self.panes.iter().enumerate() {
+            match &self.mirror {
+                Some((area, pad_rows)) if index == self.active() && i != self.focus => {
+                    self.screens[index].draw_in(buf, area, *pad_rows, &self.mirror_rows)?
+                }
+                _ => self.screens[index].draw(buf)?,
+            }
+        }
+        for area in &self.areas()[1..] {
+            for row in 0..area.height() {
+                buf.push_str(&move_terminal_cursor(area.x() - 1, area.y() + row));
+                buf.push('│');
+            }
+        }
         self.status_bar.draw(buf)?;
         self.message_bar.draw(buf)?;
         Ok(())
@@ -296,26 +1101,1045 @@ impl Drawable for Pane {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Command {
     Exit,
+    ForceQuit,
     Save,
+    SaveAndQuit,
+    SaveAs,
+    AppendSave,
+    NormalizeLineEndings,
+    StripBom,
+    CheckIndent,
+    ToggleHeaderSource,
+    Open,
+    InsertUnicode,
+    Revert,
     Find,
+    MatchBracket,
+    ToggleWhitespace,
+    ToggleScrollbar,
+    ToggleMinimap,
+    ToggleHex,
+    NextBuffer,
+    PrevBuffer,
+    SplitVertical,
+    FocusNextPane,
     ArrowLeft,
     ArrowRight,
     ArrowUp,
     ArrowDown,
     PageUp,
     PageDown,
+    ScrollLeft,
+    ScrollRight,
     Home,
     End,
     Enter,
+    DuplicateLine,
+    OpenLineBelow,
+    OpenLineAbove,
+    AddCursorBelow,
+    AddCursorAbove,
+    MoveLineUp,
+    MoveLineDown,
     Delete,
     Backspace,
+    DeleteToEol,
+    DeleteWord,
+    Indent,
+    Dedent,
+    StartSelection,
+    StartBlockSelection,
+    Copy,
+    Cut,
+    Paste,
+    Yank,
+    YankPop,
     Escape,
     Input(char),
     Noop,
 }
 
+/// How `read_prompt` treats its input: a line ended by Enter, a line whose seed the first
+/// key replaces, or a single character.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PromptMode {
+    Line,
+    Seeded,
+    Key,
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum Direction {
     Up,
     Down,
 }
+
+/// The paths a header or source file's counterpart may have, most likely first: the
+/// headers for a `.c` or C++ source, and the sources for a header.
+fn header_source_candidates(path: &str) -> Vec<String> {
+    let path = Path::new(path);
+    let extensions: &[&str] = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("c") => &["h"],
+        Some("cpp" | "cc" | "cxx") => &["hpp", "h", "hh"],
+        Some("h") => &["c", "cpp", "cc"],
+        Some("hpp" | "hh") => &["cpp", "cc", "cxx"],
+        _ => &[],
+    };
+    extensions
+        .iter()
+        .map(|ext| path.with_extension(ext).to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Parses a code point written in hex, optionally after `U+` or `0x`. Surrogates and values
+/// past U+10FFFF aren't chars, so they give `None`.
+fn parse_code_point(text: &str) -> Option<char> {
+    let text = text.trim();
+    let hex = ["U+", "u+", "0x", "0X"]
+        .iter()
+        .find_map(|prefix| text.strip_prefix(prefix))
+        .unwrap_or(text);
+    if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
+
+/// Formats a byte count as `999 bytes` below 1 KiB and as `1.4 MiB` above it.
+fn humanize_bytes(size: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if size < 1024 {
+        return format!("{} bytes", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        header_source_candidates, humanize_bytes, parse_code_point, Command, Pane, Region,
+    };
+    use crate::buffer::hex_row;
+    use crate::escape_sequence::{move_terminal_cursor, ESCAPE_SEQUENCE_CLEAR_LINE};
+    use crate::key::{InputSource, Key, ScriptedInput};
+    use crate::keymap::KeyMap;
+    use crate::screen::Screen;
+    use crate::snippet::Snippets;
+    use crate::ui::Drawable;
+    use crate::QUIT_TIMES;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::io::BufReader;
+    use std::time::SystemTime;
+
+    fn initialize_pane() -> Pane {
+        let mut pane = Pane::new("".to_string(), SystemTime::now());
+        pane.set_size(0, 0, 80, 24);
+        pane
+    }
+
+    fn temp_path(name: &str) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(format!("kilo-rs-{}-{}", std::process::id(), name));
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_process_open_command() {
+        let path = temp_path("open.txt");
+        fs::write(&path, "hello\n\tworld\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.screen().set_cursor(3, 1);
+        let input = format!("{}\r", path);
+        let mut reader = BufReader::new(input.as_bytes());
+        pane.process_command(&mut reader, Command::Open).unwrap();
+
+        assert_eq!(2, pane.screen().buffer().len());
+        assert_eq!(
+            Some("hello".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+        assert_eq!(
+            Some("\tworld".to_string()),
+            pane.screen().buffer().get_line(1)
+        );
+        assert_eq!(Some(path.clone()), pane.screen().buffer().get_filepath());
+        assert_eq!((0, 0), pane.screen().cursor());
+        assert_eq!((0, 0), pane.screen().offset());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_process_open_command_nonexistent_path() {
+        let path = temp_path("nonexistent.txt");
+
+        let mut pane = initialize_pane();
+        let input = format!("{}\r", path);
+        let mut reader = BufReader::new(input.as_bytes());
+        pane.process_command(&mut reader, Command::Open).unwrap();
+
+        assert!(pane.screen().buffer().is_empty());
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert!(message.starts_with("Can't open! I/O error:"), "{}", message);
+    }
+
+    #[test]
+    fn test_process_open_command_dirty() {
+        let path = temp_path("open_dirty.txt");
+        fs::write(&path, "hello\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.screen().insert_char('a');
+
+        let input = format!("{}\r", path);
+        let mut reader = BufReader::new(input.as_bytes());
+        pane.process_command(&mut reader, Command::Open).unwrap();
+        assert_eq!((2, 2), pane.buffer_position());
+        assert_eq!(
+            Some("hello".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+        assert!(!pane.screen().buffer().is_dirty());
+
+        pane.process_command(&mut reader, Command::PrevBuffer)
+            .unwrap();
+        assert_eq!((1, 2), pane.buffer_position());
+        assert_eq!(Some("a".to_string()), pane.screen().buffer().get_line(0));
+        assert!(pane.screen().buffer().is_dirty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_process_open_command_already_open_dirty() {
+        let path = temp_path("open_already_dirty.txt");
+        fs::write(&path, "hello\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.open_buffer(path.clone()).unwrap();
+        pane.screen().insert_char('a');
+
+        let input = format!("{}\rn", path);
+        let mut reader = BufReader::new(input.as_bytes());
+        pane.process_command(&mut reader, Command::Open).unwrap();
+        assert_eq!((1, 1), pane.buffer_position());
+
+        let input = format!("{}\ry", path);
+        let mut reader = BufReader::new(input.as_bytes());
+        pane.process_command(&mut reader, Command::Open).unwrap();
+        assert_eq!((2, 2), pane.buffer_position());
+        assert_eq!(
+            Some("hello".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_click() {
+        let mut pane = initialize_pane();
+        pane.screen()
+            .buffer()
+            .load_string("left\r\n\tx\r\n".to_string());
+        let mut reader = BufReader::new("".as_bytes());
+        pane.process_command(&mut reader, Command::SplitVertical)
+            .unwrap();
+
+        assert_eq!(Some(Region::Screen(0)), pane.region_at(0, 0));
+        assert_eq!(Some(Region::Screen(0)), pane.region_at(38, 21));
+        assert_eq!(None, pane.region_at(39, 0));
+        assert_eq!(Some(Region::Screen(1)), pane.region_at(40, 0));
+        assert_eq!(Some(Region::StatusBar), pane.region_at(10, 22));
+        assert_eq!(Some(Region::MessageBar), pane.region_at(79, 23));
+        assert_eq!(None, pane.region_at(80, 0));
+        assert_eq!(None, pane.region_at(0, 24));
+
+        pane.click(10, 1);
+        assert_eq!((9, 1), pane.get_terminal_cursor());
+        assert_eq!((2, 1), pane.screen().cursor());
+        pane.click(3, 1);
+        assert_eq!((0, 1), pane.get_terminal_cursor());
+        pane.click(2, 20);
+        assert_eq!((0, 1), pane.screen().cursor());
+
+        pane.click(43, 0);
+        assert_eq!((43, 0), pane.get_terminal_cursor());
+        pane.click(10, 22);
+        assert_eq!((43, 0), pane.get_terminal_cursor());
+    }
+
+    #[test]
+    fn test_split_vertical() {
+        let mut pane = initialize_pane();
+        pane.screen().buffer().load_string("both\r\n".to_string());
+        let mut reader = BufReader::new("".as_bytes());
+        pane.process_command(&mut reader, Command::SplitVertical)
+            .unwrap();
+        assert_eq!((1, 1), pane.buffer_position());
+
+        let mut buf = String::new();
+        pane.draw(&mut buf).unwrap();
+        assert!(buf.contains(&format!("{}both", move_terminal_cursor(0, 0))));
+        assert!(buf.contains(&format!("{}both", move_terminal_cursor(40, 0))));
+
+        pane.process_command(&mut reader, Command::Input('x'))
+            .unwrap();
+        let mut buf = String::new();
+        pane.draw(&mut buf).unwrap();
+        assert!(buf.contains(&format!("{}xboth", move_terminal_cursor(0, 0))));
+        assert_eq!((41, 0), pane.get_terminal_cursor());
+        pane.focus_next_pane();
+        assert_eq!((1, 0), pane.get_terminal_cursor());
+        let mut buf = String::new();
+        pane.draw(&mut buf).unwrap();
+        assert!(buf.contains(&format!("{}xboth", move_terminal_cursor(40, 0))));
+        pane.focus_next_pane();
+
+        pane.screen().buffer().load_string("left\r\n".to_string());
+        pane.screens.push(Screen::new());
+        pane.next_buffer();
+        pane.screen().buffer().load_string("right\r\n".to_string());
+        assert_eq!((2, 2), pane.buffer_position());
+
+        let mut buf = String::new();
+        pane.draw(&mut buf).unwrap();
+
+        assert!(buf.contains(&format!("{}left", move_terminal_cursor(0, 0))));
+        let left_row = format!(
+            "{}\x1b[34m~\x1b[39m{}\r\n",
+            move_terminal_cursor(0, 1),
+            " ".repeat(38)
+        );
+        assert!(buf.contains(&left_row), "{:?}", buf);
+        assert!(buf.contains(&format!("{}right", move_terminal_cursor(40, 0))));
+        let right_row = format!(
+            "{}\x1b[34m~\x1b[39m{}",
+            move_terminal_cursor(40, 1),
+            ESCAPE_SEQUENCE_CLEAR_LINE
+        );
+        assert!(buf.contains(&right_row), "{:?}", buf);
+        for row in 0..22 {
+            let separator = format!("{}│", move_terminal_cursor(39, row));
+            assert!(buf.contains(&separator), "row={}", row);
+        }
+
+        assert_eq!((40, 0), pane.get_terminal_cursor());
+        pane.process_command(&mut reader, Command::FocusNextPane)
+            .unwrap();
+        assert_eq!((1, 0), pane.get_terminal_cursor());
+        assert_eq!(Some("left".to_string()), pane.screen().buffer().get_line(0));
+    }
+
+    #[test]
+    fn test_switch_buffers() {
+        let first = temp_path("switch_first.txt");
+        let second = temp_path("switch_second.txt");
+        fs::write(&first, "hello\nworld\n").unwrap();
+        fs::write(&second, "foo\nbar\nbaz\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.open_buffer(first.clone()).unwrap();
+        assert_eq!((1, 1), pane.buffer_position());
+        pane.screen().set_cursor(2, 1);
+        pane.screen().insert_char('x');
+
+        pane.open_buffer(second.clone()).unwrap();
+        assert_eq!((2, 2), pane.buffer_position());
+        pane.screen().set_cursor(1, 2);
+
+        let mut reader = BufReader::new("".as_bytes());
+        pane.process_command(&mut reader, Command::NextBuffer)
+            .unwrap();
+        assert_eq!((1, 2), pane.buffer_position());
+        assert_eq!(Some(first.clone()), pane.screen().buffer().get_filepath());
+        assert_eq!((3, 1), pane.screen().cursor());
+        assert!(pane.screen().buffer().is_dirty());
+
+        pane.process_command(&mut reader, Command::NextBuffer)
+            .unwrap();
+        assert_eq!((2, 2), pane.buffer_position());
+        assert_eq!(Some(second.clone()), pane.screen().buffer().get_filepath());
+        assert_eq!((1, 2), pane.screen().cursor());
+        assert!(!pane.screen().buffer().is_dirty());
+
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn test_new_buffers_keep_buffer_settings() {
+        let first = temp_path("backup_first.txt");
+        let second = temp_path("backup_second.txt");
+        fs::write(&first, "a\n").unwrap();
+        fs::write(&second, "b\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.screen().buffer().set_backup_on_save(true);
+        pane.screen().buffer().set_refuse_binary(true);
+        pane.open_buffer(first.clone()).unwrap();
+        pane.open_buffer(second.clone()).unwrap();
+        assert_eq!((2, 2), pane.buffer_position());
+        assert!(pane.screen().buffer().backup_on_save());
+        assert!(pane.screen().buffer().refuse_binary());
+
+        pane.toggle_hex().unwrap();
+        assert_eq!((3, 3), pane.buffer_position());
+        assert!(pane.screen().buffer().backup_on_save());
+
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_command_home_end() {
+        let pane = initialize_pane();
+        assert_eq!(
+            Command::Home,
+            pane.resolve_command(Key::ControlSequence('a'))
+        );
+        assert_eq!(
+            Command::End,
+            pane.resolve_command(Key::ControlSequence('e'))
+        );
+        assert_eq!(Command::Home, pane.resolve_command(Key::Home));
+        assert_eq!(Command::End, pane.resolve_command(Key::End));
+    }
+
+    #[test]
+    fn test_resolve_command_rebind() {
+        let mut pane = initialize_pane();
+        assert_eq!(
+            Command::DeleteWord,
+            pane.resolve_command(Key::ControlSequence('w'))
+        );
+
+        let mut keymap = KeyMap::default();
+        keymap.bind(Key::ControlSequence('w'), Command::Exit);
+        pane.set_keymap(keymap);
+        assert_eq!(
+            Command::Exit,
+            pane.resolve_command(Key::ControlSequence('w'))
+        );
+        assert_eq!(
+            Command::Exit,
+            pane.resolve_command(Key::ControlSequence('q'))
+        );
+        assert_eq!(
+            Command::Input('w'),
+            pane.resolve_command(Key::NormalKey('w'))
+        );
+    }
+
+    #[test]
+    fn test_exit_clean_buffer() {
+        let mut pane = initialize_pane();
+        let mut reader = ScriptedInput::default();
+        let message = pane.message_bar.get_message(SystemTime::now());
+
+        assert!(pane.process_command(&mut reader, Command::Exit).is_err());
+        assert_eq!(message, pane.message_bar.get_message(SystemTime::now()));
+    }
+
+    #[test]
+    fn test_exit_dirty_buffer() {
+        let mut pane = initialize_pane();
+        let mut reader = ScriptedInput::default();
+        pane.process_command(&mut reader, Command::Input('a'))
+            .unwrap();
+
+        for remaining in (1..=QUIT_TIMES).rev() {
+            assert!(pane.process_command(&mut reader, Command::Exit).is_ok());
+            let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+            assert_eq!(
+                format!(
+                    "WARNING!!! File has unsaved changes. Press Ctrl+Q {} more times to quit.",
+                    remaining
+                ),
+                message
+            );
+        }
+        assert!(pane.process_command(&mut reader, Command::Exit).is_err());
+
+        // Any other command starts the countdown over.
+        pane.process_command(&mut reader, Command::ArrowLeft)
+            .unwrap();
+        assert!(pane.process_command(&mut reader, Command::Exit).is_ok());
+    }
+
+    #[test]
+    fn test_force_quit() {
+        let mut pane = initialize_pane();
+        pane.screen().insert_char('a');
+        let mut reader = BufReader::new("".as_bytes());
+
+        let err = pane
+            .process_command(&mut reader, Command::ForceQuit)
+            .unwrap_err();
+        assert_eq!("exit", err.to_string());
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert!(!message.starts_with("WARNING"), "{}", message);
+    }
+
+    #[test]
+    fn test_save_and_quit() {
+        let path = temp_path("save_and_quit.txt");
+        fs::write(&path, "abc\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.open_buffer(path.clone()).unwrap();
+        pane.screen().insert_char('x');
+        let mut reader = BufReader::new("".as_bytes());
+        let err = pane
+            .process_command(&mut reader, Command::SaveAndQuit)
+            .unwrap_err();
+        assert_eq!("exit", err.to_string());
+        assert_eq!("xabc\n", fs::read_to_string(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+        let dir = temp_path("save_and_quit_missing");
+        pane.screen().buffer().load_string("abc".to_string());
+        let input = format!("{}/file.txt\r", "\x7f".repeat(12) + &dir);
+        let mut reader = BufReader::new(input.as_bytes());
+        pane.process_command(&mut reader, Command::SaveAndQuit)
+            .unwrap();
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert!(message.starts_with("Can't save!"), "{}", message);
+    }
+
+    #[test]
+    fn test_save_and_quit_other_dirty_buffer() {
+        let path = temp_path("save_and_quit_first.txt");
+        let other = temp_path("save_and_quit_second.txt");
+        fs::write(&path, "abc\n").unwrap();
+        fs::write(&other, "def\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.open_buffer(other.clone()).unwrap();
+        pane.screen().insert_char('y');
+        pane.open_buffer(path.clone()).unwrap();
+        pane.screen().insert_char('x');
+
+        let mut reader = BufReader::new("".as_bytes());
+        pane.process_command(&mut reader, Command::SaveAndQuit)
+            .unwrap();
+        assert_eq!("xabc\n", fs::read_to_string(&path).unwrap());
+        assert_eq!("def\n", fs::read_to_string(&other).unwrap());
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert!(message.starts_with("WARNING!!!"), "{}", message);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&other).unwrap();
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut pane = initialize_pane();
+
+        let mut reader = BufReader::new("y\r".as_bytes());
+        assert!(pane.confirm(&mut reader, "Continue? (y/n) "));
+
+        let mut reader = BufReader::new("Y\r".as_bytes());
+        assert!(pane.confirm(&mut reader, "Continue? (y/n) "));
+
+        let mut reader = BufReader::new("n\r".as_bytes());
+        assert!(!pane.confirm(&mut reader, "Continue? (y/n) "));
+
+        let mut reader = BufReader::new("\x1b\x1b".as_bytes());
+        assert!(!pane.confirm(&mut reader, "Continue? (y/n) "));
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert_eq!("aborted", message);
+
+        let mut reader = BufReader::new("\r".as_bytes());
+        assert!(!pane.confirm(&mut reader, "Continue? (y/n) "));
+
+        // Keys that aren't an answer leave the prompt up.
+        let mut reader =
+            ScriptedInput::from(vec![Key::ArrowUp, Key::Backspace, Key::NormalKey('y')]);
+        assert!(pane.confirm(&mut reader, "Continue? (y/n) "));
+
+        // The answer is a single key; Enter isn't needed.
+        let mut reader = ScriptedInput::from(vec![Key::NormalKey('y'), Key::NormalKey('z')]);
+        assert!(pane.confirm(&mut reader, "Continue? (y/n) "));
+        assert_eq!(Ok(Key::NormalKey('z')), reader.next_key().map_err(|_| ()));
+    }
+
+    #[test]
+    fn test_process_revert_command() {
+        let path = temp_path("revert.txt");
+        fs::write(&path, "hello\nworld\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.screen().buffer().load_file(path.clone()).unwrap();
+        pane.screen().set_cursor(0, 1);
+        pane.screen().insert_new_line();
+        pane.screen().insert_new_line();
+        pane.screen().insert_char('a');
+        assert!(pane.screen().buffer().is_dirty());
+
+        let mut reader = BufReader::new("y\r".as_bytes());
+        pane.process_command(&mut reader, Command::Revert).unwrap();
+
+        assert_eq!(2, pane.screen().buffer().len());
+        assert_eq!(
+            Some("hello".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+        assert_eq!(
+            Some("world".to_string()),
+            pane.screen().buffer().get_line(1)
+        );
+        assert!(!pane.screen().buffer().is_dirty());
+        assert_eq!((0, 2), pane.screen().cursor());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_process_revert_command_without_filepath() {
+        let mut pane = initialize_pane();
+        pane.screen().insert_char('a');
+
+        let mut reader = BufReader::new("".as_bytes());
+        pane.process_command(&mut reader, Command::Revert).unwrap();
+
+        assert_eq!(Some("a".to_string()), pane.screen().buffer().get_line(0));
+        assert_eq!(
+            Some("No file to revert".to_string()),
+            pane.message_bar.get_message(SystemTime::now())
+        );
+    }
+
+    #[test]
+    fn test_feed_keys() {
+        let path = temp_path("feed_keys.txt");
+        let _ = fs::remove_file(&path);
+        let mut pane = initialize_pane();
+        let typed = |text: &str| text.chars().map(Key::NormalKey).collect::<Vec<Key>>();
+
+        let mut keys = typed("hello");
+        keys.push(Key::Enter);
+        keys.extend(typed("world"));
+        keys.push(Key::ControlSequence('f'));
+        keys.extend(typed("ell"));
+        keys.push(Key::Enter);
+        keys.push(Key::ControlSequence('k'));
+        keys.push(Key::ControlSequence('s'));
+        // Clear the suggested "untitled.txt" before typing the path.
+        keys.extend(std::iter::repeat_n(Key::Backspace, "untitled.txt".len()));
+        keys.extend(typed(&path));
+        keys.push(Key::Enter);
+        assert!(!pane.feed_keys(&keys));
+
+        assert_eq!(Some("h".to_string()), pane.screen().buffer().get_line(0));
+        assert_eq!(
+            Some("world".to_string()),
+            pane.screen().buffer().get_line(1)
+        );
+        assert_eq!((1, 0), pane.screen().cursor());
+        assert_eq!("h\nworld\n", fs::read_to_string(&path).unwrap());
+        assert!(pane.feed_keys(&[Key::ControlSequence('q'), Key::NormalKey('x')]));
+        assert_eq!(Some("h".to_string()), pane.screen().buffer().get_line(0));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_process_find_command_scripted() {
+        let mut pane = initialize_pane();
+        pane.screen()
+            .buffer()
+            .load_string("hello\r\nworld\r\nword\r\n".to_string());
+
+        let mut input = ScriptedInput::from(vec![
+            Key::NormalKey('w'),
+            Key::NormalKey('o'),
+            Key::ArrowDown,
+            Key::Enter,
+        ]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((0, 2), pane.screen().cursor());
+
+        let mut input = ScriptedInput::from(vec![Key::NormalKey('l'), Key::Escape]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((0, 2), pane.screen().cursor());
+    }
+
+    #[test]
+    fn test_process_find_command_seeded() {
+        let mut pane = initialize_pane();
+        pane.screen()
+            .buffer()
+            .load_string("foo bar\r\nfoobar foo\r\n".to_string());
+        pane.screen().set_cursor(1, 0);
+
+        let mut input = ScriptedInput::from(vec![Key::ControlSequence('w'), Key::Enter]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((7, 1), pane.screen().cursor());
+
+        let mut input = ScriptedInput::from(vec![Key::Enter]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((0, 0), pane.screen().cursor());
+
+        let mut input = ScriptedInput::from(vec![Key::NormalKey('b'), Key::Enter]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((4, 0), pane.screen().cursor());
+
+        let mut input = ScriptedInput::from(vec![Key::Escape]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((4, 0), pane.screen().cursor());
+    }
+
+    #[test]
+    fn test_process_find_command_empty_buffer() {
+        let mut pane = initialize_pane();
+
+        let mut input = ScriptedInput::from(vec![
+            Key::NormalKey('a'),
+            Key::ArrowUp,
+            Key::ArrowUp,
+            Key::NormalKey('b'),
+            Key::ArrowDown,
+            Key::ArrowDown,
+            Key::Enter,
+        ]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((0, 0), pane.screen().cursor());
+        assert!(pane.screen().buffer().is_empty());
+    }
+
+    #[test]
+    fn test_process_find_command_wraps() {
+        let mut pane = initialize_pane();
+        pane.screen()
+            .buffer()
+            .load_string("needle\r\nhay\r\nhay\r\n".to_string());
+        pane.screen().set_cursor(1, 2);
+
+        let mut input =
+            ScriptedInput::from(vec![Key::NormalKey('n'), Key::NormalKey('e'), Key::Enter]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((0, 0), pane.screen().cursor());
+    }
+
+    #[test]
+    fn test_process_find_command_options() {
+        let mut pane = initialize_pane();
+        pane.screen()
+            .buffer()
+            .load_string("foobar\r\nFoo\r\n".to_string());
+
+        let mut input = ScriptedInput::from(vec![
+            Key::ControlSequence('w'),
+            Key::ControlSequence('i'),
+            Key::NormalKey('f'),
+            Key::NormalKey('o'),
+            Key::NormalKey('o'),
+            Key::Enter,
+        ]);
+        pane.process_command(&mut input, Command::Find).unwrap();
+        assert_eq!((0, 1), pane.screen().cursor());
+    }
+
+    #[test]
+    fn test_toggle_hex() {
+        let mut pane = initialize_pane();
+        let mut reader = BufReader::new("".as_bytes());
+        pane.screen().insert_char('a');
+
+        pane.process_command(&mut reader, Command::ToggleHex)
+            .unwrap();
+        assert_eq!((2, 2), pane.buffer_position());
+        assert_eq!(Some(hex_row(0, b"a\n")), pane.screen().buffer().get_line(0));
+        assert!(pane.screen().buffer().is_read_only());
+
+        pane.process_command(&mut reader, Command::ToggleHex)
+            .unwrap();
+        assert_eq!((1, 2), pane.buffer_position());
+        pane.screen().insert_char('b');
+        pane.process_command(&mut reader, Command::ToggleHex)
+            .unwrap();
+        assert_eq!((2, 2), pane.buffer_position());
+        assert_eq!(
+            Some(hex_row(0, b"ab\n")),
+            pane.screen().buffer().get_line(0)
+        );
+    }
+
+    #[test]
+    fn test_kill_ring_appends_consecutive_kills() {
+        let mut pane = initialize_pane();
+        let mut reader = BufReader::new("".as_bytes());
+        pane.screen()
+            .buffer()
+            .load_string("foo bar baz\r\nqux\r\n".to_string());
+        pane.screen().set_cursor(7, 0);
+
+        pane.process_command(&mut reader, Command::DeleteToEol)
+            .unwrap();
+        pane.process_command(&mut reader, Command::DeleteWord)
+            .unwrap();
+        pane.process_command(&mut reader, Command::DeleteWord)
+            .unwrap();
+        assert_eq!(1, pane.kill_ring.len());
+        assert_eq!("foo bar baz", pane.kill_ring[0]);
+
+        pane.process_command(&mut reader, Command::ArrowDown)
+            .unwrap();
+        pane.process_command(&mut reader, Command::DeleteToEol)
+            .unwrap();
+        assert_eq!(
+            VecDeque::from(["qux".to_string(), "foo bar baz".to_string()]),
+            pane.kill_ring
+        );
+    }
+
+    #[test]
+    fn test_multiple_cursors() {
+        let mut pane = initialize_pane();
+        let mut reader = BufReader::new("".as_bytes());
+        pane.screen()
+            .buffer()
+            .load_string("abc\r\ndef\r\nghi\r\n".to_string());
+        pane.screen().set_cursor(1, 0);
+
+        pane.process_command(&mut reader, Command::AddCursorBelow)
+            .unwrap();
+        pane.process_command(&mut reader, Command::Input('x'))
+            .unwrap();
+        pane.process_command(&mut reader, Command::Delete).unwrap();
+        assert_eq!(Some("axc".to_string()), pane.screen().buffer().get_line(0));
+        assert_eq!(Some("dxf".to_string()), pane.screen().buffer().get_line(1));
+        assert_eq!(vec![(2, 0), (2, 1)], pane.screen().cursors());
+
+        pane.process_command(&mut reader, Command::Escape).unwrap();
+        assert_eq!(vec![(2, 0)], pane.screen().cursors());
+    }
+
+    #[test]
+    fn test_yank_pop() {
+        let mut pane = initialize_pane();
+        let mut reader = BufReader::new("".as_bytes());
+        pane.screen()
+            .buffer()
+            .load_string("one\r\ntwo\r\n\r\n".to_string());
+        pane.process_command(&mut reader, Command::DeleteToEol)
+            .unwrap();
+        pane.process_command(&mut reader, Command::ArrowDown)
+            .unwrap();
+        pane.process_command(&mut reader, Command::DeleteToEol)
+            .unwrap();
+        pane.process_command(&mut reader, Command::ArrowDown)
+            .unwrap();
+
+        pane.process_command(&mut reader, Command::Yank).unwrap();
+        assert_eq!(Some("two".to_string()), pane.screen().buffer().get_line(2));
+        pane.process_command(&mut reader, Command::YankPop).unwrap();
+        assert_eq!(Some("one".to_string()), pane.screen().buffer().get_line(2));
+        assert_eq!((3, 2), pane.screen().cursor());
+        pane.process_command(&mut reader, Command::YankPop).unwrap();
+        assert_eq!(Some("two".to_string()), pane.screen().buffer().get_line(2));
+
+        pane.process_command(&mut reader, Command::ArrowLeft)
+            .unwrap();
+        pane.process_command(&mut reader, Command::YankPop).unwrap();
+        assert_eq!(Some("two".to_string()), pane.screen().buffer().get_line(2));
+    }
+
+    #[test]
+    fn test_prompt_initial() {
+        let mut pane = initialize_pane();
+        let mut callback = |_: &str, _: Key, _: &mut Screen| None;
+        let mut input = ScriptedInput::from(vec![
+            Key::Backspace,
+            Key::Backspace,
+            Key::Backspace,
+            Key::NormalKey('m'),
+            Key::NormalKey('d'),
+            Key::Enter,
+        ]);
+        let answer = pane
+            .prompt(&mut input, "Save as: ", "untitled.txt", &mut callback)
+            .unwrap();
+        assert_eq!("untitled.md", answer);
+    }
+
+    #[test]
+    fn test_suggest_filepath() {
+        let mut pane = initialize_pane();
+        assert_eq!("untitled.txt", pane.suggest_filepath());
+
+        let path = temp_path("sibling.txt");
+        fs::write(&path, "").unwrap();
+        pane.split_vertical();
+        pane.open_buffer(path.clone()).unwrap();
+        let expected = std::path::Path::new(&path).with_file_name("untitled.txt");
+        assert_eq!(expected.to_string_lossy(), pane.suggest_filepath());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_insert_unicode() {
+        let mut pane = initialize_pane();
+        pane.screen().buffer().load_string("ab\r\n".to_string());
+        pane.screen().set_cursor(1, 0);
+        let insert = |pane: &mut Pane, answer: &str| {
+            let mut keys: Vec<Key> = answer.chars().map(Key::NormalKey).collect();
+            keys.push(Key::Enter);
+            pane.process_command(&mut ScriptedInput::from(keys), Command::InsertUnicode)
+                .unwrap();
+        };
+
+        insert(&mut pane, "2603");
+        assert_eq!(
+            Some("a\u{2603}b".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+        assert_eq!((4, 0), pane.screen().cursor());
+
+        insert(&mut pane, "1F600");
+        assert_eq!(
+            Some("a\u{2603}\u{1F600}b".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+        assert_eq!((8, 0), pane.screen().cursor());
+
+        insert(&mut pane, "D800");
+        assert_eq!(
+            Some("a\u{2603}\u{1F600}b".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert_eq!("Invalid code point: D800", message);
+    }
+
+    #[test]
+    fn test_snippet_expansion() {
+        let mut pane = initialize_pane();
+        let mut snippets = Snippets::default();
+        snippets.insert("inc".to_string(), "#include <$0>".to_string());
+        pane.set_snippets(snippets);
+        pane.screen()
+            .buffer()
+            .load_string("inc\r\nfoo\r\n".to_string());
+        pane.screen().set_cursor(3, 0);
+
+        let mut reader = ScriptedInput::default();
+        pane.process_command(&mut reader, Command::Indent).unwrap();
+        assert_eq!(
+            Some("#include <>".to_string()),
+            pane.screen().buffer().get_line(0)
+        );
+        assert_eq!((10, 0), pane.screen().cursor());
+
+        pane.screen().set_cursor(3, 1);
+        pane.process_command(&mut reader, Command::Indent).unwrap();
+        assert_eq!(
+            Some("\tfoo".to_string()),
+            pane.screen().buffer().get_line(1)
+        );
+    }
+
+    #[test]
+    fn test_check_indent() {
+        let mut pane = initialize_pane();
+        let mut reader = ScriptedInput::default();
+        pane.screen()
+            .buffer()
+            .load_string("\ta\n\t b\n  \tc\n".to_string());
+        pane.process_command(&mut reader, Command::CheckIndent)
+            .unwrap();
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert_eq!("2 line(s) mix tabs and spaces, first at line 2", message);
+
+        pane.screen().buffer().load_string("\ta\n".to_string());
+        pane.process_command(&mut reader, Command::CheckIndent)
+            .unwrap();
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert_eq!("No mixed indentation", message);
+    }
+
+    #[test]
+    fn test_header_source_candidates() {
+        assert_eq!(vec!["src/a.h"], header_source_candidates("src/a.c"));
+        assert_eq!(
+            vec!["a.c", "a.cpp", "a.cc"],
+            header_source_candidates("a.h")
+        );
+        assert_eq!(
+            vec!["a.hpp", "a.h", "a.hh"],
+            header_source_candidates("a.cpp")
+        );
+        assert_eq!(
+            vec!["a.cpp", "a.cc", "a.cxx"],
+            header_source_candidates("a.hpp")
+        );
+        assert!(header_source_candidates("a.rs").is_empty());
+        assert!(header_source_candidates("Makefile").is_empty());
+    }
+
+    #[test]
+    fn test_toggle_header_source() {
+        let source = temp_path("toggle.c");
+        let header = temp_path("toggle.h");
+        fs::write(&source, "int x;\n").unwrap();
+        fs::write(&header, "extern int x;\n").unwrap();
+
+        let mut pane = initialize_pane();
+        pane.open_buffer(source.clone()).unwrap();
+        let mut reader = ScriptedInput::default();
+        pane.process_command(&mut reader, Command::ToggleHeaderSource)
+            .unwrap();
+        assert_eq!(Some(header.clone()), pane.screen().buffer().get_filepath());
+        pane.process_command(&mut reader, Command::ToggleHeaderSource)
+            .unwrap();
+        assert_eq!(Some(source.clone()), pane.screen().buffer().get_filepath());
+        assert_eq!(2, pane.screens.len());
+
+        // A counterpart already shown in the other column gets the focus instead.
+        pane.split_vertical();
+        pane.process_command(&mut reader, Command::ToggleHeaderSource)
+            .unwrap();
+        assert_eq!(vec![0, 1], pane.panes);
+        pane.process_command(&mut reader, Command::ToggleHeaderSource)
+            .unwrap();
+        assert_eq!(vec![0, 1], pane.panes);
+        assert_eq!(0, pane.focus);
+
+        fs::remove_file(&header).unwrap();
+        pane.process_command(&mut reader, Command::ToggleHeaderSource)
+            .unwrap();
+        let message = pane.message_bar.get_message(SystemTime::now()).unwrap();
+        assert_eq!(format!("No header or source for {}", source), message);
+
+        fs::remove_file(&source).unwrap();
+    }
+
+    #[test]
+    fn test_parse_code_point() {
+        assert_eq!(Some('\u{2603}'), parse_code_point("2603"));
+        assert_eq!(Some('\u{1F600}'), parse_code_point("U+1f600"));
+        assert_eq!(Some('A'), parse_code_point("0x41"));
+        assert_eq!(None, parse_code_point("D800"));
+        assert_eq!(None, parse_code_point("110000"));
+        assert_eq!(None, parse_code_point("+41"));
+        assert_eq!(None, parse_code_point(""));
+    }
+
+    #[test]
+    fn test_humanize_bytes() {
+        assert_eq!("0 bytes", humanize_bytes(0));
+        assert_eq!("999 bytes", humanize_bytes(999));
+        assert_eq!("1.0 KiB", humanize_bytes(1024));
+        assert_eq!("1.4 MiB", humanize_bytes(1_500_000));
+    }
+}
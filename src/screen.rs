@@ -1,14 +1,30 @@
-use crate::buffer::EditorBuffer;
+use crate::buffer::{
+    is_separator, EditorBuffer, FileType, Highlight, Indent, RenderOptions, SearchOptions,
+};
 use crate::escape_sequence::{
-    move_terminal_cursor, ESCAPE_SEQUENCE_CLEAR_LINE, ESCAPE_SEQUENCE_HIDE_CURSOR,
+    move_terminal_cursor, Color, ESCAPE_SEQUENCE_CLEAR_LINE, ESCAPE_SEQUENCE_HIDE_CURSOR,
     ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION, ESCAPE_SEQUENCE_SHOW_CURSOR,
+    ESCAPE_SEQUENCE_STYLE_RESET, ESCAPE_SEQUENCE_STYLE_REVERSE,
 };
 use crate::pane::Pane;
+use crate::theme::Theme;
 use crate::ui::{Component, Drawable};
-use crate::KILO_VERSION;
-use std::io::{stdout, Error, Write};
+use crate::{KILO_VERSION, MINIMAP_COLUMNS, SCROLL_COLUMNS, TAB_STOP};
+use std::cell::RefCell;
+use std::io::Error;
+use std::ops::Range;
+
+/// Which numbers the gutter left of the text shows, if any.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum LineNumbers {
+    #[default]
+    Off,
+    Absolute,
+    /// Distances from the cursor line, which shows its own absolute number.
+    Relative,
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Screen {
     component: Component,
     buffer: EditorBuffer,
@@ -17,6 +33,30 @@ pub struct Screen {
     rx: usize,
     offset_x: usize,
     offset_y: usize,
+    theme: Theme,
+    show_whitespace: bool,
+    show_scrollbar: bool,
+    show_minimap: bool,
+    pad_rows: bool,
+    rulers: Vec<usize>,
+    line_numbers: LineNumbers,
+    /// Render columns past which lines are cut off instead of scrolled to.
+    max_line_length: Option<usize>,
+    smooth_scroll: bool,
+    /// Where `offset_y` is heading while a smooth scroll is drawn.
+    scroll_target: Option<usize>,
+    hex_source: Option<usize>,
+    anchor: Option<(usize, usize)>,
+    /// Whether the selection is a rectangle of render columns rather than a run of text.
+    block_selection: bool,
+    /// Cursors besides the primary one at `cx`/`cy`, each on a line of its own.
+    extra_cursors: Vec<(usize, usize)>,
+    /// The current search match, marked while the cursor is in the search prompt.
+    search_marker: Option<(usize, usize)>,
+    /// The cursor as it was when the view was last scrolled sideways by hand. `adjust`
+    /// leaves `offset_x` alone until the cursor moves.
+    scrolled_at: Option<(usize, usize)>,
+    last_rows: RefCell<Vec<String>>,
 }
 
 impl Screen {
@@ -29,6 +69,23 @@ impl Screen {
             rx: 0,
             offset_x: 0,
             offset_y: 0,
+            theme: Theme::default(),
+            show_whitespace: false,
+            show_scrollbar: false,
+            show_minimap: false,
+            pad_rows: false,
+            rulers: Vec::new(),
+            line_numbers: LineNumbers::Off,
+            max_line_length: None,
+            smooth_scroll: false,
+            scroll_target: None,
+            hex_source: None,
+            anchor: None,
+            block_selection: false,
+            extra_cursors: Vec::new(),
+            search_marker: None,
+            scrolled_at: None,
+            last_rows: RefCell::new(Vec::new()),
         }
     }
 
@@ -40,9 +97,129 @@ impl Screen {
         (self.cx, self.cy)
     }
 
+    /// Moves the cursor to `(x, y)`, clamped by `clamp_cursor`.
     pub fn set_cursor(&mut self, x: usize, y: usize) {
         self.cx = x;
         self.cy = y;
+        self.clamp_cursor();
+    }
+
+    /// Brings the cursor back into the buffer: `cy` to the virtual line just past the last
+    /// line at most, and `cx` to the end of its line and down to a char boundary. While the
+    /// buffer is still loading, a `cy` past the loaded lines is left alone, since the line
+    /// may yet arrive.
+    pub fn clamp_cursor(&mut self) {
+        if self.buffer.is_fully_loaded() && self.cy > self.buffer.len() {
+            self.cy = self.buffer.len();
+        }
+        match self.buffer.get_line(self.cy) {
+            Some(line) => self.cx = line.floor_char_boundary(self.cx),
+            None => self.cx = 0,
+        }
+    }
+
+    /// All the cursors, the primary one first.
+    pub fn cursors(&self) -> Vec<(usize, usize)> {
+        let mut cursors = vec![(self.cx, self.cy)];
+        cursors.extend_from_slice(&self.extra_cursors);
+        cursors
+    }
+
+    /// Adds a cursor at the primary cursor's column on the line below the lowest cursor.
+    pub fn add_cursor_below(&mut self) {
+        let cy = self
+            .cursors()
+            .iter()
+            .map(|&(_, y)| y)
+            .max()
+            .unwrap_or(self.cy)
+            + 1;
+        self.add_cursor(cy);
+    }
+
+    /// Adds a cursor at the primary cursor's column on the line above the highest cursor.
+    pub fn add_cursor_above(&mut self) {
+        let top = self
+            .cursors()
+            .iter()
+            .map(|&(_, y)| y)
+            .min()
+            .unwrap_or(self.cy);
+        if let Some(cy) = top.checked_sub(1) {
+            self.add_cursor(cy);
+        }
+    }
+
+    /// Adds a cursor on line `cy` at the primary cursor's render column, so that it lines up
+    /// on screen and stays on a char boundary when the lines hold tabs or multibyte chars.
+    fn add_cursor(&mut self, cy: usize) {
+        if self.buffer.get_line(cy).is_none() {
+            return;
+        }
+        let rx = self.buffer.cx_to_rx(self.cx, self.cy);
+        let cx = self.buffer.rx_to_byte(rx, cy);
+        self.extra_cursors.push((cx, cy));
+    }
+
+    pub fn set_search_marker(&mut self, search_marker: Option<(usize, usize)>) {
+        self.search_marker = search_marker;
+    }
+
+    /// Drops every cursor but the primary one.
+    pub fn collapse_cursors(&mut self) {
+        self.extra_cursors.clear();
+    }
+
+    /// Runs `edit` with each cursor in turn as the primary one. The edits must stay within
+    /// the cursor's line so that the other cursors keep pointing at the same text.
+    fn at_each_cursor(&mut self, edit: impl Fn(&mut Screen)) {
+        let mut moved = Vec::new();
+        for (cx, cy) in self.cursors() {
+            self.cx = cx;
+            self.cy = cy;
+            edit(self);
+            moved.push((self.cx, self.cy));
+        }
+        (self.cx, self.cy) = moved[0];
+        self.extra_cursors = moved.split_off(1);
+    }
+
+    /// Inserts `c` at every cursor.
+    pub fn insert_char_at_cursors(&mut self, c: char) {
+        self.at_each_cursor(|screen| screen.insert_char(c));
+    }
+
+    /// Deletes the char before every cursor. With several cursors, those at the start of a
+    /// line stay put rather than joining it to the line above.
+    pub fn delete_char_at_cursors(&mut self) {
+        if self.extra_cursors.is_empty() {
+            return self.delete_char();
+        }
+        self.at_each_cursor(|screen| {
+            if screen.cx > 0 {
+                screen.delete_char();
+            }
+        });
+    }
+
+    /// Deletes the char under every cursor. With several cursors, those at the end of a line
+    /// stay put rather than joining the next line to it.
+    pub fn delete_forward_at_cursors(&mut self) {
+        if self.extra_cursors.is_empty() {
+            self.right();
+            return self.delete_char();
+        }
+        self.at_each_cursor(|screen| {
+            if screen.cx
+                < screen
+                    .buffer
+                    .get_line(screen.cy)
+                    .map_or(0, |line| line.len())
+            {
+                screen.right();
+                screen.delete_char();
+            }
+        });
     }
 
     pub fn offset(&self) -> (usize, usize) {
@@ -56,10 +233,204 @@ impl Screen {
 
     pub fn set_size(&mut self, x: usize, y: usize, width: usize, height: usize) {
         self.component.set_size(x, y, width, height);
+        self.force_full_redraw();
+    }
+
+    pub fn force_full_redraw(&mut self) {
+        self.last_rows.borrow_mut().clear();
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Pads rows with spaces instead of clearing to the end of the line, so that a
+    /// screen with something drawn to its right doesn't erase it.
+    pub fn set_pad_rows(&mut self, pad_rows: bool) {
+        self.pad_rows = pad_rows;
+        self.force_full_redraw();
+    }
+
+    /// The index of the screen this one shows a hex dump of, if it is a hex view.
+    pub fn hex_source(&self) -> Option<usize> {
+        self.hex_source
+    }
+
+    pub fn set_hex_source(&mut self, hex_source: Option<usize>) {
+        self.hex_source = hex_source;
+    }
+
+    pub fn rulers(&self) -> &[usize] {
+        &self.rulers
+    }
+
+    /// Sets the 1-based render columns highlighted as rulers.
+    pub fn set_rulers(&mut self, rulers: Vec<usize>) {
+        self.rulers = rulers;
+        self.force_full_redraw();
+    }
+
+    pub fn line_numbers(&self) -> LineNumbers {
+        self.line_numbers
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.line_numbers = line_numbers;
+        self.force_full_redraw();
+    }
+
+    pub fn max_line_length(&self) -> Option<usize> {
+        self.max_line_length
+    }
+
+    /// Caps the drawn part of each line at `max_line_length` columns, marking longer lines
+    /// with `→`. Capped lines don't scroll sideways and the cursor stays within the cap.
+    pub fn set_max_line_length(&mut self, max_line_length: Option<usize>) {
+        self.max_line_length = max_line_length;
+        self.offset_x = 0;
+        self.force_full_redraw();
+    }
+
+    pub fn smooth_scroll(&self) -> bool {
+        self.smooth_scroll
+    }
+
+    /// Makes jumps of more than a screenful scroll there over a few frames instead of at once.
+    pub fn set_smooth_scroll(&mut self, smooth_scroll: bool) {
+        self.smooth_scroll = smooth_scroll;
+        self.finish_scroll();
+    }
+
+    /// Starts a smooth scroll if a command has moved `offset_y` more than a screenful away
+    /// from `offset_y_before`, putting the view back there to step it toward the new offset.
+    pub fn animate_from(&mut self, offset_y_before: usize) {
+        if self.smooth_scroll && offset_y_before.abs_diff(self.offset_y) > self.component.height() {
+            self.scroll_target = Some(self.offset_y);
+            self.offset_y = offset_y_before;
+        }
+    }
+
+    pub fn is_scrolling(&self) -> bool {
+        self.scroll_target.is_some()
+    }
+
+    /// Moves the view one frame further through a smooth scroll.
+    pub fn step_scroll(&mut self) {
+        if let Some(target) = self.scroll_target {
+            self.offset_y = scroll_step(self.offset_y, target);
+            if self.offset_y == target {
+                self.scroll_target = None;
+            }
+        }
+    }
+
+    /// Jumps to the end of a smooth scroll, e.g. before the next key is handled.
+    pub fn finish_scroll(&mut self) {
+        if let Some(target) = self.scroll_target.take() {
+            self.offset_y = target;
+        }
+    }
+
+    /// The columns of text shown before the `→` of a line that is too long, kept one short
+    /// of the text width so the arrow fits.
+    fn line_limit(&self) -> Option<usize> {
+        self.max_line_length
+            .map(|max| max.min(self.text_width().saturating_sub(1)))
+    }
+
+    /// The width of the line number gutter: the digits, then the theme's separator.
+    fn gutter_width(&self) -> usize {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        self.buffer.line_count_digits() + self.theme.gutter_separator().chars().count()
+    }
+
+    /// The gutter for row `file_line_no`, without a number past the end of the buffer. The
+    /// cursor line's number takes the `CurrentLineNumber` color unless that is the default.
+    fn gutter(&self, file_line_no: usize) -> String {
+        let width = self.buffer.line_count_digits();
+        let separator = self.theme.gutter_separator();
+        let number = match self.line_numbers {
+            LineNumbers::Off => return String::new(),
+            _ if file_line_no >= self.buffer.len() => {
+                return format!("{}{}", " ".repeat(width), separator)
+            }
+            LineNumbers::Relative if file_line_no != self.cy => file_line_no.abs_diff(self.cy),
+            _ => file_line_no + 1,
+        };
+        let color = self.theme.color(Highlight::CurrentLineNumber);
+        if file_line_no != self.cy || !self.theme.color_enabled() || color == Color::Default {
+            return format!("{:>width$}{}", number, separator, width = width);
+        }
+        format!(
+            "{}{:>width$}{}{}",
+            color.foreground_escape_sequence(),
+            number,
+            Color::Default.foreground_escape_sequence(),
+            separator,
+            width = width
+        )
+    }
+
+    pub fn toggle_show_whitespace(&mut self) {
+        self.show_whitespace = !self.show_whitespace;
+    }
+
+    /// Shows or hides the scrollbar, which takes the rightmost column from the text.
+    pub fn toggle_scrollbar(&mut self) {
+        self.show_scrollbar = !self.show_scrollbar;
+        self.force_full_redraw();
+    }
+
+    /// Shows or hides the minimap, an overview of the whole buffer drawn left of the
+    /// scrollbar.
+    pub fn toggle_minimap(&mut self) {
+        self.show_minimap = !self.show_minimap;
+        self.force_full_redraw();
+    }
+
+    /// The number of columns left for text.
+    fn text_width(&self) -> usize {
+        self.text_width_in(self.component.width())
+    }
+
+    /// The number of columns left for text in an area `width` columns wide.
+    fn text_width_in(&self, width: usize) -> usize {
+        let mut width = width.saturating_sub(self.gutter_width());
+        if self.show_minimap {
+            width = width.saturating_sub(MINIMAP_COLUMNS);
+        }
+        if self.show_scrollbar {
+            width = width.saturating_sub(1);
+        }
+        width
     }
 
+    /// The minimap cell for `lines`, shaded by their average render width.
+    fn minimap_glyph(&self, lines: Range<usize>) -> char {
+        if lines.is_empty() {
+            return ' ';
+        }
+        let count = lines.len();
+        let total: usize = lines.map(|cy| self.buffer.cx_to_rx(usize::MAX, cy)).sum();
+        match total / count {
+            0 => ' ',
+            1..=20 => '░',
+            21..=40 => '▒',
+            41..=60 => '▓',
+            _ => '█',
+        }
+    }
+
+    /// The cursor may rest on the virtual line just past the end of the buffer
+    /// (`cy == len`), always at column 0, so that text can be appended there.
     pub fn down(&mut self) {
-        if !self.buffer.is_empty() && self.cy < self.buffer.len() {
+        if self.cy < self.buffer.len() {
             self.cy += 1;
         }
     }
@@ -72,7 +443,11 @@ impl Screen {
 
     pub fn left(&mut self) {
         if self.cx > 0 {
-            self.cx -= 1;
+            let line = self.buffer.get_line(self.cy).unwrap_or_default();
+            self.cx -= line[..self.cx.min(line.len())]
+                .chars()
+                .next_back()
+                .map_or(1, char::len_utf8);
         } else if self.cy > 0 {
             if let Some(line) = self.buffer.get_line(self.cy - 1) {
                 self.cy -= 1;
@@ -84,7 +459,7 @@ impl Screen {
     pub fn right(&mut self) {
         if let Some(line) = self.buffer.get_line(self.cy) {
             if self.cx < line.len() {
-                self.cx += 1;
+                self.cx += line[self.cx..].chars().next().map_or(1, char::len_utf8);
             } else if self.cx == line.len() {
                 self.cy += 1;
                 self.cx = 0;
@@ -106,19 +481,38 @@ impl Screen {
         }
     }
 
+    /// Scrolls the view `SCROLL_COLUMNS` to the left without moving the cursor.
+    pub fn scroll_left(&mut self) {
+        self.offset_x = self.offset_x.saturating_sub(SCROLL_COLUMNS);
+        self.scrolled_at = Some((self.cx, self.cy));
+    }
+
+    /// Scrolls the view `SCROLL_COLUMNS` to the right without moving the cursor, stopping
+    /// once the end of the longest line is at the right edge.
+    pub fn scroll_right(&mut self) {
+        if self.max_line_length.is_some() {
+            return;
+        }
+        let limit = self
+            .buffer
+            .max_render_width()
+            .saturating_sub(self.text_width());
+        self.offset_x = (self.offset_x + SCROLL_COLUMNS).min(limit.max(self.offset_x));
+        self.scrolled_at = Some((self.cx, self.cy));
+    }
+
     pub fn home(&mut self) {
         self.cx = 0;
     }
 
     pub fn end(&mut self) {
-        if self.cy < self.buffer.len() {
-            if let Some(line) = self.buffer.get_line(self.cy) {
-                self.cx = line.len();
-            }
-        }
+        self.cx = self.buffer.get_line(self.cy).map_or(0, |line| line.len());
     }
 
     pub fn insert_new_line(&mut self) {
+        if self.buffer.get_file_type() == Some(FileType::C) && self.cx > 0 {
+            return self.insert_new_line_indented();
+        }
         if self.cx == 0 {
             self.buffer.insert_line(self.cy, "".to_string());
         } else if let Some(current) = self.buffer.get_line(self.cy) {
@@ -132,13 +526,458 @@ impl Screen {
         self.cy += 1;
     }
 
+    /// Splits the line like `insert_new_line`, starting the new line at the current line's
+    /// indentation, one level deeper after a `{`. Between `{` and `}` the `}` goes on a line
+    /// of its own and the cursor on a blank line between them.
+    fn insert_new_line_indented(&mut self) {
+        let line = match self.buffer.get_line(self.cy) {
+            Some(line) => line,
+            None => return,
+        };
+        let (before, after) = line.split_at(self.cx.min(line.len()));
+        let after = after.trim_start_matches([' ', '\t']);
+        let indent = self.buffer.leading_whitespace(self.cy).to_string();
+        let opens = before.trim_end().ends_with('{');
+        let inner = if opens {
+            format!("{}{}", indent, self.indent_step(&indent))
+        } else {
+            indent.clone()
+        };
+
+        let cy = self.cy;
+        self.buffer.with_batched_highlight(|buffer| {
+            buffer.replace_line(cy, before.to_string());
+            if opens && after.starts_with('}') {
+                buffer.insert_line(cy + 1, inner.clone());
+                buffer.insert_line(cy + 2, format!("{}{}", indent, after));
+            } else {
+                buffer.insert_line(cy + 1, format!("{}{}", inner, after));
+            }
+        });
+        self.cx = inner.len();
+        self.cy += 1;
+    }
+
+    /// One more level of indentation after `indent`, in its style. A tab follows a tab. Spaces
+    /// follow spaces, as many as the buffer indents with, or as `indent` ends with when the
+    /// buffer indents with tabs.
+    fn indent_step(&self, indent: &str) -> String {
+        let spaces = indent.len() - indent.trim_end_matches(' ').len();
+        match self.buffer.indent() {
+            _ if indent.is_empty() => self.buffer.indent().unit(),
+            _ if spaces == 0 => "\t".to_string(),
+            Indent::Spaces(n) => " ".repeat(n),
+            Indent::Tabs => " ".repeat(spaces),
+        }
+    }
+
+    /// Deletes from the cursor to the end of the line and returns the deleted text.
+    pub fn delete_to_eol(&mut self) -> String {
+        if let Some(line) = self.buffer.get_line(self.cy) {
+            if self.cx < line.len() {
+                self.buffer
+                    .replace_line(self.cy, line[..self.cx].to_string());
+                return line[self.cx..].to_string();
+            }
+        }
+        String::new()
+    }
+
+    /// Deletes backward to the start of the previous word, or joins with the previous
+    /// line at column 0. Returns the deleted word, which is empty when lines were joined.
+    pub fn delete_word(&mut self) -> String {
+        if self.cx == 0 {
+            self.delete_char();
+            return String::new();
+        }
+        if let Some(line) = self.buffer.get_line(self.cy) {
+            let start = line[..self.cx]
+                .trim_end_matches(is_separator)
+                .trim_end_matches(|c| !is_separator(c))
+                .len();
+            let new_line = format!("{}{}", &line[..start], &line[self.cx..]);
+            self.buffer.replace_line(self.cy, new_line);
+            let deleted = line[start..self.cx].to_string();
+            self.cx = start;
+            return deleted;
+        }
+        String::new()
+    }
+
+    /// Inserts one level of the buffer's indentation at the start of the current line.
+    pub fn indent(&mut self) {
+        if self.cy == self.buffer.len() {
+            self.buffer.insert_line(self.cy, "".to_string());
+        }
+        let unit = self.buffer.indent().unit();
+        self.buffer.append_string(0, self.cy, unit.clone());
+        self.cx += unit.len();
+    }
+
+    /// Removes one leading tab, or up to one indentation level of leading spaces (`TAB_STOP`
+    /// when indenting with tabs), from the current line.
+    pub fn dedent(&mut self) {
+        if let Some(line) = self.buffer.get_line(self.cy) {
+            let width = match self.buffer.indent() {
+                Indent::Tabs => TAB_STOP,
+                Indent::Spaces(n) => n,
+            };
+            let removed = if line.starts_with('\t') {
+                1
+            } else {
+                line.len() - line.trim_start_matches(' ').len()
+            }
+            .min(width);
+            if removed > 0 {
+                self.buffer
+                    .replace_line(self.cy, line[removed..].to_string());
+                self.cx = self.cx.saturating_sub(removed);
+            }
+        }
+    }
+
+    /// Anchors a selection at the cursor, or drops the selection if one is active.
+    pub fn start_selection(&mut self) {
+        self.block_selection = false;
+        self.anchor = match self.anchor {
+            Some(_) => None,
+            None => Some((self.cx, self.cy)),
+        };
+    }
+
+    /// Anchors a block selection at the cursor, or drops the selection if one is active.
+    pub fn start_block_selection(&mut self) {
+        self.start_selection();
+        self.block_selection = self.anchor.is_some();
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.anchor = None;
+        self.block_selection = false;
+    }
+
+    /// Returns the start and end (exclusive) of a linear selection as `(cx, cy)` pairs.
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        if self.block_selection {
+            return None;
+        }
+        let anchor = self.anchor?;
+        let cursor = (self.cx, self.cy);
+        if (anchor.1, anchor.0) <= (cursor.1, cursor.0) {
+            Some((anchor, cursor))
+        } else {
+            Some((cursor, anchor))
+        }
+    }
+
+    /// Returns the first and last lines of a block selection and the render columns it
+    /// covers on each of them, the end exclusive.
+    pub fn block(&self) -> Option<((usize, usize), (usize, usize))> {
+        if !self.block_selection {
+            return None;
+        }
+        let (ax, ay) = self.anchor?;
+        let anchor_rx = self.buffer.cx_to_rx(ax, ay);
+        let cursor_rx = self.buffer.cx_to_rx(self.cx, self.cy);
+        Some((
+            (ay.min(self.cy), ay.max(self.cy)),
+            (anchor_rx.min(cursor_rx), anchor_rx.max(cursor_rx)),
+        ))
+    }
+
+    /// Replaces render columns `left..right` of each line from `top` to `bottom` with
+    /// `text`, then narrows the block to the column after it, so that typing goes on at
+    /// the same column of every line. Lines too short to reach `left` are padded with
+    /// spaces when inserting.
+    fn replace_block(
+        &mut self,
+        (top, bottom): (usize, usize),
+        (left, right): (usize, usize),
+        text: &str,
+    ) {
+        let mut columns = Vec::new();
+        self.buffer.with_batched_highlight(|buffer| {
+            for cy in top..=bottom.min(buffer.len().saturating_sub(1)) {
+                let width = buffer.cx_to_rx(usize::MAX, cy);
+                let begin = buffer.rx_to_byte(left, cy);
+                let end = buffer.rx_to_byte(right, cy);
+                if let Some(line) = buffer.get_line(cy).filter(|_| begin < end) {
+                    buffer.replace_line(cy, format!("{}{}", &line[..begin], &line[end..]));
+                }
+                let mut insert = String::new();
+                if !text.is_empty() {
+                    insert.push_str(&" ".repeat(left.saturating_sub(width)));
+                    insert.push_str(text);
+                }
+                columns.push(begin + insert.len());
+                buffer.append_string(begin, cy, insert);
+            }
+        });
+        let column = |cy: usize| columns.get(cy - top).copied().unwrap_or(0);
+        if let Some((_, ay)) = self.anchor {
+            self.anchor = Some((column(ay), ay));
+        }
+        self.cx = column(self.cy);
+    }
+
+    /// Types `text` at the block's column on each of its lines, replacing what the block
+    /// covers.
+    pub fn insert_in_block(&mut self, text: &str) {
+        if let Some((lines, columns)) = self.block() {
+            self.replace_block(lines, columns, text);
+        }
+    }
+
+    /// Deletes what the block covers on each of its lines or, when it is a bare column,
+    /// the char before it (after it when `forward`).
+    pub fn delete_in_block(&mut self, forward: bool) {
+        let (lines, (left, right)) = match self.block() {
+            Some(block) => block,
+            None => return,
+        };
+        let columns = match (left < right, forward) {
+            (true, _) => (left, right),
+            (false, true) => (left, left + 1),
+            (false, false) if left > 0 => (left - 1, left),
+            (false, false) => return,
+        };
+        self.replace_block(lines, columns, "");
+    }
+
+    /// Returns the render columns of line `num` covered by the selection.
+    pub fn selection_columns(&self, num: usize) -> Option<(usize, usize)> {
+        if let Some(((top, bottom), columns)) = self.block() {
+            return (top <= num && num <= bottom).then_some(columns);
+        }
+        let ((sx, sy), (ex, ey)) = self.selection()?;
+        if num < sy || ey < num {
+            return None;
+        }
+        let line = self.buffer.get_line(num)?;
+        let begin = if num == sy { sx } else { 0 };
+        let end = if num == ey { ex } else { line.len() };
+        Some((
+            self.buffer.cx_to_rx(begin, num),
+            self.buffer.cx_to_rx(end, num),
+        ))
+    }
+
+    /// The marker for rows past the end of the buffer, colored apart from the text so that
+    /// it can't be mistaken for a line in the file.
+    fn end_of_buffer_marker(&self) -> String {
+        let c = self.theme.end_of_buffer_char();
+        let color = self.theme.color(Highlight::EndOfBuffer);
+        if !self.theme.color_enabled() || color == Color::Default {
+            return c.to_string();
+        }
+        format!(
+            "{}{}{}",
+            color.foreground_escape_sequence(),
+            c,
+            Color::Default.foreground_escape_sequence()
+        )
+    }
+
+    /// The render columns drawn as markers on line `num`: extra cursors and the search match.
+    fn markers(&self, num: usize) -> Vec<usize> {
+        self.extra_cursors
+            .iter()
+            .chain(&self.search_marker)
+            .filter(|&&(_, cy)| cy == num)
+            .map(|&(cx, cy)| self.buffer.cx_to_rx(cx, cy))
+            .collect()
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        if let Some(((top, bottom), (left, right))) = self.block() {
+            let lines: Vec<String> = (top..=bottom)
+                .filter_map(|cy| {
+                    let line = self.buffer.get_line(cy)?;
+                    let begin = self.buffer.rx_to_byte(left, cy);
+                    let end = self.buffer.rx_to_byte(right, cy);
+                    Some(line[begin..end].to_string())
+                })
+                .collect();
+            return Some(lines.join("\n"));
+        }
+        let ((sx, sy), (ex, ey)) = self.selection()?;
+        let mut lines = Vec::new();
+        for i in sy..=ey {
+            let line = self.buffer.get_line(i).unwrap_or_default();
+            let begin = if i == sy { sx.min(line.len()) } else { 0 };
+            let end = if i == ey {
+                ex.min(line.len())
+            } else {
+                line.len()
+            };
+            lines.push(line[begin..end].to_string());
+        }
+        Some(lines.join("\n"))
+    }
+
+    /// Removes the selected text and leaves the cursor at its start.
+    pub fn delete_selection(&mut self) {
+        if let Some((lines, (left, right))) = self.block() {
+            if left < right {
+                self.replace_block(lines, (left, right), "");
+            }
+            return self.clear_selection();
+        }
+        let ((sx, sy), (ex, ey)) = match self.selection() {
+            Some(selection) => selection,
+            None => return,
+        };
+        self.anchor = None;
+        let first = match self.buffer.get_line(sy) {
+            Some(line) => line,
+            None => return,
+        };
+        let last = self.buffer.get_line(ey).unwrap_or_default();
+        let new_line = format!(
+            "{}{}",
+            &first[..sx.min(first.len())],
+            &last[ex.min(last.len())..]
+        );
+        self.buffer.with_batched_highlight(|buffer| {
+            for _ in sy..ey.min(buffer.len() - 1) {
+                buffer.delete_line(sy + 1);
+            }
+            buffer.replace_line(sy, new_line);
+        });
+        self.cx = sx;
+        self.cy = sy;
+    }
+
+    /// Inserts `text`, which may span several lines, at the cursor and leaves the
+    /// cursor after it.
+    pub fn insert_text(&mut self, text: &str) {
+        if self.cy == self.buffer.len() {
+            self.buffer.insert_line(self.cy, "".to_string());
+            self.cx = 0;
+        }
+        let line = self.buffer.get_line(self.cy).unwrap_or_default();
+        let (prefix, suffix) = line.split_at(self.cx.min(line.len()));
+        let parts: Vec<&str> = text.split('\n').collect();
+        let last = parts.len() - 1;
+
+        if last == 0 {
+            self.buffer
+                .replace_line(self.cy, format!("{}{}{}", prefix, parts[0], suffix));
+            self.cx = prefix.len() + parts[0].len();
+            return;
+        }
+
+        let cy = self.cy;
+        self.buffer.with_batched_highlight(|buffer| {
+            buffer.replace_line(cy, format!("{}{}", prefix, parts[0]));
+            for (i, part) in parts[1..last].iter().enumerate() {
+                buffer.insert_line(cy + 1 + i, part.to_string());
+            }
+            buffer.insert_line(cy + last, format!("{}{}", parts[last], suffix));
+        });
+        self.cx = parts[last].len();
+        self.cy += last;
+    }
+
+    /// Opens an empty line below the current one, keeping the current line's leading
+    /// whitespace, and moves the cursor to its end.
+    pub fn open_line_below(&mut self) {
+        let indent = self.buffer.leading_whitespace(self.cy).to_string();
+        let cy = (self.cy + 1).min(self.buffer.len());
+        self.buffer.insert_line(cy, indent.clone());
+        self.cx = indent.len();
+        self.cy = cy;
+    }
+
+    /// Like `open_line_below`, but opens the line above the current one.
+    pub fn open_line_above(&mut self) {
+        let indent = self.buffer.leading_whitespace(self.cy).to_string();
+        self.buffer.insert_line(self.cy, indent.clone());
+        self.cx = indent.len();
+    }
+
+    /// Returns where the word ending at the cursor starts, and the word itself.
+    pub fn word_at(&self) -> Option<(usize, String)> {
+        let line = self.buffer.get_line(self.cy)?;
+        let before = &line[..self.cx.min(line.len())];
+        let start = before.len()
+            - before
+                .chars()
+                .rev()
+                .take_while(|&c| !is_separator(c))
+                .map(char::len_utf8)
+                .sum::<usize>();
+        (start < before.len()).then(|| (start, before[start..].to_string()))
+    }
+
+    /// The whole word the cursor is in or at the start of, and where it starts.
+    pub fn word_under_cursor(&self) -> Option<(usize, String)> {
+        let line = self.buffer.get_line(self.cy)?;
+        let cx = self.cx.min(line.len());
+        let start = self.word_at().map_or(cx, |(start, _)| start);
+        let end = cx
+            + line[cx..]
+                .chars()
+                .take_while(|&c| !is_separator(c))
+                .map(char::len_utf8)
+                .sum::<usize>();
+        (start < end).then(|| (start, line[start..end].to_string()))
+    }
+
+    /// Replaces the text from `start` to the cursor with the snippet `text`, indenting its
+    /// later lines like the current one, and leaves the cursor at its `$0`.
+    pub fn expand_snippet(&mut self, start: usize, text: &str) {
+        let indent = self.buffer.leading_whitespace(self.cy).to_string();
+        let text = text.replace('\n', &format!("\n{}", indent));
+        let (before, after) = text.split_once("$0").unwrap_or((&text, ""));
+        if let Some(line) = self.buffer.get_line(self.cy) {
+            let end = self.cx.min(line.len());
+            self.buffer
+                .replace_line(self.cy, format!("{}{}", &line[..start], &line[end..]));
+        }
+        self.cx = start;
+        self.insert_text(before);
+        let cursor = self.cursor();
+        self.insert_text(after);
+        (self.cx, self.cy) = cursor;
+    }
+
+    pub fn duplicate_line(&mut self) {
+        if let Some(line) = self.buffer.get_line(self.cy) {
+            self.buffer.insert_line(self.cy + 1, line);
+            self.cy += 1;
+        }
+    }
+
+    pub fn move_line_up(&mut self) {
+        if self.cy == 0 || self.cy >= self.buffer.len() {
+            return;
+        }
+        if let Some(line) = self.buffer.get_line(self.cy) {
+            self.buffer.delete_line(self.cy);
+            self.buffer.insert_line(self.cy - 1, line);
+            self.cy -= 1;
+        }
+    }
+
+    pub fn move_line_down(&mut self) {
+        if self.cy + 1 >= self.buffer.len() {
+            return;
+        }
+        if let Some(line) = self.buffer.get_line(self.cy) {
+            self.buffer.delete_line(self.cy);
+            self.buffer.insert_line(self.cy + 1, line);
+            self.cy += 1;
+        }
+    }
+
     pub fn insert_char(&mut self, c: char) {
         if self.cy == self.buffer.len() {
             self.buffer.insert_line(self.buffer.len(), "".to_string());
             self.cx = 0;
         }
         self.buffer.insert_char(self.cx, self.cy, c);
-        self.cx += 1
+        self.cx += c.len_utf8();
     }
 
     pub fn delete_char(&mut self) {
@@ -152,19 +991,27 @@ impl Screen {
                     self.cy -= 1;
                 }
             }
-        } else {
-            self.buffer.delete_char(self.cx - 1, self.cy);
-            self.cx -= 1;
+        } else if let Some(line) = self.buffer.get_line(self.cy) {
+            let width = line[..self.cx]
+                .chars()
+                .next_back()
+                .map_or(1, char::len_utf8);
+            self.cx -= width;
+            self.buffer.delete_char(self.cx, self.cy);
         }
     }
 
-    pub fn find(&mut self, query: &str) -> bool {
+    pub fn find(&mut self, query: &str, options: SearchOptions) -> bool {
         for i in self.cy..self.buffer.len() {
             if let Some(line) = self.buffer.get_line(i) {
                 let begin = if i == self.cy { self.cx } else { 0 };
 
-                if let Some(j) = line[begin..line.len()].find(query) {
-                    self.cx = begin + j;
+                if let Some(j) = options
+                    .overlapping_match_positions(&line, query)
+                    .into_iter()
+                    .find(|&j| begin <= j)
+                {
+                    self.cx = j;
                     self.cy = i;
                     return true;
                 }
@@ -173,12 +1020,16 @@ impl Screen {
         false
     }
 
-    pub fn rfind(&mut self, query: &str) -> bool {
+    pub fn rfind(&mut self, query: &str, options: SearchOptions) -> bool {
         for i in (0..=self.cy).rev() {
             if let Some(line) = self.buffer.get_line(i) {
                 let end = if i == self.cy { self.cx } else { line.len() };
 
-                if let Some(j) = line[0..end].rfind(query) {
+                if let Some(j) = options
+                    .overlapping_match_positions(&line, query)
+                    .into_iter()
+                    .rfind(|&j| j <= end)
+                {
                     self.cx = j;
                     self.cy = i;
                     return true;
@@ -188,44 +1039,178 @@ impl Screen {
         false
     }
 
-    pub fn adjust(&mut self) {
-        self.rx = 0;
-
-        if let Some(line) = self.buffer.get_line(self.cy) {
-            if line.len() < self.cx {
-                self.cx = line.len();
-            }
-        }
-
-        if self.cy < self.buffer.len() {
-            self.rx = self.buffer.cx_to_rx(self.cx, self.cy);
-        }
-
-        if self.rx < self.offset_x {
-            self.offset_x = self.rx;
+    /// Searches forward, continuing from the top of the buffer once the end is reached.
+    /// Returns `None` if there is no match, otherwise whether the search wrapped.
+    pub fn find_wrapping(&mut self, query: &str, options: SearchOptions) -> Option<bool> {
+        if self.find(query, options) {
+            return Some(false);
         }
-        if self.rx >= self.offset_x + self.component.width() {
-            self.offset_x = self.rx - self.component.width() + 1;
+        let (cx, cy) = self.cursor();
+        self.set_cursor(0, 0);
+        if self.find(query, options) {
+            return Some(true);
         }
+        self.set_cursor(cx, cy);
+        None
+    }
 
-        if self.cy < self.offset_y {
-            self.offset_y = self.cy;
+    /// Searches backward, continuing from the bottom of the buffer once the top is reached.
+    /// Returns `None` if there is no match, otherwise whether the search wrapped.
+    pub fn rfind_wrapping(&mut self, query: &str, options: SearchOptions) -> Option<bool> {
+        if self.rfind(query, options) {
+            return Some(false);
         }
-        if self.cy >= self.offset_y + self.component.height() {
-            self.offset_y = self.cy - self.component.height() + 1;
+        let (cx, cy) = self.cursor();
+        self.set_cursor(0, self.buffer.len());
+        if self.rfind(query, options) {
+            return Some(true);
         }
+        self.set_cursor(cx, cy);
+        None
     }
 
-    pub fn get_terminal_cursor(&self) -> (usize, usize) {
-        (
-            self.component.x() + self.rx - self.offset_x,
-            self.component.y() + self.cy - self.offset_y,
-        )
+    pub fn match_bracket(&mut self) -> bool {
+        let c = match self
+            .buffer
+            .get_line(self.cy)
+            .and_then(|line| line.as_bytes().get(self.cx).copied())
+        {
+            Some(c) => c,
+            None => return false,
+        };
+        let (open, close, forward) = match c {
+            b'(' => (b'(', b')', true),
+            b'[' => (b'[', b']', true),
+            b'{' => (b'{', b'}', true),
+            b')' => (b')', b'(', false),
+            b']' => (b']', b'[', false),
+            b'}' => (b'}', b'{', false),
+            _ => return false,
+        };
+
+        let mut depth = 0;
+        let lines: Vec<usize> = if forward {
+            (self.cy..self.buffer.len()).collect()
+        } else {
+            (0..=self.cy).rev().collect()
+        };
+        for i in lines {
+            if let Some(line) = self.buffer.get_line(i) {
+                let bytes = line.as_bytes();
+                let columns: Vec<usize> = match (forward, i == self.cy) {
+                    (true, true) => (self.cx..bytes.len()).collect(),
+                    (true, false) => (0..bytes.len()).collect(),
+                    (false, true) => (0..=self.cx).rev().collect(),
+                    (false, false) => (0..bytes.len()).rev().collect(),
+                };
+                for j in columns {
+                    if bytes[j] == open {
+                        depth += 1;
+                    } else if bytes[j] == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            self.ensure_visible(j, i);
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Loads lines of a file that is still streaming in far enough to show the screen
+    /// around the cursor.
+    pub fn load_visible_lines(&mut self) -> Result<(), Error> {
+        let top = self.cy.max(self.offset_y);
+        self.buffer.ensure_loaded(top + self.component.height() + 1)
+    }
+
+    pub fn adjust(&mut self) {
+        self.rx = 0;
+        self.clamp_cursor();
+
+        if self.cy < self.buffer.len() {
+            self.rx = self.buffer.cx_to_rx(self.cx, self.cy);
+            if let Some(limit) = self.line_limit().filter(|&limit| self.rx > limit) {
+                self.cx = self.buffer.rx_to_byte(limit, self.cy);
+                self.rx = self.buffer.cx_to_rx(self.cx, self.cy);
+            }
+        }
+        if self.max_line_length.is_some() {
+            self.offset_x = 0;
+        }
+
+        if self.scrolled_at != Some((self.cx, self.cy)) {
+            self.scrolled_at = None;
+            if self.rx < self.offset_x {
+                self.offset_x = self.rx;
+            }
+            if self.rx >= self.offset_x + self.text_width() {
+                self.offset_x = (self.rx + 1).saturating_sub(self.text_width());
+            }
+        }
+
+        if self.cy < self.offset_y {
+            self.offset_y = self.cy;
+        }
+        if self.cy >= self.offset_y + self.component.height() {
+            self.offset_y = self.cy - self.component.height() + 1;
+        }
+    }
+
+    /// Moves the cursor to `(cx, cy)` for a jump. A line out of view is centered rather than
+    /// brought just inside the edge as `adjust` would, so that there is context around it.
+    pub fn ensure_visible(&mut self, cx: usize, cy: usize) {
+        self.set_cursor(cx, cy);
+        let cy = self.cy;
+        let height = self.component.height();
+        if cy < self.offset_y || cy >= self.offset_y + height {
+            self.offset_y = cy.saturating_sub(height / 2);
+        }
+        self.adjust();
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.component.contains(x, y)
+    }
+
+    /// Moves the cursor to the char drawn at terminal cell `(x, y)`, clamped to the end of
+    /// the buffer and of the line. A click on the gutter goes to the start of the line.
+    pub fn click(&mut self, x: usize, y: usize) {
+        let cy = (self.offset_y + y.saturating_sub(self.component.y()))
+            .min(self.buffer.len().saturating_sub(1));
+        let column = x.saturating_sub(self.component.x() + self.gutter_width());
+        self.cy = cy;
+        self.cx = self.buffer.rx_to_byte(self.offset_x + column, cy);
+        self.adjust();
+    }
+
+    /// The cursor's position on the terminal, kept at the edge of the view when it has been
+    /// scrolled out of sight sideways.
+    pub fn get_terminal_cursor(&self) -> (usize, usize) {
+        let column = self
+            .rx
+            .saturating_sub(self.offset_x)
+            .min(self.text_width().saturating_sub(1));
+        // Only a smooth scroll in progress leaves the cursor line out of view.
+        let row = self
+            .cy
+            .saturating_sub(self.offset_y)
+            .min(self.component.height().saturating_sub(1));
+        (
+            self.component.x() + self.gutter_width() + column,
+            self.component.y() + row,
+        )
     }
 
     pub fn get_cy(&self) -> usize {
         self.cy
     }
+
+    pub fn get_rx(&self) -> usize {
+        self.rx
+    }
 }
 
 impl Default for Screen {
@@ -234,67 +1219,205 @@ impl Default for Screen {
     }
 }
 
-pub fn refresh_screen(pane: &mut Pane) -> Result<(), Error> {
-    let mut buf = String::new();
+/// Appends a full frame for `pane` to `buf`: its rows, then the cursor.
+pub fn render(pane: &Pane, buf: &mut String) -> Result<(), Error> {
     buf.push_str(ESCAPE_SEQUENCE_HIDE_CURSOR);
     buf.push_str(ESCAPE_SEQUENCE_MOVE_CURSOR_TO_FIRST_POSITION);
 
-    pane.draw(&mut buf)?;
+    pane.draw(buf)?;
 
     let cursor = pane.get_terminal_cursor();
     let move_cursor_str = move_terminal_cursor(cursor.0, cursor.1);
     buf.push_str(&move_cursor_str);
 
     buf.push_str(ESCAPE_SEQUENCE_SHOW_CURSOR);
+    Ok(())
+}
 
-    print!("{}", buf);
-    stdout().flush()?;
+pub fn refresh_screen(pane: &mut Pane) -> Result<(), Error> {
+    let mut buf = pane.terminal().take_frame();
+    render(pane, &mut buf)?;
 
-    Ok(())
+    let ret = pane.terminal().write(&buf);
+    pane.terminal().put_frame(buf);
+    ret
 }
 
-impl Drawable for Screen {
-    fn draw(&self, buf: &mut String) -> Result<(), Error> {
-        for i in 0..self.component.height() {
-            let file_line_no = i + self.offset_y;
+/// Returns the first row and the number of rows of the scrollbar thumb for a viewport of
+/// `height` rows scrolled to `offset` in a buffer of `len` lines. The thumb's share of the
+/// height is the share of the buffer in view, and it is never less than one row.
+fn scrollbar_thumb(offset: usize, height: usize, len: usize) -> (usize, usize) {
+    if height == 0 || len <= height {
+        return (0, height);
+    }
+    let size = (height * height).div_ceil(len).clamp(1, height);
+    let row = (offset * height / len).min(height - size);
+    (row, size)
+}
 
-            let cursor = move_terminal_cursor(self.component.x(), i + self.component.y());
-            buf.push_str(&cursor);
+/// The offset for the next frame of a smooth scroll from `offset` to `target`, covering half
+/// the remaining distance, rounded up, so the scroll slows down as it arrives.
+fn scroll_step(offset: usize, target: usize) -> usize {
+    let step = offset.abs_diff(target).div_ceil(2);
+    if offset < target {
+        offset + step
+    } else {
+        offset - step
+    }
+}
+
+/// The lines of a buffer of `len` lines that minimap row `row` of `height` rows stands for.
+/// Each row covers the same number of lines, at least one, so a short buffer leaves the
+/// rows past its end empty.
+fn minimap_lines(row: usize, height: usize, len: usize) -> Range<usize> {
+    let per_row = len.div_ceil(height.max(1)).max(1);
+    (row * per_row).min(len)..((row + 1) * per_row).min(len)
+}
+
+/// Counts the characters of `s` that occupy a column, skipping escape sequences.
+fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+impl Screen {
+    /// Draws into `area` instead of the screen's own, diffing against `last_rows`, so that a
+    /// second pane can show this screen too.
+    pub fn draw_in(
+        &self,
+        buf: &mut String,
+        area: &Component,
+        pad_rows: bool,
+        last_rows: &RefCell<Vec<String>>,
+    ) -> Result<(), Error> {
+        let mut last_rows = last_rows.borrow_mut();
+        last_rows.resize(area.height(), String::new());
+        let width = self.text_width_in(area.width());
+        let height = area.height();
+        let thumb = self
+            .show_scrollbar
+            .then(|| scrollbar_thumb(self.offset_y, height, self.buffer.len()));
+
+        for (i, last_row) in last_rows.iter_mut().enumerate() {
+            let file_line_no = i + self.offset_y;
+            let mut row = self.gutter(file_line_no);
 
             if file_line_no < self.buffer.len() {
-                if let Some(render) =
-                    self.buffer
-                        .get_render(file_line_no, self.offset_x, self.component.width())
-                {
-                    buf.push_str(&render);
+                let truncated_at = self
+                    .line_limit()
+                    .filter(|&limit| self.buffer.cx_to_rx(usize::MAX, file_line_no) > limit);
+                let options = RenderOptions {
+                    theme: &self.theme,
+                    show_whitespace: self.show_whitespace,
+                    selection: self.selection_columns(file_line_no),
+                    rulers: &self.rulers,
+                    markers: &self.markers(file_line_no),
+                };
+                if let Some(render) = self.buffer.get_render(
+                    file_line_no,
+                    self.offset_x,
+                    truncated_at.unwrap_or(width),
+                    &options,
+                ) {
+                    row.push_str(&render);
                 }
-            } else if self.buffer.is_empty() && i == self.component.height() / 3 {
+                if truncated_at.is_some() {
+                    row.push('→');
+                }
+            } else if self.buffer.is_empty() && i == area.height() / 3 {
                 let title = format!("kilo-rs -- version {}", KILO_VERSION);
-                let t: String = title.chars().take(self.component.width()).collect();
-                let mut padding = (self.component.width() - t.len()) / 2;
+                let t: String = title.chars().take(width).collect();
+                let mut padding = (width - t.len()) / 2;
                 if padding > 0 {
-                    buf.push('~');
+                    row.push_str(&self.end_of_buffer_marker());
                     padding -= 1;
                 }
                 for _ in 0..padding {
-                    buf.push(' ');
+                    row.push(' ');
+                }
+                row.push_str(&t);
+            } else {
+                row.push_str(&self.end_of_buffer_marker());
+            }
+
+            if self.show_minimap || self.show_scrollbar {
+                for _ in visible_width(&row)..self.gutter_width() + width {
+                    row.push(' ');
+                }
+            }
+            if self.show_minimap {
+                let lines = minimap_lines(i, height, self.buffer.len());
+                let in_view = lines.start < self.offset_y + height && self.offset_y < lines.end;
+                let glyph = self.minimap_glyph(lines);
+                row.push(' ');
+                if in_view {
+                    row.push_str(ESCAPE_SEQUENCE_STYLE_REVERSE);
+                    row.push(glyph);
+                    row.push_str(ESCAPE_SEQUENCE_STYLE_RESET);
+                } else {
+                    row.push(glyph);
+                }
+            }
+            if let Some((top, size)) = thumb {
+                if top <= i && i < top + size {
+                    row.push_str(ESCAPE_SEQUENCE_STYLE_REVERSE);
+                    row.push(' ');
+                    row.push_str(ESCAPE_SEQUENCE_STYLE_RESET);
+                } else {
+                    row.push(' ');
+                }
+            } else if pad_rows {
+                for _ in visible_width(&row)..self.gutter_width() + width {
+                    row.push(' ');
                 }
-                buf.push_str(&t);
             } else {
-                buf.push('~');
+                row.push_str(ESCAPE_SEQUENCE_CLEAR_LINE);
             }
 
-            buf.push_str(ESCAPE_SEQUENCE_CLEAR_LINE);
+            if *last_row == row {
+                continue;
+            }
+
+            let cursor = move_terminal_cursor(area.x(), i + area.y());
+            buf.push_str(&cursor);
+            buf.push_str(&row);
             buf.push_str("\r\n");
+            *last_row = row;
         }
 
         Ok(())
     }
 }
 
+impl Drawable for Screen {
+    fn draw(&self, buf: &mut String) -> Result<(), Error> {
+        self.draw_in(buf, &self.component, self.pad_rows, &self.last_rows)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{EditorBuffer, Screen};
+    use super::{minimap_lines, refresh_screen, scroll_step, scrollbar_thumb, LineNumbers, Screen};
+    use crate::buffer::{Highlight, Indent, SearchOptions};
+    use crate::escape_sequence::Color;
+    use crate::pane::Pane;
+    use crate::theme::Theme;
+    use crate::ui::{Drawable, SharedWriter, Terminal};
+    use std::time::SystemTime;
 
     fn initialize_screen() -> Screen {
         let mut screen = Screen::new();
@@ -309,7 +1432,7 @@ mod tests {
 
     fn cursor_test_runner<T>(test_cases: &[([usize; 2], [usize; 2])], func: T)
     where
-        T: Fn(&mut Screen) -> (),
+        T: Fn(&mut Screen),
     {
         let mut screen = initialize_screen();
         for (i, data) in test_cases.iter().enumerate() {
@@ -428,4 +1551,1120 @@ mod tests {
         screen.adjust();
         assert_eq!(31, screen.offset_y);
     }
+
+    #[test]
+    fn test_draw_max_line_length() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 3);
+        screen
+            .buffer
+            .load_string(format!("{}\nab\n{}\n", "x".repeat(30), "y".repeat(10)));
+        screen.set_max_line_length(Some(10));
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(
+            buf.contains(&format!("\x1b[1;1H{}\x1b[39m→\x1b[K", "x".repeat(10))),
+            "{:?}",
+            buf
+        );
+        assert!(buf.contains("\x1b[2;1Hab\x1b[39m\x1b[K"), "{:?}", buf);
+        assert!(
+            buf.contains(&format!("\x1b[3;1H{}\x1b[39m\x1b[K", "y".repeat(10))),
+            "{:?}",
+            buf
+        );
+
+        screen.set_cursor(25, 0);
+        screen.adjust();
+        assert_eq!((10, 0), screen.cursor());
+        assert_eq!((10, 0), screen.get_terminal_cursor());
+        screen.scroll_right();
+        screen.adjust();
+        assert_eq!(0, screen.offset_x);
+
+        screen.buffer.load_string(format!("{}\n", "é".repeat(30)));
+        screen.set_cursor(50, 0);
+        screen.adjust();
+        assert_eq!((20, 0), screen.cursor());
+        assert_eq!(10, screen.get_rx());
+    }
+
+    #[test]
+    fn test_click_multibyte() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 5);
+        screen.buffer.load_string("éab\n".to_string());
+
+        screen.click(1, 0);
+        assert_eq!((2, 0), screen.cursor());
+        assert_eq!(1, screen.get_rx());
+        screen.click(2, 0);
+        assert_eq!((3, 0), screen.cursor());
+        screen.click(10, 0);
+        assert_eq!((4, 0), screen.cursor());
+    }
+
+    #[test]
+    fn test_scroll_horizontally() {
+        let mut screen = initialize_screen();
+        screen.buffer.insert_line(1, "-".repeat(110));
+        screen.set_cursor(3, 0);
+        screen.adjust();
+
+        screen.scroll_right();
+        screen.adjust();
+        assert_eq!(8, screen.offset_x);
+        assert_eq!((3, 0), screen.cursor());
+        assert_eq!((0, 0), screen.get_terminal_cursor());
+
+        for _ in 0..20 {
+            screen.scroll_right();
+        }
+        screen.adjust();
+        assert_eq!(90, screen.offset_x);
+
+        screen.scroll_left();
+        screen.adjust();
+        assert_eq!(82, screen.offset_x);
+        for _ in 0..20 {
+            screen.scroll_left();
+        }
+        assert_eq!(0, screen.offset_x);
+
+        screen.scroll_right();
+        screen.right();
+        screen.adjust();
+        assert_eq!(4, screen.offset_x);
+    }
+
+    #[test]
+    fn test_scroll_step() {
+        assert_eq!(50, scroll_step(0, 100));
+        assert_eq!(75, scroll_step(50, 100));
+        assert_eq!(100, scroll_step(99, 100));
+        assert_eq!(50, scroll_step(100, 1));
+        assert_eq!(7, scroll_step(7, 7));
+
+        let mut offset = 0;
+        let mut frames = 0;
+        while offset != 1000 {
+            offset = scroll_step(offset, 1000);
+            frames += 1;
+        }
+        assert_eq!(10, frames);
+    }
+
+    #[test]
+    fn test_smooth_scroll() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 10);
+        screen.buffer.load_string("a\n".repeat(100));
+        screen.set_smooth_scroll(true);
+
+        screen.ensure_visible(0, 2);
+        screen.animate_from(0);
+        assert!(!screen.is_scrolling());
+
+        screen.ensure_visible(0, 90);
+        assert_eq!(85, screen.offset().1);
+        screen.animate_from(0);
+        assert!(screen.is_scrolling());
+        assert_eq!(0, screen.offset().1);
+        assert_eq!((0, 9), screen.get_terminal_cursor());
+
+        screen.step_scroll();
+        assert_eq!(43, screen.offset().1);
+        screen.finish_scroll();
+        assert!(!screen.is_scrolling());
+        assert_eq!((0, 5), screen.get_terminal_cursor());
+
+        screen.set_smooth_scroll(false);
+        screen.ensure_visible(0, 0);
+        screen.animate_from(85);
+        assert!(!screen.is_scrolling());
+    }
+
+    #[test]
+    fn test_clamp_cursor() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 10);
+        screen.buffer.load_string("abc\nde\n".to_string());
+
+        screen.set_cursor(10, 1);
+        assert_eq!((2, 1), screen.cursor());
+        screen.set_cursor(4, 7);
+        assert_eq!((0, 2), screen.cursor());
+        screen.set_cursor(3, 0);
+        assert_eq!((3, 0), screen.cursor());
+
+        screen.ensure_visible(5, 99);
+        assert_eq!((0, 2), screen.cursor());
+        assert_eq!(0, screen.offset().1);
+    }
+
+    #[test]
+    fn test_clamp_cursor_char_boundary() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 10);
+        screen.buffer.load_string("éa\n".to_string());
+
+        screen.ensure_visible(1, 0);
+        assert_eq!((0, 0), screen.cursor());
+        screen.insert_char('x');
+        assert_eq!(Some("xéa".to_string()), screen.buffer.get_line(0));
+
+        screen.right();
+        screen.adjust();
+        assert_eq!((3, 0), screen.cursor());
+        assert_eq!(2, screen.get_rx());
+        screen.left();
+        screen.adjust();
+        assert_eq!((1, 0), screen.cursor());
+        assert_eq!(1, screen.get_rx());
+    }
+
+    #[test]
+    fn test_delete_char_multibyte() {
+        let mut screen = Screen::new();
+        screen.buffer.load_string("ab\n".to_string());
+        screen.set_cursor(1, 0);
+        screen.insert_char('é');
+        screen.insert_char('☃');
+        assert_eq!((6, 0), screen.cursor());
+
+        screen.delete_char();
+        assert_eq!(Some("aéb".to_string()), screen.buffer.get_line(0));
+        assert_eq!((3, 0), screen.cursor());
+        screen.delete_char();
+        screen.delete_char();
+        assert_eq!(Some("b".to_string()), screen.buffer.get_line(0));
+        assert_eq!((0, 0), screen.cursor());
+    }
+
+    #[test]
+    fn test_ensure_visible() {
+        let mut screen = initialize_screen();
+        screen.set_cursor(0, 50);
+        screen.adjust();
+        assert_eq!(31, screen.offset().1);
+
+        let mut screen = initialize_screen();
+        screen.ensure_visible(0, 50);
+        assert_eq!((0, 40), screen.offset());
+        assert_eq!((0, 50), screen.cursor());
+
+        screen.ensure_visible(0, 55);
+        assert_eq!(40, screen.offset().1);
+
+        screen.set_cursor(0, 10);
+        screen.adjust();
+        assert_eq!(10, screen.offset().1);
+
+        let mut screen = initialize_screen();
+        screen.set_offset(0, 40);
+        screen.ensure_visible(0, 10);
+        assert_eq!(0, screen.offset().1);
+        screen.ensure_visible(0, 99);
+        assert_eq!(89, screen.offset().1);
+    }
+
+    #[test]
+    fn test_match_bracket() {
+        let mut screen = Screen::new();
+        screen.component.set_size(0, 0, 20, 20);
+        screen
+            .buffer
+            .load_string("int main() {\r\n  if (a[0]) {\r\n  }\r\n}\r\n".to_string());
+
+        screen.set_cursor(11, 0);
+        assert!(screen.match_bracket());
+        assert_eq!((0, 3), screen.cursor());
+
+        assert!(screen.match_bracket());
+        assert_eq!((11, 0), screen.cursor());
+
+        screen.set_cursor(12, 1);
+        assert!(screen.match_bracket());
+        assert_eq!((2, 2), screen.cursor());
+
+        screen.set_cursor(5, 1);
+        assert!(screen.match_bracket());
+        assert_eq!((10, 1), screen.cursor());
+
+        screen.set_cursor(9, 1);
+        assert!(screen.match_bracket());
+        assert_eq!((7, 1), screen.cursor());
+    }
+
+    #[test]
+    fn test_match_bracket_unbalanced() {
+        let mut screen = Screen::new();
+        screen.component.set_size(0, 0, 20, 20);
+        screen
+            .buffer
+            .load_string("{\r\n  (\r\n)\r\nabc\r\n".to_string());
+
+        screen.set_cursor(0, 0);
+        assert!(!screen.match_bracket());
+        assert_eq!((0, 0), screen.cursor());
+
+        screen.set_cursor(1, 3);
+        assert!(!screen.match_bracket());
+        assert_eq!((1, 3), screen.cursor());
+    }
+
+    #[test]
+    fn test_refresh_screen() {
+        let writer = SharedWriter::default();
+        let mut pane = Pane::new("".to_string(), SystemTime::UNIX_EPOCH);
+        pane.set_terminal(Terminal::with_writer(Box::new(writer.clone())));
+        pane.set_size(0, 0, 20, 10);
+        pane.screen()
+            .buffer()
+            .load_string("int main() {\r\n\treturn 0;\r\n}\r\n".to_string());
+
+        let mut expected = "\x1b[?25l\x1b[H".to_string();
+        pane.draw(&mut expected).unwrap();
+        expected.push_str("\x1b[1;1H\x1b[?25h");
+
+        pane.screen().force_full_redraw();
+        refresh_screen(&mut pane).unwrap();
+        let first = writer.0.borrow_mut().split_off(0);
+        pane.screen().force_full_redraw();
+        refresh_screen(&mut pane).unwrap();
+        let second = writer.0.borrow_mut().split_off(0);
+
+        assert_eq!(expected.as_bytes(), &first[..]);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_draw_changed_rows_only() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 5);
+        screen
+            .buffer
+            .load_string("abc\r\ndef\r\nghi\r\n".to_string());
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        for row in 1..=5 {
+            assert!(buf.contains(&format!("\x1b[{};1H", row)), "row={}", row);
+        }
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert_eq!("", buf);
+
+        screen.set_cursor(1, 1);
+        screen.insert_char('x');
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert_eq!("\x1b[2;1Hdxef\x1b[39m\x1b[K\r\n", buf);
+
+        screen.force_full_redraw();
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        for row in 1..=5 {
+            assert!(buf.contains(&format!("\x1b[{};1H", row)), "row={}", row);
+        }
+    }
+
+    #[test]
+    fn test_scrollbar_thumb() {
+        assert_eq!((0, 10), scrollbar_thumb(0, 10, 5));
+        assert_eq!((0, 1), scrollbar_thumb(0, 10, 100));
+        assert_eq!((5, 1), scrollbar_thumb(50, 10, 100));
+        assert_eq!((9, 1), scrollbar_thumb(99, 10, 100));
+        assert_eq!((0, 5), scrollbar_thumb(0, 10, 20));
+        assert_eq!((5, 5), scrollbar_thumb(10, 10, 20));
+        assert_eq!((2, 4), scrollbar_thumb(7, 10, 30));
+        assert_eq!((9, 1), scrollbar_thumb(10_000, 10, 10_010));
+        assert_eq!((0, 0), scrollbar_thumb(0, 0, 5));
+    }
+
+    #[test]
+    fn test_draw_zero_height() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 0);
+        screen.buffer.load_string("abc\n".to_string());
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        screen.toggle_scrollbar();
+        screen.draw(&mut buf).unwrap();
+        assert_eq!("", buf);
+    }
+
+    #[test]
+    fn test_draw_scrollbar() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 6, 2);
+        screen.buffer.load_string("abcdefgh\n1\n2\n3\n".to_string());
+        screen.toggle_scrollbar();
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(
+            buf.contains("\x1b[1;1Habcde\x1b[39m\x1b[7m \x1b[m"),
+            "{:?}",
+            buf
+        );
+        assert!(buf.contains("\x1b[2;1H1\x1b[39m     \r\n"), "{:?}", buf);
+
+        screen.set_cursor(0, 3);
+        screen.adjust();
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[1;1H2\x1b[39m     \r\n"), "{:?}", buf);
+        assert!(
+            buf.contains("\x1b[2;1H3\x1b[39m    \x1b[7m \x1b[m"),
+            "{:?}",
+            buf
+        );
+    }
+
+    #[test]
+    fn test_minimap_lines() {
+        assert_eq!(0..1, minimap_lines(0, 10, 5));
+        assert_eq!(4..5, minimap_lines(4, 10, 5));
+        assert_eq!(5..5, minimap_lines(5, 10, 5));
+        assert_eq!(0..10, minimap_lines(0, 10, 100));
+        assert_eq!(90..100, minimap_lines(9, 10, 100));
+        assert_eq!(0..4, minimap_lines(0, 3, 10));
+        assert_eq!(8..10, minimap_lines(2, 3, 10));
+        assert_eq!(0..0, minimap_lines(0, 10, 0));
+    }
+
+    #[test]
+    fn test_draw_minimap() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 8, 2);
+        let line = "x".repeat(50);
+        screen
+            .buffer
+            .load_string(format!("ab\n{}\n\n{}\n", line, line));
+        screen.toggle_minimap();
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(
+            buf.contains("\x1b[1;1Hab\x1b[39m     \x1b[7m▒\x1b[m"),
+            "{:?}",
+            buf
+        );
+        assert!(buf.contains("\x1b[2;1Hxxxxxx\x1b[39m ▒"), "{:?}", buf);
+
+        screen.set_cursor(0, 3);
+        screen.adjust();
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[1;1H\x1b[39m       ▒"), "{:?}", buf);
+        assert!(
+            buf.contains("\x1b[2;1Hxxxxxx\x1b[39m \x1b[7m▒\x1b[m"),
+            "{:?}",
+            buf
+        );
+    }
+
+    #[test]
+    fn test_draw_rulers() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 2);
+        screen.buffer.load_string("abc\r\n".to_string());
+        screen.set_rulers(vec![10]);
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        let ruler = format!("{}\x1b[48;5;236m \x1b[49m", " ".repeat(6));
+        assert!(
+            buf.contains(&format!("\x1b[1;1Habc\x1b[39m{}", ruler)),
+            "{:?}",
+            buf
+        );
+
+        screen.set_offset(5, 0);
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        let ruler = format!("{}\x1b[48;5;236m \x1b[49m", " ".repeat(4));
+        assert!(
+            buf.contains(&format!("\x1b[1;1H\x1b[39m{}", ruler)),
+            "{:?}",
+            buf
+        );
+    }
+
+    #[test]
+    fn test_draw_line_numbers() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 12);
+        screen.buffer.load_string("a\n".repeat(10));
+        screen.set_line_numbers(LineNumbers::Relative);
+        screen.set_cursor(0, 3);
+        screen.adjust();
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[1;1H 3 a"), "{:?}", buf);
+        assert!(buf.contains("\x1b[3;1H 1 a"), "{:?}", buf);
+        assert!(buf.contains("\x1b[4;1H 4 a"), "{:?}", buf);
+        assert!(buf.contains("\x1b[5;1H 1 a"), "{:?}", buf);
+        assert!(buf.contains("\x1b[10;1H 6 a"), "{:?}", buf);
+        assert!(buf.contains("\x1b[11;1H   \x1b[34m~"), "{:?}", buf);
+        assert_eq!((3, 3), screen.get_terminal_cursor());
+
+        screen.set_line_numbers(LineNumbers::Absolute);
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[1;1H 1 a"), "{:?}", buf);
+        assert!(buf.contains("\x1b[10;1H10 a"), "{:?}", buf);
+    }
+
+    #[test]
+    fn test_draw_gutter_theme() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 3);
+        screen.buffer.load_string("a\nb\n".to_string());
+        let mut theme = Theme::default();
+        theme.set_gutter_separator(" │ ".to_string());
+        theme.set_color(Highlight::CurrentLineNumber, Color::Yellow);
+        screen.set_theme(theme);
+        screen.set_line_numbers(LineNumbers::Relative);
+        screen.set_cursor(0, 1);
+        screen.adjust();
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[1;1H1 │ a"), "{:?}", buf);
+        assert!(buf.contains("\x1b[2;1H\x1b[33m2\x1b[39m │ b"), "{:?}", buf);
+        assert!(buf.contains("\x1b[3;1H  │ \x1b[34m~"), "{:?}", buf);
+        assert_eq!((4, 1), screen.get_terminal_cursor());
+    }
+
+    #[test]
+    fn test_multiple_cursors() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 4);
+        screen.buffer.load_string("abc\r\nd\r\nefg\r\n".to_string());
+        screen.set_cursor(1, 1);
+        screen.add_cursor_above();
+        screen.add_cursor_below();
+        assert_eq!(vec![(1, 1), (1, 0), (1, 2)], screen.cursors());
+
+        screen.insert_char_at_cursors('x');
+        screen.insert_char_at_cursors('y');
+        assert_eq!(Some("axybc".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("dxy".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("exyfg".to_string()), screen.buffer.get_line(2));
+        assert_eq!(vec![(3, 1), (3, 0), (3, 2)], screen.cursors());
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("axy\x1b[7mb\x1b[27mc"), "{:?}", buf);
+
+        screen.delete_char_at_cursors();
+        assert_eq!(Some("dx".to_string()), screen.buffer.get_line(1));
+        screen.set_cursor(0, 1);
+        screen.delete_char_at_cursors();
+        assert_eq!(Some("dx".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("abc".to_string()), screen.buffer.get_line(0));
+        assert_eq!(3, screen.buffer.len());
+
+        screen.collapse_cursors();
+        assert_eq!(vec![(0, 1)], screen.cursors());
+    }
+
+    #[test]
+    fn test_multiple_cursors_non_ascii() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 4);
+        screen.buffer.load_string("abc\néa\n\tb\n".to_string());
+        screen.set_cursor(1, 0);
+        screen.add_cursor_below();
+        assert_eq!(vec![(1, 0), (2, 1)], screen.cursors());
+
+        screen.insert_char_at_cursors('x');
+        assert_eq!(Some("axbc".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("éxa".to_string()), screen.buffer.get_line(1));
+
+        screen.collapse_cursors();
+        screen.set_cursor(0, 2);
+        screen.add_cursor_above();
+        assert_eq!(vec![(0, 2), (0, 1)], screen.cursors());
+    }
+
+    #[test]
+    fn test_draw_end_of_buffer() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 3);
+        screen.buffer.load_string("abc\r\n\r\n".to_string());
+        let mut theme = Theme::default();
+        theme.set_end_of_buffer_char('.');
+        theme.set_color(Highlight::EndOfBuffer, Color::Ansi256(240));
+        screen.set_theme(theme);
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[2;1H\x1b[39m\x1b[K"), "{:?}", buf);
+        assert!(
+            buf.contains("\x1b[3;1H\x1b[38;5;240m.\x1b[39m\x1b[K"),
+            "{:?}",
+            buf
+        );
+
+        screen.theme.set_color_enabled(false);
+        screen.force_full_redraw();
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[3;1H.\x1b[K"), "{:?}", buf);
+    }
+
+    #[test]
+    fn test_draw_search_marker() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 2);
+        screen.buffer.load_string("foo\r\n\tbar\r\n".to_string());
+        screen.set_search_marker(Some((1, 1)));
+
+        let mut buf = String::new();
+        screen.draw(&mut buf).unwrap();
+        assert!(buf.contains("\x1b[1;1Hfoo\x1b[39m\x1b[K"), "{:?}", buf);
+        assert!(
+            buf.contains("\x1b[2;1H        \x1b[7mb\x1b[27mar\x1b[39m"),
+            "{:?}",
+            buf
+        );
+    }
+
+    #[test]
+    fn test_insert_new_line_after_brace() {
+        let path = std::env::temp_dir().join(format!("kilo-rs-{}-brace.c", std::process::id()));
+        std::fs::write(&path, "  if (x) {\n  f(); {}\n").unwrap();
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_file(path.to_string_lossy().into_owned())
+            .unwrap();
+
+        screen.set_cursor(10, 0);
+        screen.insert_new_line();
+        assert_eq!(Some("  if (x) {".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("    ".to_string()), screen.buffer.get_line(1));
+        assert_eq!((4, 1), screen.cursor());
+
+        screen.set_cursor(8, 2);
+        screen.insert_new_line();
+        assert_eq!(Some("  f(); {".to_string()), screen.buffer.get_line(2));
+        assert_eq!(Some("    ".to_string()), screen.buffer.get_line(3));
+        assert_eq!(Some("  }".to_string()), screen.buffer.get_line(4));
+        assert_eq!((4, 3), screen.cursor());
+
+        screen.set_cursor(4, 2);
+        screen.insert_new_line();
+        assert_eq!(Some("  f(".to_string()), screen.buffer.get_line(2));
+        assert_eq!(Some("  ); {".to_string()), screen.buffer.get_line(3));
+        assert_eq!((2, 3), screen.cursor());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_indent_step() {
+        let mut screen = Screen::new();
+        assert_eq!("\t", screen.indent_step(""));
+        assert_eq!("\t", screen.indent_step("\t"));
+        assert_eq!("  ", screen.indent_step("\t  "));
+
+        screen.buffer.set_indent(Indent::Spaces(4));
+        assert_eq!("    ", screen.indent_step(""));
+        assert_eq!("    ", screen.indent_step("  "));
+        assert_eq!("\t", screen.indent_step("\t\t"));
+    }
+
+    #[test]
+    fn test_open_line() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("abc\r\n\tdef\r\nghi\r\n".to_string());
+        screen.set_cursor(2, 1);
+        screen.open_line_below();
+        assert_eq!(4, screen.buffer.len());
+        assert_eq!(Some("\tdef".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("\t".to_string()), screen.buffer.get_line(2));
+        assert_eq!(Some("ghi".to_string()), screen.buffer.get_line(3));
+        assert_eq!((1, 2), screen.cursor());
+
+        screen.set_cursor(2, 0);
+        screen.open_line_above();
+        assert_eq!(Some("".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("abc".to_string()), screen.buffer.get_line(1));
+        assert_eq!((0, 0), screen.cursor());
+
+        screen.set_cursor(0, 5);
+        screen.open_line_below();
+        assert_eq!(6, screen.buffer.len());
+        assert_eq!((0, 5), screen.cursor());
+    }
+
+    #[test]
+    fn test_expand_snippet() {
+        let mut screen = Screen::new();
+        screen.buffer.load_string("\tx = main\r\n".to_string());
+        screen.set_cursor(9, 0);
+        assert_eq!(Some((5, "main".to_string())), screen.word_at());
+
+        screen.expand_snippet(5, "int main(void) {\n\t$0\n}");
+        assert_eq!(
+            Some("\tx = int main(void) {".to_string()),
+            screen.buffer.get_line(0)
+        );
+        assert_eq!(Some("\t\t".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("\t}".to_string()), screen.buffer.get_line(2));
+        assert_eq!((2, 1), screen.cursor());
+        assert_eq!(None, screen.word_at());
+    }
+
+    #[test]
+    fn test_duplicate_line() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("abc\r\ndef\r\nghi\r\n".to_string());
+        screen.set_cursor(1, 0);
+        screen.duplicate_line();
+
+        assert_eq!(4, screen.buffer.len());
+        assert_eq!(Some("abc".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("abc".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("def".to_string()), screen.buffer.get_line(2));
+        assert_eq!((1, 1), screen.cursor());
+        assert!(screen.buffer.is_dirty());
+
+        screen.set_cursor(0, 4);
+        screen.duplicate_line();
+        assert_eq!(4, screen.buffer.len());
+        assert_eq!((0, 4), screen.cursor());
+    }
+
+    #[test]
+    fn test_move_line() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("abc\r\ndef\r\nghi\r\n".to_string());
+        let lines = |screen: &Screen| -> Vec<String> {
+            (0..screen.buffer.len())
+                .filter_map(|i| screen.buffer.get_line(i))
+                .collect()
+        };
+
+        screen.set_cursor(2, 1);
+        screen.move_line_up();
+        assert_eq!(vec!["def", "abc", "ghi"], lines(&screen));
+        assert_eq!((2, 0), screen.cursor());
+        assert!(screen.buffer.is_dirty());
+
+        screen.move_line_up();
+        assert_eq!(vec!["def", "abc", "ghi"], lines(&screen));
+        assert_eq!((2, 0), screen.cursor());
+
+        screen.move_line_down();
+        screen.move_line_down();
+        assert_eq!(vec!["abc", "ghi", "def"], lines(&screen));
+        assert_eq!((2, 2), screen.cursor());
+
+        screen.move_line_down();
+        assert_eq!(vec!["abc", "ghi", "def"], lines(&screen));
+        assert_eq!((2, 2), screen.cursor());
+    }
+
+    #[test]
+    fn test_delete_to_eol() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("hello world\r\nxyz\r\n".to_string());
+
+        screen.set_cursor(5, 0);
+        screen.delete_to_eol();
+        assert_eq!(Some("hello".to_string()), screen.buffer.get_line(0));
+        assert_eq!((5, 0), screen.cursor());
+        assert!(screen.buffer.is_dirty());
+
+        screen.delete_to_eol();
+        assert_eq!(2, screen.buffer.len());
+        assert_eq!(Some("hello".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("xyz".to_string()), screen.buffer.get_line(1));
+    }
+
+    #[test]
+    fn test_delete_word() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("abc\r\nfoo bar  baz\r\n".to_string());
+
+        screen.set_cursor(9, 1);
+        screen.delete_word();
+        assert_eq!(Some("foo baz".to_string()), screen.buffer.get_line(1));
+        assert_eq!((4, 1), screen.cursor());
+        assert!(screen.buffer.is_dirty());
+
+        screen.delete_word();
+        assert_eq!(Some("baz".to_string()), screen.buffer.get_line(1));
+        assert_eq!((0, 1), screen.cursor());
+
+        screen.delete_word();
+        assert_eq!(1, screen.buffer.len());
+        assert_eq!(Some("abcbaz".to_string()), screen.buffer.get_line(0));
+        assert_eq!((3, 0), screen.cursor());
+    }
+
+    #[test]
+    fn test_indent() {
+        let mut screen = Screen::new();
+        screen.buffer.load_string("\r\nabc\r\n".to_string());
+
+        screen.indent();
+        assert_eq!(Some("\t".to_string()), screen.buffer.get_line(0));
+        assert_eq!((1, 0), screen.cursor());
+        assert!(screen.buffer.is_dirty());
+
+        screen.set_cursor(2, 1);
+        screen.indent();
+        assert_eq!(Some("\tabc".to_string()), screen.buffer.get_line(1));
+        assert_eq!((3, 1), screen.cursor());
+
+        screen.buffer.set_indent(Indent::Spaces(4));
+        screen.indent();
+        assert_eq!(Some("    \tabc".to_string()), screen.buffer.get_line(1));
+        assert_eq!((7, 1), screen.cursor());
+        screen.dedent();
+        screen.dedent();
+        assert_eq!(Some("abc".to_string()), screen.buffer.get_line(1));
+    }
+
+    #[test]
+    fn test_dedent() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("   abc\r\n\t\tdef\r\n            ghi\r\nxyz\r\n".to_string());
+
+        screen.set_cursor(4, 0);
+        screen.dedent();
+        assert_eq!(Some("abc".to_string()), screen.buffer.get_line(0));
+        assert_eq!((1, 0), screen.cursor());
+
+        screen.set_cursor(0, 1);
+        screen.dedent();
+        assert_eq!(Some("\tdef".to_string()), screen.buffer.get_line(1));
+        assert_eq!((0, 1), screen.cursor());
+
+        screen.set_cursor(12, 2);
+        screen.dedent();
+        assert_eq!(Some("    ghi".to_string()), screen.buffer.get_line(2));
+        assert_eq!((4, 2), screen.cursor());
+
+        screen.set_cursor(0, 3);
+        screen.dedent();
+        assert_eq!(Some("xyz".to_string()), screen.buffer.get_line(3));
+    }
+
+    #[test]
+    fn test_selection_columns() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("abcdefgh\r\n\tijk\r\nlmnop\r\nqrs\r\n".to_string());
+        assert_eq!(None, screen.selection_columns(0));
+
+        screen.set_cursor(2, 0);
+        screen.start_selection();
+        screen.set_cursor(5, 0);
+        assert_eq!(Some((2, 5)), screen.selection_columns(0));
+        assert_eq!(None, screen.selection_columns(1));
+        assert_eq!(Some("cde".to_string()), screen.selected_text());
+
+        screen.set_cursor(3, 2);
+        assert_eq!(Some((2, 8)), screen.selection_columns(0));
+        assert_eq!(Some((0, 11)), screen.selection_columns(1));
+        assert_eq!(Some((0, 3)), screen.selection_columns(2));
+        assert_eq!(None, screen.selection_columns(3));
+        assert_eq!(
+            Some("cdefgh\n\tijk\nlmn".to_string()),
+            screen.selected_text()
+        );
+
+        screen.start_selection();
+        assert_eq!(None, screen.selection_columns(0));
+    }
+
+    #[test]
+    fn test_block_selection_non_ascii() {
+        let mut screen = Screen::new();
+        screen.buffer.load_string("éab\r\nxyz\r\n".to_string());
+        screen.start_block_selection();
+        screen.set_cursor(1, 1);
+        assert_eq!(Some(((0, 1), (0, 1))), screen.block());
+        assert_eq!(Some("é\nx".to_string()), screen.selected_text());
+
+        screen.delete_selection();
+        assert_eq!(Some("ab".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("yz".to_string()), screen.buffer.get_line(1));
+    }
+
+    #[test]
+    fn test_block_insert_non_ascii_prefix() {
+        let mut screen = Screen::new();
+        screen.buffer.load_string("éab\r\nxab\r\n".to_string());
+        screen.set_cursor(2, 0);
+        screen.start_block_selection();
+        screen.set_cursor(1, 1);
+        assert_eq!(Some(((0, 1), (1, 1))), screen.block());
+
+        screen.insert_in_block("-");
+        screen.insert_in_block("ü");
+        assert_eq!(Some("é-üab".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("x-üab".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some(((0, 1), (3, 3))), screen.block());
+        assert_eq!((4, 1), screen.cursor());
+        screen.adjust();
+        assert_eq!(3, screen.get_rx());
+    }
+
+    #[test]
+    fn test_block_selection() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("abc\r\ndefg\r\nhi\r\nxyz\r\n".to_string());
+        screen.start_block_selection();
+        screen.set_cursor(0, 2);
+        assert_eq!(Some(((0, 2), (0, 0))), screen.block());
+        assert_eq!(None, screen.selection());
+
+        screen.insert_in_block("> ");
+        screen.insert_in_block("-");
+        assert_eq!(Some("> -abc".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("> -defg".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("> -hi".to_string()), screen.buffer.get_line(2));
+        assert_eq!(Some("xyz".to_string()), screen.buffer.get_line(3));
+        assert_eq!((3, 2), screen.cursor());
+
+        screen.delete_in_block(false);
+        assert_eq!(Some("> abc".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some(((0, 2), (2, 2))), screen.block());
+
+        screen.set_cursor(4, 2);
+        assert_eq!(Some(((0, 2), (2, 4))), screen.block());
+        assert_eq!(Some((2, 4)), screen.selection_columns(1));
+        assert_eq!(None, screen.selection_columns(3));
+        assert_eq!(Some("ab\nde\nhi".to_string()), screen.selected_text());
+
+        screen.delete_selection();
+        assert_eq!(Some("> c".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("> fg".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("> ".to_string()), screen.buffer.get_line(2));
+        assert_eq!((2, 2), screen.cursor());
+        assert_eq!(None, screen.block());
+
+        screen.set_cursor(2, 3);
+        screen.start_block_selection();
+        screen.set_cursor(2, 2);
+        screen.insert_in_block("|");
+        assert_eq!(Some("> |".to_string()), screen.buffer.get_line(2));
+        assert_eq!(Some("xy|z".to_string()), screen.buffer.get_line(3));
+    }
+
+    #[test]
+    fn test_selection_backward() {
+        let mut screen = Screen::new();
+        screen.buffer.load_string("abcdefgh\r\nijk\r\n".to_string());
+
+        screen.set_cursor(2, 1);
+        screen.start_selection();
+        screen.set_cursor(6, 0);
+        assert_eq!(Some(((6, 0), (2, 1))), screen.selection());
+        assert_eq!(Some("gh\nij".to_string()), screen.selected_text());
+    }
+
+    #[test]
+    fn test_delete_selection_and_insert_text() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("abcdefgh\r\nijk\r\nlmnop\r\n".to_string());
+
+        screen.set_cursor(2, 0);
+        screen.start_selection();
+        screen.set_cursor(3, 2);
+        let text = screen.selected_text().unwrap();
+        screen.delete_selection();
+        assert_eq!(1, screen.buffer.len());
+        assert_eq!(Some("abop".to_string()), screen.buffer.get_line(0));
+        assert_eq!((2, 0), screen.cursor());
+        assert_eq!(None, screen.selection());
+
+        screen.insert_text(&text);
+        assert_eq!(3, screen.buffer.len());
+        assert_eq!(Some("abcdefgh".to_string()), screen.buffer.get_line(0));
+        assert_eq!(Some("ijk".to_string()), screen.buffer.get_line(1));
+        assert_eq!(Some("lmnop".to_string()), screen.buffer.get_line(2));
+        assert_eq!((3, 2), screen.cursor());
+
+        screen.insert_text("xy");
+        assert_eq!(Some("lmnxyop".to_string()), screen.buffer.get_line(2));
+        assert_eq!((5, 2), screen.cursor());
+    }
+
+    #[test]
+    fn test_cursor_virtual_last_line() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 10);
+        screen.buffer.load_string("abc\r\nde\r\n".to_string());
+
+        screen.set_cursor(2, 1);
+        screen.right();
+        assert_eq!((0, 2), screen.cursor());
+        screen.right();
+        assert_eq!((0, 2), screen.cursor());
+        screen.down();
+        assert_eq!((0, 2), screen.cursor());
+
+        screen.end();
+        assert_eq!((0, 2), screen.cursor());
+        screen.home();
+        assert_eq!((0, 2), screen.cursor());
+
+        screen.set_cursor(5, 2);
+        screen.adjust();
+        assert_eq!((0, 2), screen.cursor());
+
+        screen.set_cursor(1, 7);
+        screen.adjust();
+        assert_eq!((0, 2), screen.cursor());
+
+        screen.insert_char('x');
+        assert_eq!(3, screen.buffer.len());
+        assert_eq!(Some("x".to_string()), screen.buffer.get_line(2));
+        assert_eq!((1, 2), screen.cursor());
+
+        screen.set_cursor(3, 0);
+        screen.down();
+        screen.adjust();
+        assert_eq!((2, 1), screen.cursor());
+    }
+
+    #[test]
+    fn test_cursor_empty_buffer() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 20, 10);
+
+        screen.down();
+        screen.right();
+        screen.end();
+        screen.adjust();
+        assert_eq!((0, 0), screen.cursor());
+    }
+
+    #[test]
+    fn test_find_wrapping() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("foo\r\nbar\r\nbaz\r\n".to_string());
+        let options = SearchOptions::default();
+
+        screen.set_cursor(0, 1);
+        assert_eq!(Some(false), screen.find_wrapping("baz", options));
+        assert_eq!((0, 2), screen.cursor());
+
+        assert_eq!(Some(true), screen.find_wrapping("foo", options));
+        assert_eq!((0, 0), screen.cursor());
+
+        screen.set_cursor(1, 2);
+        assert_eq!(None, screen.find_wrapping("qux", options));
+        assert_eq!((1, 2), screen.cursor());
+    }
+
+    #[test]
+    fn test_rfind_wrapping() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("foo\r\nbar\r\nbaz\r\n".to_string());
+        let options = SearchOptions::default();
+
+        screen.set_cursor(0, 1);
+        assert_eq!(Some(false), screen.rfind_wrapping("foo", options));
+        assert_eq!((0, 0), screen.cursor());
+
+        assert_eq!(Some(true), screen.rfind_wrapping("baz", options));
+        assert_eq!((0, 2), screen.cursor());
+
+        assert_eq!(None, screen.rfind_wrapping("qux", options));
+        assert_eq!((0, 2), screen.cursor());
+    }
+
+    #[test]
+    fn test_find_options() {
+        let mut screen = Screen::new();
+        screen
+            .buffer
+            .load_string("foobar Foo\r\nfoo_bar foo\r\n".to_string());
+
+        let options = SearchOptions::default();
+        assert!(screen.find("Foo", options));
+        assert_eq!((7, 0), screen.cursor());
+
+        let options = SearchOptions {
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        screen.set_cursor(0, 0);
+        assert!(screen.find("Foo", options));
+        assert_eq!((0, 0), screen.cursor());
+
+        let options = SearchOptions {
+            whole_word: true,
+            ..SearchOptions::default()
+        };
+        screen.set_cursor(0, 0);
+        assert!(screen.find("foo", options));
+        assert_eq!((8, 1), screen.cursor());
+        screen.left();
+        assert!(!screen.rfind("foo", options));
+
+        let options = SearchOptions {
+            ignore_case: true,
+            whole_word: true,
+        };
+        assert!(screen.rfind("FOO", options));
+        assert_eq!((7, 0), screen.cursor());
+        assert_eq!(" (ignore case, whole word)", options.label());
+        assert_eq!("", SearchOptions::default().label());
+    }
+
+    #[test]
+    fn test_find_overlapping() {
+        let mut screen = Screen::new();
+        screen.buffer.load_string("aaa\r\n".to_string());
+        let options = SearchOptions::default();
+
+        assert!(screen.find("aa", options));
+        assert_eq!((0, 0), screen.cursor());
+        screen.right();
+        assert!(screen.find("aa", options));
+        assert_eq!((1, 0), screen.cursor());
+
+        screen.end();
+        assert!(screen.rfind("aa", options));
+        assert_eq!((1, 0), screen.cursor());
+        screen.left();
+        assert!(screen.rfind("aa", options));
+        assert_eq!((0, 0), screen.cursor());
+    }
 }
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+
+/// Text that a trigger word typed before Tab expands to. A `$0` in the text marks where the
+/// cursor ends up; without one it goes after the expansion.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Snippets {
+    expansions: HashMap<String, String>,
+}
+
+impl Snippets {
+    pub fn get(&self, trigger: &str) -> Option<&str> {
+        self.expansions.get(trigger).map(|text| text.as_str())
+    }
+
+    pub fn insert(&mut self, trigger: String, text: String) {
+        self.expansions.insert(trigger, text);
+    }
+
+    /// Parses `trigger = text` lines, where the text may use `\n`, `\t` and `\\`, e.g.
+    /// `main = int main(void) {\n\t$0\n}`. Empty lines and lines starting with `#` are
+    /// ignored.
+    pub fn parse(text: &str) -> Result<Snippets, Error> {
+        let mut snippets = Snippets::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || Error::other(format!("invalid snippet line {}: {}", i + 1, line));
+            let (trigger, text) = line.split_once('=').ok_or_else(invalid)?;
+            let trigger = trigger.trim();
+            if trigger.is_empty() || trigger.contains(char::is_whitespace) {
+                return Err(invalid());
+            }
+            snippets.insert(trigger.to_string(), unescape(text.trim()));
+        }
+        Ok(snippets)
+    }
+
+    pub fn load_file(path: &str) -> Result<Snippets, Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => Snippets::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Snippets::default()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Turns `\n`, `\t` and `\\` into the chars they stand for. Any other backslash is kept.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unescape, Snippets};
+
+    #[test]
+    fn test_parse() {
+        let snippets =
+            Snippets::parse("# C\nmain = int main(void) {\\n\\t$0\\n}\n\ninc = #include <$0>\n")
+                .unwrap();
+        assert_eq!(Some("int main(void) {\n\t$0\n}"), snippets.get("main"));
+        assert_eq!(Some("#include <$0>"), snippets.get("inc"));
+        assert_eq!(None, snippets.get("for"));
+
+        assert!(Snippets::parse("main").is_err());
+        assert!(Snippets::parse("two words = x").is_err());
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!("a\nb\tc\\d", unescape("a\\nb\\tc\\\\d"));
+        assert_eq!("\\x\\", unescape("\\x\\"));
+    }
+}
@@ -5,10 +5,13 @@ use crate::screen::Screen;
 use crate::ui::{Component, Drawable};
 use std::io::Error;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct StatusBar {
     component: Component,
-    left_status: String,
+    /// The left status is kept in three parts so that the filename can be cropped alone.
+    left_prefix: String,
+    filename: String,
+    left_suffix: String,
     right_status: String,
 }
 
@@ -16,7 +19,9 @@ impl StatusBar {
     pub fn new() -> StatusBar {
         StatusBar {
             component: Component::default(),
-            left_status: "".to_string(),
+            left_prefix: "".to_string(),
+            filename: "".to_string(),
+            left_suffix: "".to_string(),
             right_status: "".to_string(),
         }
     }
@@ -25,35 +30,97 @@ impl StatusBar {
         self.component.set_size(x, y, width, height);
     }
 
-    pub fn set_left_status(&mut self, screen: &mut Screen) {
-        self.left_status = format!(
-            "{:<20} - {} lines {}",
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.component.contains(x, y)
+    }
+
+    /// `position` is the 1-based index of the active buffer and the number of open buffers.
+    pub fn set_left_status(&mut self, screen: &mut Screen, position: (usize, usize)) {
+        self.left_prefix = if position.1 > 1 {
+            format!("[{}/{}] ", position.0, position.1)
+        } else {
+            "".to_string()
+        };
+        self.filename = format!(
+            "{:<20}",
             screen
                 .buffer()
                 .get_filepath()
-                .unwrap_or_else(|| "[No Name]".to_string()),
-            self.component.height(),
-            if screen.buffer().is_dirty() {
-                "(modified)"
+                .unwrap_or_else(|| "[No Name]".to_string())
+        );
+        let buffer = screen.buffer();
+        let flags: Vec<&str> = [
+            (buffer.is_dirty(), "(modified)"),
+            (buffer.is_read_only(), "(read-only)"),
+            (!buffer.is_fully_loaded(), "(loading)"),
+        ]
+        .into_iter()
+        .filter_map(|(set, flag)| set.then_some(flag))
+        .collect();
+        self.left_suffix = format!(" - {} lines {}", self.component.height(), flags.join(" "));
+    }
+
+    /// Builds the left status in at most `width` chars. The filename gives way first,
+    /// keeping its end since that names the file, then the rest is cropped.
+    fn left_status(&self, width: usize) -> String {
+        let fixed = self.left_prefix.chars().count() + self.left_suffix.chars().count();
+        let name_len = self.filename.chars().count();
+        let room = width.saturating_sub(fixed);
+        let filename = if name_len <= room {
+            self.filename.clone()
+        } else {
+            let name = self.filename.trim_end();
+            let name_len = name.chars().count();
+            if name_len <= room {
+                name.to_string()
             } else {
-                ""
+                let marker = if room > 3 { "..." } else { "" };
+                let tail: String = name
+                    .chars()
+                    .skip(name_len - (room - marker.len()))
+                    .collect();
+                format!("{}{}", marker, tail)
             }
-        );
+        };
+        format!("{}{}{}", self.left_prefix, filename, self.left_suffix)
+            .chars()
+            .take(width)
+            .collect()
     }
 
     pub fn set_right_status(&mut self, screen: &mut Screen) {
         self.right_status = format!(
-            "{} | {}/{}",
+            "{} | {}{}{} | {} | {:>width$}/{} | col {}",
             screen
                 .buffer()
                 .get_file_type()
                 .map_or("no ft", |ft| ft.to_str()),
+            screen.buffer().encoding().to_str(),
+            if screen.buffer().has_bom() {
+                " [BOM]"
+            } else {
+                ""
+            },
+            if screen.buffer().has_mixed_line_endings() {
+                " [mixed]"
+            } else {
+                ""
+            },
+            screen.buffer().indent().to_status(),
             screen.get_cy() + 1,
-            screen.buffer().len()
+            screen.buffer().len(),
+            screen.get_rx() + 1,
+            width = screen.buffer().line_count_digits(),
         );
     }
 }
 
+impl Default for StatusBar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Drawable for StatusBar {
     fn draw(&self, buf: &mut String) -> Result<(), Error> {
         let cursor = move_terminal_cursor(self.component.x(), self.component.y());
@@ -61,33 +128,22 @@ impl Drawable for StatusBar {
 
         buf.push_str(ESCAPE_SEQUENCE_STYLE_REVERSE);
 
-        if self.component.width() < self.left_status.len() {
-            let s: String = self
-                .left_status
-                .chars()
-                .take(self.component.width())
-                .collect();
-            buf.push_str(&s);
-        } else {
-            buf.push_str(&self.left_status);
-
-            if self.component.width() as isize
-                - self.left_status.len() as isize
-                - self.right_status.len() as isize
-                > 0
-            {
-                for _ in
-                    0..(self.component.width() - self.left_status.len() - self.right_status.len())
-                {
-                    buf.push(' ');
-                }
-                buf.push_str(&self.right_status);
-            } else {
-                for _ in 0..(self.component.width() - self.left_status.len()) {
-                    buf.push(' ');
-                }
-            }
+        // The right status holds the cursor position, so it is kept and only loses its
+        // start when it alone is too wide. The left status gets the rest, less one space.
+        let width = self.component.width();
+        let right_len = self.right_status.chars().count();
+        let right: String = self
+            .right_status
+            .chars()
+            .skip(right_len.saturating_sub(width))
+            .collect();
+        let right_len = right_len.min(width);
+        let left = self.left_status((width - right_len).saturating_sub(1));
+        buf.push_str(&left);
+        for _ in left.chars().count()..width - right_len {
+            buf.push(' ');
         }
+        buf.push_str(&right);
 
         buf.push_str(ESCAPE_SEQUENCE_STYLE_RESET);
         buf.push_str("\r\n");
@@ -95,3 +151,108 @@ impl Drawable for StatusBar {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::StatusBar;
+    use crate::buffer::Indent;
+    use crate::escape_sequence::{ESCAPE_SEQUENCE_STYLE_RESET, ESCAPE_SEQUENCE_STYLE_REVERSE};
+    use crate::screen::Screen;
+    use crate::ui::Drawable;
+
+    #[test]
+    fn test_set_right_status() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 80, 20);
+        screen.buffer().load_string("\tabc\r\nxyz\r\n".to_string());
+        screen.set_cursor(2, 0);
+        screen.adjust();
+
+        let mut status_bar = StatusBar::new();
+        status_bar.set_right_status(&mut screen);
+        assert_eq!(
+            "no ft | utf-8 | tabs:8 | 1/2 | col 10",
+            status_bar.right_status
+        );
+
+        screen.buffer().set_indent(Indent::Spaces(4));
+        status_bar.set_right_status(&mut screen);
+        assert_eq!(
+            "no ft | utf-8 | spaces:4 | 1/2 | col 10",
+            status_bar.right_status
+        );
+
+        screen.buffer().load_string("a\n".repeat(12));
+        screen.set_cursor(0, 0);
+        screen.adjust();
+        status_bar.set_right_status(&mut screen);
+        assert!(
+            status_bar.right_status.ends_with("|  1/12 | col 1"),
+            "{}",
+            status_bar.right_status
+        );
+    }
+
+    #[test]
+    fn test_set_left_status() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 80, 20);
+
+        let mut status_bar = StatusBar::new();
+        status_bar.set_left_status(&mut screen, (1, 1));
+        assert!(status_bar.left_status(80).starts_with("[No Name]"));
+
+        status_bar.set_left_status(&mut screen, (2, 3));
+        assert!(status_bar.left_status(80).starts_with("[2/3] [No Name]"));
+
+        screen.buffer().load_hex_dump(b"abc");
+        screen.buffer().insert_char(0, 0, 'x');
+        status_bar.set_left_status(&mut screen, (1, 1));
+        let status = status_bar.left_status(80);
+        assert!(status.ends_with(" (modified) (read-only)"), "{:?}", status);
+    }
+
+    #[test]
+    fn test_draw_crops_filename_first() {
+        let mut screen = Screen::new();
+        screen.set_size(0, 0, 40, 20);
+        screen.buffer().load_string("abc\n".to_string());
+
+        let mut status_bar = StatusBar::new();
+        status_bar.set_size(0, 0, 57, 1);
+        status_bar.set_left_status(&mut screen, (1, 1));
+        status_bar.filename = "/home/user/src/kilo-rs/main.rs".to_string();
+        status_bar.set_right_status(&mut screen);
+        assert_eq!(
+            "no ft | utf-8 | tabs:8 | 1/1 | col 1",
+            status_bar.right_status
+        );
+
+        let mut buf = String::new();
+        status_bar.draw(&mut buf).unwrap();
+        let line = buf
+            .split(ESCAPE_SEQUENCE_STYLE_REVERSE)
+            .nth(1)
+            .and_then(|s| s.split(ESCAPE_SEQUENCE_STYLE_RESET).next())
+            .unwrap();
+        assert_eq!(57, line.chars().count());
+        assert!(
+            line.ends_with(" no ft | utf-8 | tabs:8 | 1/1 | col 1"),
+            "{:?}",
+            line
+        );
+        // Only 20 columns are left over, so the filename makes way.
+        assert!(line.starts_with("...ain.rs - 1 lines "), "{:?}", line);
+
+        assert_eq!("...ain.rs - 1 lines ", status_bar.left_status(20));
+        assert_eq!(
+            "/home/user/src/kilo-rs/main.rs - 1 lines ",
+            status_bar.left_status(60)
+        );
+
+        status_bar.set_size(0, 0, 11, 1);
+        let mut buf = String::new();
+        status_bar.draw(&mut buf).unwrap();
+        assert!(buf.contains("1/1 | col 1"), "{:?}", buf);
+    }
+}
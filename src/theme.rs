@@ -0,0 +1,327 @@
+use crate::buffer::Highlight;
+use crate::escape_sequence::Color;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+
+/// Whether the terminal shows text on a dark or a light background, which picks the default
+/// palette.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Background {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Background {
+    /// Reads a `COLORFGBG` value, `fg;bg` or `fg;default;bg` with ANSI color indexes as rxvt
+    /// and friends set it. Backgrounds 7 and 9 to 15 are light, the rest dark; anything else
+    /// can't be told.
+    pub fn from_colorfgbg(value: &str) -> Option<Background> {
+        let bg: u8 = value.rsplit(';').next()?.trim().parse().ok()?;
+        match bg {
+            7 | 9..=15 => Some(Background::Light),
+            0..=6 | 8 => Some(Background::Dark),
+            _ => None,
+        }
+    }
+
+    /// The background `COLORFGBG` reports, dark when it's unset or unclear.
+    pub fn detect() -> Background {
+        std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|value| Background::from_colorfgbg(&value))
+            .unwrap_or_default()
+    }
+
+    fn parse(name: &str) -> Option<Background> {
+        match name {
+            "dark" => Some(Background::Dark),
+            "light" => Some(Background::Light),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Theme {
+    colors: HashMap<Highlight, Color>,
+    background: Background,
+    color_enabled: bool,
+    end_of_buffer_char: char,
+    gutter_separator: String,
+}
+
+impl Theme {
+    pub fn color(&self, highlight: Highlight) -> Color {
+        self.colors
+            .get(&highlight)
+            .copied()
+            .unwrap_or(Color::Default)
+    }
+
+    pub fn set_color(&mut self, highlight: Highlight, color: Color) {
+        self.colors.insert(highlight, color);
+    }
+
+    /// The default theme for a terminal with the given background.
+    pub fn for_background(background: Background) -> Theme {
+        Theme {
+            colors: palette(background),
+            background,
+            color_enabled: true,
+            end_of_buffer_char: '~',
+            gutter_separator: " ".to_string(),
+        }
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    /// Whether rendering emits color escapes at all, e.g. false for `NO_COLOR`.
+    pub fn color_enabled(&self) -> bool {
+        self.color_enabled
+    }
+
+    pub fn set_color_enabled(&mut self, color_enabled: bool) {
+        self.color_enabled = color_enabled;
+    }
+
+    /// The char drawn on rows past the end of the buffer, `~` unless configured.
+    pub fn end_of_buffer_char(&self) -> char {
+        self.end_of_buffer_char
+    }
+
+    pub fn set_end_of_buffer_char(&mut self, c: char) {
+        self.end_of_buffer_char = c;
+    }
+
+    /// What the line number gutter puts between the numbers and the text, a space unless
+    /// configured.
+    pub fn gutter_separator(&self) -> &str {
+        &self.gutter_separator
+    }
+
+    pub fn set_gutter_separator(&mut self, separator: String) {
+        self.gutter_separator = separator;
+    }
+
+    /// Parses `name = color` lines, where color is a name (`red`), a 256-color index (`208`)
+    /// or a 24-bit hex value (`#ff8800`), `end_of_buffer_char = c` for a single char and
+    /// `gutter_separator = " │ "`, quoted to keep its spaces. Empty lines and lines starting
+    /// with `#` are ignored.
+    pub fn parse(text: &str) -> Result<Theme, Error> {
+        Theme::parse_for(text, Background::default())
+    }
+
+    /// Like `parse`, with colors not set in `text` taken from the palette for `background`.
+    /// A `background = dark|light` line overrides the given background.
+    pub fn parse_for(text: &str, background: Background) -> Result<Theme, Error> {
+        let mut theme = Theme::for_background(background);
+        let mut colors = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || Error::other(format!("invalid theme line {}: {}", i + 1, line));
+            let (name, value) = line.split_once('=').ok_or_else(invalid)?;
+            if name.trim() == "end_of_buffer_char" {
+                let mut chars = value.trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => theme.set_end_of_buffer_char(c),
+                    _ => return Err(invalid()),
+                }
+                continue;
+            }
+            if name.trim() == "gutter_separator" {
+                let value = value.trim();
+                let separator = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                theme.set_gutter_separator(separator.to_string());
+                continue;
+            }
+            if name.trim() == "background" {
+                theme.background = Background::parse(value.trim()).ok_or_else(invalid)?;
+                continue;
+            }
+            let highlight = parse_highlight(name.trim()).ok_or_else(invalid)?;
+            let color = parse_color(value.trim()).ok_or_else(invalid)?;
+            colors.push((highlight, color));
+        }
+        theme.colors = palette(theme.background);
+        theme.colors.extend(colors);
+        Ok(theme)
+    }
+
+    pub fn load_file(path: &str, background: Background) -> Result<Theme, Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => Theme::parse_for(&text, background),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Theme::for_background(background)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::for_background(Background::default())
+    }
+}
+
+/// The default colors. Normal text keeps the terminal's own foreground either way; on a light
+/// background yellow keywords, cyan comments and a dark ruler would be hard to see.
+fn palette(background: Background) -> HashMap<Highlight, Color> {
+    let (comment, keyword1, ruler) = match background {
+        Background::Dark => (Color::Cyan, Color::Yellow, Color::Ansi256(236)),
+        Background::Light => (
+            Color::Ansi256(242),
+            Color::Ansi256(130),
+            Color::Ansi256(254),
+        ),
+    };
+    HashMap::from([
+        (Highlight::Normal, Color::Default),
+        (Highlight::Number, Color::Red),
+        (Highlight::Match, Color::Blue),
+        (Highlight::String, Color::Magenta),
+        (Highlight::Escape, Color::Red),
+        (Highlight::Comment, comment),
+        (Highlight::MultilineComment, comment),
+        (Highlight::Keyword1, keyword1),
+        (Highlight::Keyword2, Color::Green),
+        (Highlight::Selection, Color::Default),
+        (Highlight::Ruler, ruler),
+        (Highlight::EndOfBuffer, Color::Blue),
+        (Highlight::CurrentLineNumber, Color::Default),
+    ])
+}
+
+fn parse_highlight(name: &str) -> Option<Highlight> {
+    match name {
+        "normal" => Some(Highlight::Normal),
+        "number" => Some(Highlight::Number),
+        "match" => Some(Highlight::Match),
+        "string" => Some(Highlight::String),
+        "escape" => Some(Highlight::Escape),
+        "comment" => Some(Highlight::Comment),
+        "multiline_comment" => Some(Highlight::MultilineComment),
+        "keyword1" => Some(Highlight::Keyword1),
+        "keyword2" => Some(Highlight::Keyword2),
+        "selection" => Some(Highlight::Selection),
+        "ruler" => Some(Highlight::Ruler),
+        "end_of_buffer" => Some(Highlight::EndOfBuffer),
+        "current_line_number" => Some(Highlight::CurrentLineNumber),
+        _ => None,
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let component = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb(component(0)?, component(2)?, component(4)?));
+    }
+    if let Ok(n) = value.parse::<u8>() {
+        return Some(Color::Ansi256(n));
+    }
+    match value {
+        "black" => Some(Color::Black),
+        "red" => Some(Color::Red),
+        "green" => Some(Color::Green),
+        "yellow" => Some(Color::Yellow),
+        "blue" => Some(Color::Blue),
+        "magenta" => Some(Color::Magenta),
+        "cyan" => Some(Color::Cyan),
+        "white" => Some(Color::White),
+        "default" => Some(Color::Default),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Background, Theme};
+    use crate::buffer::Highlight;
+    use crate::escape_sequence::Color;
+
+    #[test]
+    fn test_parse() {
+        let theme =
+            Theme::parse("# comment\nkeyword1 = #ff8800\n\nnumber = 208\nstring=green\n").unwrap();
+
+        assert_eq!(Color::Rgb(255, 136, 0), theme.color(Highlight::Keyword1));
+        assert_eq!(
+            "\x1b[38;2;255;136;0m",
+            theme
+                .color(Highlight::Keyword1)
+                .foreground_escape_sequence()
+        );
+        assert_eq!(Color::Ansi256(208), theme.color(Highlight::Number));
+        assert_eq!(Color::Green, theme.color(Highlight::String));
+        assert_eq!(Color::Green, theme.color(Highlight::Keyword2));
+        assert_eq!('~', theme.end_of_buffer_char());
+
+        let theme = Theme::parse("end_of_buffer = 240\nend_of_buffer_char = ·\n").unwrap();
+        assert_eq!(Color::Ansi256(240), theme.color(Highlight::EndOfBuffer));
+        assert_eq!('·', theme.end_of_buffer_char());
+        assert_eq!(" ", theme.gutter_separator());
+
+        let theme =
+            Theme::parse("gutter_separator = \" │ \"\ncurrent_line_number = yellow\n").unwrap();
+        assert_eq!(" │ ", theme.gutter_separator());
+        assert_eq!(Color::Yellow, theme.color(Highlight::CurrentLineNumber));
+        let theme = Theme::parse("gutter_separator = |\n").unwrap();
+        assert_eq!("|", theme.gutter_separator());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Theme::parse("keyword1 = #ff88").is_err());
+        assert!(Theme::parse("keyword1 = 256").is_err());
+        assert!(Theme::parse("unknown = red").is_err());
+        assert!(Theme::parse("keyword1 red").is_err());
+        assert!(Theme::parse("end_of_buffer_char = ab").is_err());
+        assert!(Theme::parse("end_of_buffer_char =").is_err());
+        assert!(Theme::parse("background = grey").is_err());
+    }
+
+    #[test]
+    fn test_from_colorfgbg() {
+        assert_eq!(Some(Background::Light), Background::from_colorfgbg("0;15"));
+        assert_eq!(Some(Background::Light), Background::from_colorfgbg("0;7"));
+        assert_eq!(
+            Some(Background::Light),
+            Background::from_colorfgbg("0;default;15")
+        );
+        assert_eq!(Some(Background::Dark), Background::from_colorfgbg("15;0"));
+        assert_eq!(Some(Background::Dark), Background::from_colorfgbg("7;8"));
+        assert_eq!(None, Background::from_colorfgbg("15;default"));
+        assert_eq!(None, Background::from_colorfgbg(""));
+    }
+
+    #[test]
+    fn test_background_palette() {
+        let dark = Theme::for_background(Background::Dark);
+        let light = Theme::for_background(Background::Light);
+        assert_eq!(Color::Default, light.color(Highlight::Normal));
+        assert_eq!(Color::Cyan, dark.color(Highlight::Comment));
+        assert_eq!(Color::Ansi256(242), light.color(Highlight::Comment));
+        assert_eq!(dark, Theme::default());
+
+        let theme = Theme::parse_for("comment = red\n", Background::Light).unwrap();
+        assert_eq!(Background::Light, theme.background());
+        assert_eq!(Color::Red, theme.color(Highlight::Comment));
+        assert_eq!(Color::Ansi256(130), theme.color(Highlight::Keyword1));
+
+        let theme = Theme::parse("comment = red\nbackground = light\n").unwrap();
+        assert_eq!(Background::Light, theme.background());
+        assert_eq!(Color::Red, theme.color(Highlight::Comment));
+        assert_eq!(Color::Ansi256(254), theme.color(Highlight::Ruler));
+    }
+}
@@ -1,20 +1,44 @@
-use std::io::Error;
+use std::io::{stdout, BufWriter, Error, Write};
 
 pub struct Terminal {
     width: usize,
     height: usize,
+    out: Box<dyn Write>,
+    frame: String,
 }
 
 impl Terminal {
     pub fn new() -> Result<Terminal, Error> {
-        let mut terminal = Terminal {
-            width: 0,
-            height: 0,
-        };
+        let mut terminal = Terminal::with_writer(Box::new(BufWriter::new(stdout().lock())));
         terminal.update()?;
         Ok(terminal)
     }
 
+    pub fn with_writer(out: Box<dyn Write>) -> Terminal {
+        Terminal {
+            width: 0,
+            height: 0,
+            out,
+            frame: String::new(),
+        }
+    }
+
+    pub fn write(&mut self, s: &str) -> Result<(), Error> {
+        self.out.write_all(s.as_bytes())?;
+        self.out.flush()
+    }
+
+    /// Takes the frame buffer, cleared but keeping its allocation from the previous frame.
+    pub fn take_frame(&mut self) -> String {
+        let mut frame = std::mem::take(&mut self.frame);
+        frame.clear();
+        frame
+    }
+
+    pub fn put_frame(&mut self, frame: String) {
+        self.frame = frame;
+    }
+
     pub fn get_width(&self) -> usize {
         self.width
     }
@@ -37,6 +61,12 @@ impl Terminal {
     }
 }
 
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal::with_writer(Box::new(std::io::sink()))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Component {
     x: usize,
@@ -76,6 +106,11 @@ impl Component {
     pub fn height(&self) -> usize {
         self.height
     }
+
+    /// Whether the terminal cell at `(x, y)` lies inside the component.
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
 }
 
 impl Default for Component {
@@ -87,3 +122,39 @@ impl Default for Component {
 pub trait Drawable {
     fn draw(&self, buf: &mut String) -> Result<(), Error>;
 }
+
+/// A writer whose clones all append to the same bytes, so that a test can read back what a
+/// `Terminal` wrote.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedWriter(pub(crate) std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Component;
+
+    #[test]
+    fn test_contains() {
+        let component = Component::new(10, 5, 20, 3);
+        assert!(component.contains(15, 6));
+        assert!(component.contains(10, 5));
+        assert!(component.contains(29, 7));
+        assert!(!component.contains(30, 7));
+        assert!(!component.contains(29, 8));
+        assert!(!component.contains(9, 5));
+        assert!(!component.contains(10, 4));
+        assert!(!Component::default().contains(0, 0));
+    }
+}